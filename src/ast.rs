@@ -1,7 +1,12 @@
-use crate::value::{Token, Value};
+use crate::{
+    attr::{Attributes, Type},
+    value::{Param, Token, Value, VariantFields},
+};
 
 use thiserror::Error;
 
+use std::{collections::HashMap, path::PathBuf};
+
 use std::rc::Rc;
 
 #[derive(Debug, Clone)]
@@ -22,6 +27,96 @@ pub enum Expr {
         value: Box<Expr>,
     },
     Name(Token),
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    /// `object[index] = value`, `operator` is the operator of a compound assignment like `+=`
+    SetIndex {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        operator: Option<Token>,
+        value: Box<Expr>,
+    },
+    Some(Box<Expr>),
+    Ok(Box<Expr>),
+    Err(Box<Expr>),
+    Try {
+        value: Box<Expr>,
+        question: Token,
+    },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+        /// Names of the keyword arguments at the end of `arguments`
+        keywords: Vec<Token>,
+    },
+    List {
+        bracket: Token,
+        elements: Vec<Expr>,
+    },
+    /// `%{key => value, ...}`
+    Map {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+    },
+    /// `[element for name in iterable if condition ...]`, or `%{key => element for ...}` building
+    /// a map when there is a `key`
+    Comprehension {
+        bracket: Token,
+        key: Option<Box<Expr>>,
+        element: Box<Expr>,
+        clauses: Vec<ComprehensionClause>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
+    /// `object?.name`, the object itself when it is nil or None and a property of the value
+    /// inside it when it is Some. `call` has the paren and arguments of `object?.name(...)`,
+    /// which is skipped along with the property
+    SafeGet {
+        object: Box<Expr>,
+        name: Token,
+        call: Option<(Token, Vec<Expr>, Vec<Token>)>,
+    },
+    /// `object.name = value`, `operator` is the operator of a compound assignment like `+=`
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        operator: Option<Token>,
+        value: Box<Expr>,
+    },
+    Path {
+        object: Box<Expr>,
+        name: Token,
+    },
+    /// `{ statements; value }`, nil when the last expression ends in a ';'
+    Block {
+        statements: Vec<Stmt>,
+        value: Option<Box<Expr>>,
+    },
+    If {
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Option<Box<Expr>>,
+    },
+    /// `super.method` inside a method of the struct `owner`
+    Super {
+        keyword: Token,
+        owner: Token,
+        method: Token,
+    },
+}
+
+/// Clause of a comprehension, each one nests inside the clauses before it.
+#[derive(Debug, Clone)]
+pub enum ComprehensionClause {
+    For { name: Token, iterable: Expr },
+    If(Expr),
 }
 
 impl Expr {
@@ -35,13 +130,116 @@ impl Expr {
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
-    Block(Vec<Stmt>),
     Expression(Expr),
-    Print(Expr),
+    Print {
+        keyword: Token,
+        value: Expr,
+    },
     Let {
         name: Token,
         initializer: Option<Expr>,
     },
+    For {
+        name: Token,
+        iterable: Expr,
+        body: Vec<Stmt>,
+    },
+    Try {
+        keyword: Token,
+        body: Vec<Stmt>,
+        catch: Option<(Token, Vec<Stmt>)>,
+        finally: Option<Vec<Stmt>>,
+    },
+    Throw {
+        keyword: Token,
+        value: Expr,
+    },
+    Enum {
+        name: Token,
+        variants: Vec<(Token, VariantFields)>,
+        attributes: Attributes,
+    },
+    Function {
+        name: Token,
+        clauses: Vec<FnClause>,
+        attributes: Attributes,
+    },
+    Struct {
+        name: Token,
+        parent: Option<Token>,
+        fields: Vec<Token>,
+        /// Type of each field annotated like `x: number()`, parallel to `fields`
+        field_types: Vec<Option<Type>>,
+        methods: Vec<Method>,
+        attributes: Attributes,
+    },
+    Trait {
+        name: Token,
+        /// Methods declared without a body
+        required: Vec<RequiredMethod>,
+        defaults: Vec<Method>,
+        attributes: Attributes,
+    },
+    Impl {
+        trait_name: Token,
+        ty: Token,
+        methods: Vec<Method>,
+    },
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+    /// `mod name;` with the statements of the module's file, `path` prefixes its globals
+    Mod {
+        name: Token,
+        path: String,
+        file: PathBuf,
+        /// Top-level items of the module and whether each is `pub`
+        items: HashMap<String, bool>,
+        body: Vec<Stmt>,
+    },
+    /// `use path::{name, name as alias};` or `use path::*;` with each name it brings into the
+    /// running module and the global it refers to
+    Use {
+        keyword: Token,
+        imports: Vec<(String, String)>,
+    },
+    /// `yield value;` inside a `fn*` body
+    Yield {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+}
+
+/// Method declared in a struct, trait or impl body.
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub name: Token,
+    pub clauses: Vec<FnClause>,
+    pub attributes: Attributes,
+}
+
+/// Method a trait declares without a body, which implementations have to provide.
+#[derive(Debug, Clone)]
+pub struct RequiredMethod {
+    pub name: Token,
+    /// Declaration without the `fn` and the ';'
+    pub signature: String,
+    pub attributes: Attributes,
+}
+
+/// One clause of a function, consecutive `fn` declarations with the same name are grouped.
+#[derive(Debug, Clone)]
+pub struct FnClause {
+    pub params: Vec<Param>,
+    /// Default value of each parameter, parallel to `params`
+    pub defaults: Vec<Option<Expr>>,
+    pub signature: String,
+    pub guard: Option<Expr>,
+    /// Always an `Expr::Block`, its value is the return value
+    pub body: Expr,
+    /// Declared with `fn*`, the same for every clause of a function
+    pub generator: bool,
 }
 impl Stmt {
     pub fn walk<T, R>(&self, visitor: &mut T) -> anyhow::Result<R>
@@ -100,6 +298,89 @@ impl AstWalker<Expr, String> for AstStringify {
             Expr::Unary { operator, right } => self.lispify(&operator.lexeme, &[&right.as_ref()]),
             Expr::Name(name) => Ok(name.lexeme.clone()),
             Expr::Assignment { name, value } => self.lispify(&name.lexeme, &[&value.as_ref()]),
+            Expr::Index { object, index, .. } => {
+                self.lispify("index", &[object.as_ref(), index.as_ref()])
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                operator,
+                value,
+                ..
+            } => {
+                let op = operator.as_ref().map_or("", |op| op.lexeme.as_str());
+                let name = format!("index {}=", op);
+                self.lispify(&name, &[object.as_ref(), index.as_ref(), value.as_ref()])
+            }
+            Expr::Some(value) => self.lispify("Some", &[value.as_ref()]),
+            Expr::Ok(value) => self.lispify("Ok", &[value.as_ref()]),
+            Expr::Err(value) => self.lispify("Err", &[value.as_ref()]),
+            Expr::Try { value, .. } => self.lispify("?", &[value.as_ref()]),
+            Expr::Get { object, name } => self.lispify(&format!(".{}", name.lexeme), &[object]),
+            Expr::Path { object, name } => self.lispify(&format!("::{}", name.lexeme), &[object]),
+            Expr::SafeGet { object, name, call } => {
+                let get = format!("?.{}", name.lexeme);
+                match call {
+                    Some((_, arguments, _)) => {
+                        let mut exprs = vec![object.as_ref()];
+                        exprs.extend(arguments);
+                        self.lispify(&format!("call {}", get), &exprs)
+                    }
+                    None => self.lispify(&get, &[object]),
+                }
+            }
+            Expr::Set {
+                object,
+                name,
+                operator,
+                value,
+            } => {
+                let op = operator.as_ref().map_or("", |op| op.lexeme.as_str());
+                self.lispify(&format!(".{} {}=", name.lexeme, op), &[object, value])
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let mut exprs = vec![callee.as_ref()];
+                exprs.extend(arguments);
+                self.lispify("call", &exprs)
+            }
+            Expr::List { elements, .. } => {
+                let exprs: Vec<&Expr> = elements.iter().collect();
+                self.lispify("list", &exprs)
+            }
+            Expr::Map { entries, .. } => {
+                let exprs: Vec<&Expr> = entries.iter().flat_map(|(k, v)| [k, v]).collect();
+                self.lispify("map", &exprs)
+            }
+            Expr::Comprehension {
+                key,
+                element,
+                clauses,
+                ..
+            } => {
+                let mut exprs: Vec<&Expr> = key.iter().map(AsRef::as_ref).collect();
+                exprs.push(element);
+                exprs.extend(clauses.iter().map(|clause| match clause {
+                    ComprehensionClause::For { iterable, .. } => iterable,
+                    ComprehensionClause::If(condition) => condition,
+                }));
+                self.lispify("comprehension", &exprs)
+            }
+            Expr::Super { method, .. } => Ok(format!("super.{}", method.lexeme)),
+            Expr::Block { value, .. } => match value {
+                Some(value) => self.lispify("block", &[value.as_ref()]),
+                None => Ok("(block)".into()),
+            },
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let mut exprs = vec![condition.as_ref(), then_branch.as_ref()];
+                exprs.extend(else_branch.as_deref());
+                self.lispify("if", &exprs)
+            }
         }
     }
 }
@@ -0,0 +1,214 @@
+use std::fmt;
+
+use anyhow::bail;
+
+use crate::value::{Token, TokenType};
+
+/// `@doc` and `@spec` attributes preceding a `fn`, `struct`, `enum` or `trait` declaration or a
+/// method. They are kept on the declared value, where the `doc` and `spec` natives read them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Attributes {
+    /// @doc "text"
+    pub doc: Option<String>,
+    /// @spec name(types) -> type, only allowed on functions and methods
+    pub spec: Option<Spec>,
+}
+
+/// @spec add(number(), number()) -> number()
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spec {
+    pub name: String,
+    pub line: u32,
+    pub params: Vec<Type>,
+    pub returns: Type,
+}
+
+/// Type in a spec. Built-in types are written like calls, `number()` or `list(string())`, structs
+/// and enums by their name, and `a | b` is either.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Call {
+        name: String,
+        args: Vec<Type>,
+    },
+    /// Struct or enum name, or `nil`
+    Name(String),
+    Union(Vec<Type>),
+}
+
+impl Attributes {
+    /// Parses the attributes starting at the '@' at `start`, returning them along with the index
+    /// of the token after the last one.
+    pub fn parse(tokens: &[Token], start: usize) -> anyhow::Result<(Attributes, usize)> {
+        let mut parser = AttrParser { tokens, i: start };
+        let mut attributes = Attributes::default();
+        while parser.peek().ty == TokenType::At {
+            parser.i += 1;
+            let name = parser.expect(TokenType::Ident, "Expected attribute name after '@'")?;
+            match name.lexeme.as_str() {
+                "doc" if attributes.doc.is_some() => {
+                    bail!("Duplicate @doc attribute on line {}", name.line)
+                }
+                "doc" => {
+                    let text = parser.expect(TokenType::String, "Expected string after @doc")?;
+                    attributes.doc = Some(text.literal.to_string());
+                }
+                "spec" if attributes.spec.is_some() => {
+                    bail!("Duplicate @spec attribute on line {}", name.line)
+                }
+                "spec" => attributes.spec = Some(parser.spec()?),
+                other => bail!(
+                    "Unknown attribute '@{}' on line {}, expected @doc or @spec",
+                    other,
+                    name.line
+                ),
+            }
+        }
+        Ok((attributes, parser.i))
+    }
+
+    /// Checks the attributes can be put on the `kind` declaration named `name`.
+    pub fn check(&self, kind: &str, name: &str) -> anyhow::Result<()> {
+        let Some(spec) = &self.spec else {
+            return Ok(());
+        };
+        if kind != "fn" {
+            bail!(
+                "@spec on line {} can only be put on functions, not on {} {}",
+                spec.line,
+                kind,
+                name
+            );
+        }
+        if spec.name != name {
+            bail!(
+                "@spec on line {} is for '{}' but precedes fn {}",
+                spec.line,
+                spec.name,
+                name
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Type {
+    /// Parses the type starting at `start`, like the annotation of a struct field, returning it
+    /// along with the index of the token after it.
+    pub fn parse(tokens: &[Token], start: usize) -> anyhow::Result<(Type, usize)> {
+        let mut parser = AttrParser { tokens, i: start };
+        let ty = parser.union()?;
+        Ok((ty, parser.i))
+    }
+}
+
+struct AttrParser<'a> {
+    tokens: &'a [Token],
+    i: usize,
+}
+
+impl AttrParser<'_> {
+    fn peek(&self) -> &Token {
+        // the token stream always ends with Eof
+        &self.tokens[self.i.min(self.tokens.len() - 1)]
+    }
+
+    fn expect(&mut self, ty: TokenType, message: &str) -> anyhow::Result<Token> {
+        let token = self.peek().clone();
+        if token.ty != ty {
+            bail!("{} on line {}, got '{}'", message, token.line, token.lexeme);
+        }
+        self.i += 1;
+        Ok(token)
+    }
+
+    /// name(types) -> type
+    fn spec(&mut self) -> anyhow::Result<Spec> {
+        let name = self.expect(TokenType::Ident, "Expected function name after @spec")?;
+        self.expect(
+            TokenType::LeftParen,
+            "Expected '(' after @spec function name",
+        )?;
+        let params = self.types(TokenType::RightParen)?;
+        self.expect(TokenType::Arrow, "Expected '->' before @spec return type")?;
+        Ok(Spec {
+            name: name.lexeme,
+            line: name.line,
+            params,
+            returns: self.union()?,
+        })
+    }
+
+    /// Comma separated types up to and including `close`.
+    fn types(&mut self, close: TokenType) -> anyhow::Result<Vec<Type>> {
+        let mut types = Vec::new();
+        while self.peek().ty != close {
+            types.push(self.union()?);
+            if self.peek().ty != TokenType::Comma {
+                break;
+            }
+            self.i += 1;
+        }
+        self.expect(close, "Expected ')' after types")?;
+        Ok(types)
+    }
+
+    fn union(&mut self) -> anyhow::Result<Type> {
+        let mut types = vec![self.ty()?];
+        while self.peek().ty == TokenType::Pipe {
+            self.i += 1;
+            types.push(self.ty()?);
+        }
+        Ok(if types.len() == 1 {
+            types.remove(0)
+        } else {
+            Type::Union(types)
+        })
+    }
+
+    fn ty(&mut self) -> anyhow::Result<Type> {
+        if self.peek().ty == TokenType::Nil {
+            self.i += 1;
+            return Ok(Type::Name("nil".into()));
+        }
+        let name = self.expect(TokenType::Ident, "Expected type")?.lexeme;
+        if self.peek().ty != TokenType::LeftParen {
+            return Ok(Type::Name(name));
+        }
+        self.i += 1;
+        let args = self.types(TokenType::RightParen)?;
+        Ok(Type::Call { name, args })
+    }
+}
+
+impl fmt::Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        write_joined(f, &self.params, ", ")?;
+        write!(f, ") -> {}", self.returns)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Call { name, args } => {
+                write!(f, "{}(", name)?;
+                write_joined(f, args, ", ")?;
+                write!(f, ")")
+            }
+            Type::Name(name) => write!(f, "{}", name),
+            Type::Union(types) => write_joined(f, types, " | "),
+        }
+    }
+}
+
+fn write_joined(f: &mut fmt::Formatter<'_>, types: &[Type], separator: &str) -> fmt::Result {
+    for (i, ty) in types.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        write!(f, "{}", ty)?;
+    }
+    Ok(())
+}
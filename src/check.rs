@@ -0,0 +1,1622 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    ast::{ComprehensionClause, Expr, FnClause, Method, Stmt},
+    attr::{Attributes, Type},
+    module::Module,
+    value::{arity_error, Object, Param, Token, TokenType, Value},
+};
+
+/// Gradual type checker run by `corrosion check`, over a script parsed along with its modules.
+///
+/// Types come from @spec attributes and struct field annotations like `x: number()`, and are
+/// inferred through expressions from literals, operators and calls to annotated functions.
+/// Anything it can't tell the type of is `any()`, which is compatible with every type, so code
+/// without annotations is never reported. Nothing it finds changes how a script runs.
+///
+/// It also warns about values that may be nil, like `let x;` or an `if` without an `else`,
+/// reaching arithmetic, field access or calls. Comparing a local to nil, testing it in a
+/// condition or returning early when it is nil narrows it for the code it guards.
+pub struct Checker {
+    /// Every top-level item of the script and its modules, by global
+    items: HashSet<String>,
+    /// Specs of functions by global and of methods by `Struct.method`
+    specs: HashMap<String, Signature>,
+    structs: HashMap<String, StructInfo>,
+    enums: HashSet<String>,
+    /// Names `use` brought into each module, to their globals
+    imports: HashMap<String, HashMap<String, String>>,
+    /// Path of the module being checked
+    module: String,
+    scopes: Vec<HashMap<String, Ty>>,
+    /// Specs of the functions declared in each of `scopes`
+    local_specs: Vec<HashMap<String, Signature>>,
+    /// Return type of the annotated function whose body is being checked
+    returns: Option<(String, Ty)>,
+    /// Names assigned in a function body without being declared there, which may change
+    /// whenever the function is called
+    reassigned: HashSet<String>,
+    report: Report,
+}
+
+/// What `corrosion check` found in a script.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Definite type mismatches
+    pub errors: Vec<String>,
+    /// Values that may be nil used where nil fails at runtime
+    pub warnings: Vec<String>,
+}
+
+/// Type of a value as far as the checker can tell.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ty {
+    Any,
+    Nil,
+    Number,
+    String,
+    Boolean,
+    Range,
+    Function,
+    List(Box<Ty>),
+    /// Keys and values of a map
+    Map(Box<Ty>, Box<Ty>),
+    Option(Box<Ty>),
+    Result(Box<Ty>, Box<Ty>),
+    /// Instance of the struct with this global
+    Struct(String),
+    /// Instance of the enum with this global
+    Enum(String),
+    Union(Vec<Ty>),
+}
+
+#[derive(Debug, Clone)]
+struct Signature {
+    params: Vec<Ty>,
+    returns: Ty,
+    /// Parameters of the function when it has a single clause, calls bind their arguments to
+    /// them like they will at runtime
+    declared: Option<Vec<Param>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct StructInfo {
+    parent: Option<String>,
+    fields: Vec<(String, Option<Type>)>,
+    /// `fields` with their types converted, parent fields first like the constructor takes them
+    types: Vec<(String, Ty)>,
+}
+
+impl Ty {
+    /// Union of `types`, flattened and without duplicates. It is `any()` when one of them is,
+    /// or `any() | nil` if another may be nil, so the union still may be.
+    fn union(types: Vec<Ty>) -> Ty {
+        let mut members: Vec<Ty> = Vec::new();
+        for ty in types {
+            let inner = match ty {
+                Ty::Union(inner) => inner,
+                ty => vec![ty],
+            };
+            for ty in inner {
+                if !members.contains(&ty) {
+                    members.push(ty);
+                }
+            }
+        }
+        if members.contains(&Ty::Any) {
+            members.retain(|ty| matches!(ty, Ty::Any | Ty::Nil));
+        }
+        match members.len() {
+            0 => Ty::Any,
+            1 => members.remove(0),
+            _ => Ty::Union(members),
+        }
+    }
+
+    /// Whether a value of this type may be nil.
+    fn may_be_nil(&self) -> bool {
+        match self {
+            Ty::Nil => true,
+            Ty::Union(types) => types.contains(&Ty::Nil),
+            _ => false,
+        }
+    }
+
+    /// Type of a value of this type known not to be nil, `any()` if it can only be nil.
+    fn without_nil(self) -> Ty {
+        match self {
+            Ty::Nil => Ty::Any,
+            Ty::Union(types) => Ty::union(types.into_iter().filter(|t| *t != Ty::Nil).collect()),
+            ty => ty,
+        }
+    }
+
+    fn of_literal(value: &Value) -> Ty {
+        match value {
+            Value::Number(_) => Ty::Number,
+            Value::Boolean(_) => Ty::Boolean,
+            Value::Nil => Ty::Nil,
+            Value::Obj(Object::String(_)) => Ty::String,
+            Value::Obj(Object::Option(_)) => Ty::Option(Box::new(Ty::Any)),
+            _ => Ty::Any,
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Any => write!(f, "any()"),
+            Ty::Nil => write!(f, "nil"),
+            Ty::Number => write!(f, "number()"),
+            Ty::String => write!(f, "string()"),
+            Ty::Boolean => write!(f, "boolean()"),
+            Ty::Range => write!(f, "range()"),
+            Ty::Function => write!(f, "function()"),
+            Ty::List(ty) => write!(f, "list({})", ty),
+            Ty::Map(key, value) => write!(f, "map({}, {})", key, value),
+            Ty::Option(ty) => write!(f, "option({})", ty),
+            Ty::Result(ok, err) => write!(f, "result({}, {})", ok, err),
+            Ty::Struct(name) | Ty::Enum(name) => write!(f, "{}", name),
+            Ty::Union(types) => {
+                for (i, ty) in types.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", ty)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Checker {
+    /// Times a loop body is checked at most before its locals' types settle, a type like
+    /// `list(list(...))` can keep growing
+    const LOOP_PASSES: usize = 8;
+
+    /// Checks the statements of a parsed script, returning the type errors and nil warnings
+    /// found.
+    pub fn check(stmts: &[Stmt]) -> Report {
+        let mut checker = Checker {
+            items: HashSet::new(),
+            specs: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashSet::new(),
+            imports: HashMap::new(),
+            module: String::new(),
+            scopes: vec![HashMap::new()],
+            local_specs: vec![HashMap::new()],
+            returns: None,
+            reassigned: HashSet::new(),
+            report: Report::default(),
+        };
+        checker.declare(stmts);
+        checker.annotate(stmts);
+        for stmt in stmts {
+            checker.stmt(stmt);
+        }
+        checker.report
+    }
+
+    fn location(&self, line: u32) -> String {
+        if self.module.is_empty() {
+            format!("line {}", line)
+        } else {
+            format!("line {} in module '{}'", line, self.module)
+        }
+    }
+
+    fn error(&mut self, line: u32, message: String) {
+        let error = format!("Type Error :: {} => {}", self.location(line), message);
+        self.report.errors.push(error);
+    }
+
+    /// Warns when `expr`, of type `ty`, may be nil where it is `used`.
+    fn nil_check(&mut self, expr: &Expr, ty: &Ty, line: u32, used: impl FnOnce() -> String) {
+        if !ty.may_be_nil() {
+            return;
+        }
+        let certainty = if *ty == Ty::Nil { "is" } else { "may be" };
+        let warning = format!(
+            "Nil Warning :: {} => {} {} nil {}",
+            self.location(line),
+            describe(expr),
+            certainty,
+            used()
+        );
+        self.report.warnings.push(warning);
+    }
+
+    fn global(&self, name: &str) -> String {
+        Module {
+            path: self.module.clone(),
+            ..Module::default()
+        }
+        .global(name)
+    }
+
+    /// Global `name` refers to in the current module, a path like `module::item` starts from
+    /// an import or a module declared in it. Names that aren't items are natives, if anything.
+    fn resolve(&self, name: &str) -> String {
+        let (first, rest) = match name.split_once("::") {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (name, None),
+        };
+        let imported = self
+            .imports
+            .get(&self.module)
+            .and_then(|imports| imports.get(first));
+        let base = match imported {
+            Some(global) => global.clone(),
+            None if self.items.contains(&self.global(first)) => self.global(first),
+            None => first.to_string(),
+        };
+        match rest {
+            Some(rest) => format!("{}::{}", base, rest),
+            None => base,
+        }
+    }
+
+    /// Runs `f` with `module` as the module being checked.
+    fn in_module(&mut self, module: &str, f: impl FnOnce(&mut Self)) {
+        let enclosing = std::mem::replace(&mut self.module, module.to_string());
+        f(self);
+        self.module = enclosing;
+    }
+
+    /// Collects the items, struct fields and imports of every module.
+    fn declare(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Function { name, .. } | Stmt::Trait { name, .. } => {
+                    self.items.insert(self.global(&name.lexeme));
+                }
+                Stmt::Let { name, .. } => {
+                    self.items.insert(self.global(&name.lexeme));
+                }
+                Stmt::Enum { name, .. } => {
+                    let global = self.global(&name.lexeme);
+                    self.items.insert(global.clone());
+                    self.enums.insert(global);
+                }
+                Stmt::Struct {
+                    name,
+                    parent,
+                    fields,
+                    field_types,
+                    ..
+                } => {
+                    let global = self.global(&name.lexeme);
+                    self.items.insert(global.clone());
+                    let info = StructInfo {
+                        // resolved once every module's items are known
+                        parent: parent.as_ref().map(|p| p.lexeme.clone()),
+                        fields: fields
+                            .iter()
+                            .zip(field_types)
+                            .map(|(field, ty)| (field.lexeme.clone(), ty.clone()))
+                            .collect(),
+                        types: Vec::new(),
+                    };
+                    self.structs.insert(global, info);
+                }
+                Stmt::Use { imports, .. } => {
+                    self.imports
+                        .entry(self.module.clone())
+                        .or_default()
+                        .extend(imports.iter().cloned());
+                }
+                Stmt::Mod {
+                    name, path, body, ..
+                } => {
+                    self.items.insert(self.global(&name.lexeme));
+                    self.in_module(path, |checker| checker.declare(body));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Converts the @spec of every function and method and the field types of every struct.
+    fn annotate(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Function {
+                    name,
+                    clauses,
+                    attributes,
+                } => self.annotate_function(self.global(&name.lexeme), clauses, attributes),
+                Stmt::Struct { name, methods, .. } => {
+                    let global = self.global(&name.lexeme);
+                    let info = self.structs[&global].clone();
+                    let parent = info.parent.as_deref().map(|p| self.resolve(p));
+                    let mut types = Vec::new();
+                    for (field, ty) in &info.fields {
+                        let ty = match ty {
+                            Some(ty) => self.ty(ty, name.line),
+                            None => Ty::Any,
+                        };
+                        types.push((field.clone(), ty));
+                    }
+                    let info = self.structs.get_mut(&global).expect("Declared above");
+                    info.parent = parent;
+                    info.types = types;
+                    self.annotate_methods(&global, methods);
+                }
+                Stmt::Trait { name, defaults, .. } => {
+                    self.annotate_methods(&self.global(&name.lexeme), defaults)
+                }
+                Stmt::Impl { ty, methods, .. } => {
+                    self.annotate_methods(&self.resolve(&ty.lexeme), methods)
+                }
+                Stmt::Mod { path, body, .. } => {
+                    self.in_module(path, |checker| checker.annotate(body))
+                }
+                _ => {}
+            }
+        }
+        if self.module.is_empty() {
+            self.inherit_fields();
+        }
+    }
+
+    fn annotate_methods(&mut self, owner: &str, methods: &[Method]) {
+        for method in methods {
+            let global = format!("{}.{}", owner, method.name.lexeme);
+            self.annotate_function(global, &method.clauses, &method.attributes);
+        }
+    }
+
+    fn annotate_function(&mut self, global: String, clauses: &[FnClause], attributes: &Attributes) {
+        if let Some(signature) = self.signature(clauses, attributes) {
+            self.specs.insert(global, signature);
+        }
+    }
+
+    fn signature(&mut self, clauses: &[FnClause], attributes: &Attributes) -> Option<Signature> {
+        let spec = attributes.spec.as_ref()?;
+        Some(Signature {
+            params: spec.params.iter().map(|p| self.ty(p, spec.line)).collect(),
+            returns: self.ty(&spec.returns, spec.line),
+            declared: match clauses {
+                [clause] => Some(clause.params.clone()),
+                _ => None,
+            },
+        })
+    }
+
+    /// Prepends the fields of each struct's ancestors to its own, the order its constructor
+    /// takes them in.
+    fn inherit_fields(&mut self) {
+        let structs: Vec<String> = self.structs.keys().cloned().collect();
+        for global in structs {
+            let mut types = Vec::new();
+            let mut seen = HashSet::new();
+            let mut current = Some(global.clone());
+            while let Some(name) = current.filter(|name| seen.insert(name.clone())) {
+                let Some(info) = self.structs.get(&name) else {
+                    break;
+                };
+                let own = info
+                    .types
+                    .iter()
+                    .filter(|(f, _)| types.iter().all(|(t, _)| t != f));
+                types.splice(0..0, own.cloned().collect::<Vec<_>>());
+                current = info.parent.clone();
+            }
+            self.structs.get_mut(&global).expect("Listed above").types = types;
+        }
+    }
+
+    /// Type a spec or field annotation stands for, built-in types are written like calls.
+    fn ty(&mut self, ty: &Type, line: u32) -> Ty {
+        match ty {
+            Type::Union(types) => Ty::union(types.iter().map(|t| self.ty(t, line)).collect()),
+            Type::Name(name) if name == "nil" => Ty::Nil,
+            Type::Name(name) => {
+                let global = self.resolve(name);
+                if self.structs.contains_key(&global) {
+                    Ty::Struct(global)
+                } else if self.enums.contains(&global) {
+                    Ty::Enum(global)
+                } else {
+                    self.error(line, format!("Unknown type '{}'", name));
+                    Ty::Any
+                }
+            }
+            Type::Call { name, args } => {
+                let params = match name.as_str() {
+                    "any" | "number" | "string" | "boolean" | "range" | "function" => 0,
+                    "list" | "option" => 1,
+                    "result" | "map" => 2,
+                    _ => {
+                        self.error(line, format!("Unknown type '{}'", ty));
+                        return Ty::Any;
+                    }
+                };
+                if args.len() > params {
+                    self.error(line, format!("Too many arguments in type '{}'", ty));
+                }
+                // left out arguments are any(), `list()` is a list of anything
+                let mut args = args.iter().map(|t| self.ty(t, line)).map(Box::new);
+                let mut arg = || args.next().unwrap_or(Box::new(Ty::Any));
+                match name.as_str() {
+                    "number" => Ty::Number,
+                    "string" => Ty::String,
+                    "boolean" => Ty::Boolean,
+                    "range" => Ty::Range,
+                    "function" => Ty::Function,
+                    "list" => Ty::List(arg()),
+                    "map" => Ty::Map(arg(), arg()),
+                    "option" => Ty::Option(arg()),
+                    "result" => Ty::Result(arg(), arg()),
+                    _ => Ty::Any,
+                }
+            }
+        }
+    }
+
+    /// Whether a value of type `actual` can be given where `expected` is. Only definite
+    /// mismatches are rejected, `any()` and unions that may hold the right type are accepted.
+    fn compatible(&self, actual: &Ty, expected: &Ty) -> bool {
+        match (actual, expected) {
+            (Ty::Any, _) | (_, Ty::Any) => true,
+            (Ty::Union(types), _) => types.iter().any(|t| self.compatible(t, expected)),
+            (_, Ty::Union(types)) => types.iter().any(|t| self.compatible(actual, t)),
+            (Ty::List(a), Ty::List(e)) | (Ty::Option(a), Ty::Option(e)) => self.compatible(a, e),
+            (Ty::Result(a, ae), Ty::Result(e, ee)) | (Ty::Map(a, ae), Ty::Map(e, ee)) => {
+                self.compatible(a, e) && self.compatible(ae, ee)
+            }
+            (Ty::Struct(a), Ty::Struct(e)) => self.extends(a, e),
+            _ => actual == expected,
+        }
+    }
+
+    /// Whether the struct `child` is `ancestor` or inherits from it.
+    fn extends(&self, child: &str, ancestor: &str) -> bool {
+        let mut seen = HashSet::new();
+        let mut current = Some(child.to_string());
+        while let Some(name) = current.filter(|name| seen.insert(name.clone())) {
+            if name == ancestor {
+                return true;
+            }
+            current = self.structs.get(&name).and_then(|info| info.parent.clone());
+        }
+        false
+    }
+
+    fn expect(&mut self, actual: &Ty, expected: &Ty, line: u32, what: impl FnOnce() -> String) {
+        if !self.compatible(actual, expected) {
+            let message = format!("Expected {} for {} but got {}", expected, what(), actual);
+            self.error(line, message);
+        }
+    }
+
+    fn define(&mut self, name: &str, ty: Ty) {
+        self.scopes
+            .last_mut()
+            .expect("There is always a scope")
+            .insert(name.to_string(), ty);
+    }
+
+    fn local(&self, name: &str) -> Option<&Ty> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Spec of the function the local `name` is bound to, if it is one declared with a spec.
+    fn local_spec(&self, name: &str) -> Option<Signature> {
+        let scope = self
+            .scopes
+            .iter()
+            .rposition(|scope| scope.contains_key(name))?;
+        self.local_specs[scope].get(name).cloned()
+    }
+
+    /// Replaces the type of the local `name` with what `f` makes of it, if there is one.
+    fn refine(&mut self, name: &str, f: impl FnOnce(Ty) -> Ty) -> bool {
+        let Some(scope) = self.scopes.iter_mut().rev().find(|s| s.contains_key(name)) else {
+            return false;
+        };
+        let ty = scope.remove(name).expect("Found above");
+        scope.insert(name.to_string(), f(ty));
+        true
+    }
+
+    /// Runs `f` over code that may not run at all, so afterwards each local may have the type
+    /// it had before or the one `f` left it with.
+    fn optionally<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let before = self.scopes.clone();
+        let result = f(self);
+        let after = std::mem::replace(&mut self.scopes, before);
+        self.join(after);
+        result
+    }
+
+    /// Runs `f` over a loop body, which may run any number of times. It is first run without
+    /// reporting until the types locals have at the start of the body stop changing, so the
+    /// body is checked against what its earlier iterations leave behind.
+    fn repeatedly(&mut self, f: impl Fn(&mut Self)) {
+        for _ in 0..Self::LOOP_PASSES {
+            let before = self.scopes.clone();
+            let report = std::mem::take(&mut self.report);
+            f(self);
+            self.report = report;
+            let after = std::mem::replace(&mut self.scopes, before.clone());
+            self.join(after);
+            if self.scopes == before {
+                break;
+            }
+        }
+        self.optionally(f);
+    }
+
+    /// Merges the types locals have in `other`, the same scopes after another branch.
+    fn join(&mut self, other: Vec<HashMap<String, Ty>>) {
+        for (scope, other) in self.scopes.iter_mut().zip(other) {
+            for (name, ty) in scope.iter_mut() {
+                if let Some(other) = other.get(name) {
+                    *ty = Ty::union(vec![ty.clone(), other.clone()]);
+                }
+            }
+        }
+    }
+
+    /// Narrows the locals `condition` tells apart from nil, for code that only runs when it
+    /// is `truthy`, or falsy otherwise.
+    fn narrow(&mut self, condition: &Expr, truthy: bool) {
+        match condition {
+            Expr::Grouping(inner) => self.narrow(inner, truthy),
+            Expr::Unary { operator, right } if operator.ty == TokenType::Bang => {
+                self.narrow(right, !truthy)
+            }
+            Expr::Name(name) if truthy => {
+                self.refine(&name.lexeme, Ty::without_nil);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => match (operator.ty, truthy) {
+                (TokenType::And, true) | (TokenType::Or, false) => {
+                    self.narrow(left, truthy);
+                    self.narrow(right, truthy);
+                }
+                (TokenType::EqualEqual | TokenType::BangEqual, _) => {
+                    let Some(name) = nil_comparison(left, right) else {
+                        return;
+                    };
+                    if (operator.ty == TokenType::BangEqual) == truthy {
+                        self.refine(name, Ty::without_nil);
+                    } else {
+                        self.refine(name, |ty| {
+                            if ty.may_be_nil() || ty == Ty::Any {
+                                Ty::Nil
+                            } else {
+                                ty
+                            }
+                        });
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        self.local_specs.push(HashMap::new());
+        let result = f(self);
+        self.scopes.pop();
+        self.local_specs.pop();
+        result
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.scoped(|checker| stmts.iter().for_each(|stmt| checker.stmt(stmt)));
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) | Stmt::Print { value: expr, .. } => {
+                self.expr(expr);
+            }
+            Stmt::Throw { value, .. } => {
+                self.expr(value);
+            }
+            Stmt::Let { name, initializer } => {
+                let ty = initializer.as_ref().map_or(Ty::Nil, |e| self.expr(e));
+                if self.reassigned.contains(&name.lexeme) {
+                    self.define(&name.lexeme, Ty::Any);
+                } else {
+                    self.define(&name.lexeme, ty);
+                }
+            }
+            Stmt::For {
+                name,
+                iterable,
+                body,
+            } => {
+                let element = self.element(iterable);
+                self.repeatedly(|checker| {
+                    checker.scoped(|checker| {
+                        checker.define(&name.lexeme, element.clone());
+                        body.iter().for_each(|stmt| checker.stmt(stmt));
+                    })
+                });
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                // the body and the catch may stop anywhere
+                self.optionally(|checker| checker.block(body));
+                if let Some((name, body)) = catch {
+                    self.optionally(|checker| {
+                        checker.scoped(|checker| {
+                            checker.define(&name.lexeme, Ty::Any);
+                            body.iter().for_each(|stmt| checker.stmt(stmt));
+                        })
+                    });
+                }
+                if let Some(body) = finally {
+                    self.block(body);
+                }
+            }
+            Stmt::Function {
+                name,
+                clauses,
+                attributes,
+            } => {
+                let spec = if self.scopes.len() == 1 {
+                    self.specs.get(&self.global(&name.lexeme)).cloned()
+                } else {
+                    // functions declared in a body are locals
+                    self.define(&name.lexeme, Ty::Function);
+                    let spec = self.signature(clauses, attributes);
+                    if let Some(spec) = &spec {
+                        let scope = self
+                            .local_specs
+                            .last_mut()
+                            .expect("There is always a scope");
+                        scope.insert(name.lexeme.clone(), spec.clone());
+                    }
+                    spec
+                };
+                self.function(&name.lexeme, name.line, clauses, spec, None);
+            }
+            Stmt::Struct { name, methods, .. } => {
+                let global = self.global(&name.lexeme);
+                self.methods(&global, methods, Some(Ty::Struct(global.clone())));
+            }
+            Stmt::Trait { name, defaults, .. } => {
+                self.methods(&self.global(&name.lexeme), defaults, None);
+            }
+            Stmt::Impl { ty, methods, .. } => {
+                let global = self.resolve(&ty.lexeme);
+                let receiver = self
+                    .structs
+                    .contains_key(&global)
+                    .then(|| Ty::Struct(global.clone()));
+                self.methods(&global, methods, receiver);
+            }
+            Stmt::Return { keyword, value } => {
+                let ty = value.as_ref().map_or(Ty::Nil, |e| self.expr(e));
+                if let Some((function, returns)) = self.returns.clone() {
+                    self.expect(&ty, &returns, keyword.line, || {
+                        format!("the return value of {}", function)
+                    });
+                }
+            }
+            Stmt::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Mod { path, body, .. } => {
+                let scopes = std::mem::replace(&mut self.scopes, vec![HashMap::new()]);
+                let specs = std::mem::replace(&mut self.local_specs, vec![HashMap::new()]);
+                self.in_module(path, |checker| {
+                    body.iter().for_each(|stmt| checker.stmt(stmt));
+                });
+                self.scopes = scopes;
+                self.local_specs = specs;
+            }
+            Stmt::Enum { .. } | Stmt::Use { .. } => {}
+        }
+    }
+
+    fn methods(&mut self, owner: &str, methods: &[Method], receiver: Option<Ty>) {
+        for method in methods {
+            let global = format!("{}.{}", owner, method.name.lexeme);
+            let spec = self.specs.get(&global).cloned();
+            let line = method.name.line;
+            self.function(&global, line, &method.clauses, spec, receiver.clone());
+        }
+    }
+
+    /// Checks each clause of the function `name`, declared on `line`, with its parameters typed
+    /// by its spec. Without one, the first parameter of a method is an instance of its struct.
+    fn function(
+        &mut self,
+        name: &str,
+        line: u32,
+        clauses: &[FnClause],
+        spec: Option<Signature>,
+        receiver: Option<Ty>,
+    ) {
+        for clause in clauses {
+            let required = clause
+                .params
+                .iter()
+                .filter(|p| matches!(p, Param::Name(_) | Param::Literal(_) | Param::Some(_)))
+                .count();
+            let rest = clause.params.iter().any(|p| matches!(p, Param::Rest(_)));
+            if let Some(spec) = &spec {
+                let given = spec.params.len();
+                if given < required || (!rest && given > clause.params.len()) {
+                    self.error(
+                        line,
+                        format!(
+                            "@spec of {} has {} parameter type(s) but '{}' takes {}",
+                            name,
+                            given,
+                            clause.signature,
+                            clause.params.len()
+                        ),
+                    );
+                }
+            }
+            let enclosing = std::mem::replace(
+                &mut self.returns,
+                spec.as_ref().map(|s| (name.to_string(), s.returns.clone())),
+            );
+            // variables from outside the body may have been reassigned by the time it runs
+            let scopes = std::mem::replace(&mut self.scopes, vec![HashMap::new()]);
+            let specs = std::mem::replace(&mut self.local_specs, vec![HashMap::new()]);
+            self.scoped(|checker| {
+                for (i, param) in clause.params.iter().enumerate() {
+                    let ty = match (&spec, &receiver) {
+                        (Some(spec), _) => spec.params.get(i).cloned().unwrap_or(Ty::Any),
+                        (None, Some(receiver)) if i == 0 => receiver.clone(),
+                        _ => Ty::Any,
+                    };
+                    match param {
+                        Param::Name(name) | Param::Default(name) => checker.define(name, ty),
+                        Param::Rest(name) => checker.define(name, Ty::List(Box::new(ty))),
+                        Param::Some(binding) => match ty {
+                            Ty::Option(inner) => checker.define(binding, *inner),
+                            Ty::Any => checker.define(binding, Ty::Any),
+                            ty => {
+                                checker.expect(&Ty::Option(Box::new(Ty::Any)), &ty, line, || {
+                                    format!("parameter {} of {}", i + 1, name)
+                                });
+                                checker.define(binding, Ty::Any);
+                            }
+                        },
+                        Param::Literal(value) => {
+                            checker.expect(&Ty::of_literal(value), &ty, line, || {
+                                format!("parameter {} of {}", i + 1, name)
+                            });
+                        }
+                    }
+                }
+                for default in clause.defaults.iter().flatten() {
+                    checker.expr(default);
+                }
+                if let Some(guard) = &clause.guard {
+                    checker.expr(guard);
+                }
+                let Expr::Block { statements, value } = &clause.body else {
+                    checker.expr(&clause.body);
+                    return;
+                };
+                statements.iter().for_each(|stmt| checker.stmt(stmt));
+                // a body ending in a ';' returns with `return`, which is checked on its own
+                if let Some(value) = value {
+                    let ty = checker.expr(value);
+                    if let Some((function, returns)) = checker.returns.clone() {
+                        checker.expect(&ty, &returns, line, || {
+                            format!("the return value of {}", function)
+                        });
+                    }
+                }
+            });
+            self.returns = enclosing;
+            self.scopes = scopes;
+            self.local_specs = specs;
+            for name in self.reassigned.clone() {
+                self.refine(&name, |_| Ty::Any);
+            }
+        }
+    }
+
+    /// Type of the elements a `for` loop or comprehension over `iterable` binds.
+    fn element(&mut self, iterable: &Expr) -> Ty {
+        match self.expr(iterable) {
+            Ty::List(ty) | Ty::Map(ty, _) => *ty,
+            Ty::Range => Ty::Number,
+            Ty::String => Ty::String,
+            _ => Ty::Any,
+        }
+    }
+
+    /// Infers the type of `expr`, reporting mismatches inside it.
+    fn expr(&mut self, expr: &Expr) -> Ty {
+        match expr {
+            Expr::Literal(value) => Ty::of_literal(value),
+            Expr::Grouping(expr) => self.expr(expr),
+            Expr::Unary { operator, right } => {
+                let ty = self.expr(right);
+                match operator.ty {
+                    TokenType::Bang => Ty::Boolean,
+                    _ => {
+                        // operands that are always nil are type errors
+                        if ty != Ty::Nil {
+                            self.nil_check(right, &ty, operator.line, || {
+                                format!("in unary '{}'", operator.lexeme)
+                            });
+                        }
+                        self.expect(&ty, &Ty::Number, operator.line, || {
+                            format!("the operand of unary '{}'", operator.lexeme)
+                        });
+                        Ty::Number
+                    }
+                }
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left_ty = self.expr(left);
+                let right_ty = match operator.ty {
+                    // the right operand only runs when the left one is truthy for &&, falsy for ||
+                    TokenType::And | TokenType::Or => self.optionally(|checker| {
+                        checker.narrow(left, operator.ty == TokenType::And);
+                        checker.expr(right)
+                    }),
+                    _ => self.expr(right),
+                };
+                if !matches!(
+                    operator.ty,
+                    TokenType::EqualEqual
+                        | TokenType::BangEqual
+                        | TokenType::In
+                        | TokenType::And
+                        | TokenType::Or
+                        | TokenType::QuestionQuestion
+                ) {
+                    for (expr, ty) in [(left, &left_ty), (right, &right_ty)] {
+                        if *ty != Ty::Nil {
+                            self.nil_check(expr, ty, operator.line, || {
+                                format!("in '{}'", operator.lexeme)
+                            });
+                        }
+                    }
+                }
+                self.binary(
+                    left_ty,
+                    operator.ty,
+                    &operator.lexeme,
+                    right_ty,
+                    operator.line,
+                )
+            }
+            Expr::Assignment { name, value } => {
+                let ty = self.expr(value);
+                if !self.refine(&name.lexeme, |_| ty.clone()) {
+                    self.reassigned.insert(name.lexeme.clone());
+                }
+                ty
+            }
+            Expr::Name(name) => match self.local(&name.lexeme) {
+                Some(ty) => ty.clone(),
+                None => {
+                    let global = self.resolve(&name.lexeme);
+                    if self.specs.contains_key(&global) {
+                        Ty::Function
+                    } else {
+                        Ty::Any
+                    }
+                }
+            },
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object_ty = self.expr(object);
+                self.nil_check(object, &object_ty, bracket.line, || "when indexed".into());
+                let index = self.expr(index);
+                match object_ty {
+                    // a range slices the list
+                    Ty::List(_) if index == Ty::Range => object_ty,
+                    Ty::List(element) => {
+                        self.expect(&index, &Ty::Number, bracket.line, || "a list index".into());
+                        *element
+                    }
+                    Ty::Map(key, value) => {
+                        self.expect(&index, &key, bracket.line, || "a map key".into());
+                        *value
+                    }
+                    Ty::String => Ty::String,
+                    _ => Ty::Any,
+                }
+            }
+            Expr::Some(value) => Ty::Option(Box::new(self.expr(value))),
+            Expr::Ok(value) => Ty::Result(Box::new(self.expr(value)), Box::new(Ty::Any)),
+            Expr::Err(value) => Ty::Result(Box::new(Ty::Any), Box::new(self.expr(value))),
+            Expr::Try { value, .. } => match self.expr(value) {
+                Ty::Option(ty) | Ty::Result(ty, _) => *ty,
+                _ => Ty::Any,
+            },
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+                keywords,
+            } => {
+                let args: Vec<(&Expr, Ty)> = arguments.iter().map(|a| (a, self.expr(a))).collect();
+                self.call(callee, &args, keywords, paren.line)
+            }
+            Expr::List { elements, .. } => {
+                let types: Vec<Ty> = elements.iter().map(|e| self.expr(e)).collect();
+                Ty::List(Box::new(common(types)))
+            }
+            Expr::Map { entries, .. } => {
+                let (keys, values): (Vec<Ty>, Vec<Ty>) = entries
+                    .iter()
+                    .map(|(key, value)| (self.expr(key), self.expr(value)))
+                    .unzip();
+                Ty::Map(Box::new(common(keys)), Box::new(common(values)))
+            }
+            Expr::Comprehension {
+                key,
+                element,
+                clauses,
+                ..
+            } => self.scoped(|checker| {
+                for clause in clauses {
+                    match clause {
+                        ComprehensionClause::For { name, iterable } => {
+                            let ty = checker.element(iterable);
+                            checker.define(&name.lexeme, ty);
+                        }
+                        ComprehensionClause::If(condition) => {
+                            checker.expr(condition);
+                            checker.narrow(condition, true);
+                        }
+                    }
+                }
+                match key {
+                    Some(key) => {
+                        Ty::Map(Box::new(checker.expr(key)), Box::new(checker.expr(element)))
+                    }
+                    None => Ty::List(Box::new(checker.expr(element))),
+                }
+            }),
+            Expr::Get { object, name } => {
+                let object_ty = self.expr(object);
+                self.nil_check(object, &object_ty, name.line, || {
+                    format!("when reading '.{}'", name.lexeme)
+                });
+                self.field(&object_ty, &name.lexeme).unwrap_or(Ty::Any)
+            }
+            Expr::Path { .. } => match path(expr) {
+                Some(path) => {
+                    let global = self.resolve(&path);
+                    let (owner, _) = Module::split(&global);
+                    if self.specs.contains_key(&global) {
+                        Ty::Function
+                    } else if self.enums.contains(owner) {
+                        Ty::Enum(owner.to_string())
+                    } else {
+                        Ty::Any
+                    }
+                }
+                None => Ty::Any,
+            },
+            Expr::SafeGet { object, name, call } => {
+                let object_ty = self.expr(object);
+                let inner = match object_ty.clone().without_nil() {
+                    Ty::Option(inner) => *inner,
+                    ty => ty,
+                };
+                let ty = match call {
+                    Some((_, arguments, _)) => {
+                        arguments.iter().for_each(|arg| {
+                            self.expr(arg);
+                        });
+                        Ty::Any
+                    }
+                    None => self.field(&inner, &name.lexeme).unwrap_or(Ty::Any),
+                };
+                // nil short-circuits to nil, None to None
+                match object_ty {
+                    Ty::Option(_) => Ty::union(vec![ty, Ty::Option(Box::new(Ty::Any))]),
+                    object_ty if object_ty.may_be_nil() => Ty::union(vec![ty, Ty::Nil]),
+                    _ => ty,
+                }
+            }
+            Expr::Set {
+                object,
+                name,
+                operator,
+                value,
+            } => {
+                let object_ty = self.expr(object);
+                self.nil_check(object, &object_ty, name.line, || {
+                    format!("when setting '.{}'", name.lexeme)
+                });
+                let object = object_ty;
+                let value = self.expr(value);
+                let field = self.field(&object, &name.lexeme);
+                match (field, operator) {
+                    (Some(field), None) => {
+                        self.expect(&value, &field, name.line, || {
+                            format!("field '{}' of {}", name.lexeme, object)
+                        });
+                        value
+                    }
+                    (Some(field), Some(operator)) => {
+                        let ty = self.binary(
+                            field.clone(),
+                            operator.ty,
+                            &operator.lexeme,
+                            value,
+                            name.line,
+                        );
+                        self.expect(&ty, &field, name.line, || {
+                            format!("field '{}' of {}", name.lexeme, object)
+                        });
+                        ty
+                    }
+                    (None, _) => value,
+                }
+            }
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                operator,
+                value,
+            } => {
+                let object_ty = self.expr(object);
+                self.nil_check(object, &object_ty, bracket.line, || "when indexed".into());
+                let index = self.expr(index);
+                let value = self.expr(value);
+                let Ty::List(element) = object_ty else {
+                    return value;
+                };
+                self.expect(&index, &Ty::Number, bracket.line, || "a list index".into());
+                match operator {
+                    Some(operator) => {
+                        let (op, lexeme) = (operator.ty, &operator.lexeme);
+                        self.binary(*element, op, lexeme, value, bracket.line)
+                    }
+                    None => value,
+                }
+            }
+            Expr::Block { statements, value } => self.scoped(|checker| {
+                statements.iter().for_each(|stmt| checker.stmt(stmt));
+                value.as_ref().map_or(Ty::Nil, |value| checker.expr(value))
+            }),
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                let before = self.scopes.clone();
+                self.narrow(condition, true);
+                let then = self.expr(then_branch);
+                let after_then = std::mem::replace(&mut self.scopes, before);
+                self.narrow(condition, false);
+                let otherwise = else_branch.as_ref().map_or(Ty::Nil, |e| self.expr(e));
+                // a branch that always returns or throws doesn't reach the code after the if
+                let else_diverges = else_branch.as_deref().is_some_and(diverges);
+                match (diverges(then_branch), else_diverges) {
+                    (true, _) => otherwise,
+                    (false, true) => {
+                        self.scopes = after_then;
+                        then
+                    }
+                    (false, false) => {
+                        self.join(after_then);
+                        Ty::union(vec![then, otherwise])
+                    }
+                }
+            }
+            Expr::Super { .. } => Ty::Any,
+        }
+    }
+
+    /// Type of a binary operation, reporting operands it can't be applied to. Struct operands
+    /// may overload operators, so they are left alone.
+    fn binary(&mut self, left: Ty, operator: TokenType, lexeme: &str, right: Ty, line: u32) -> Ty {
+        let overloaded = |ty: &Ty| matches!(ty, Ty::Struct(_) | Ty::Any | Ty::Union(_));
+        match operator {
+            TokenType::Plus if overloaded(&left) => Ty::Any,
+            TokenType::Plus => {
+                if matches!(left, Ty::Number | Ty::String) {
+                    self.expect(&right, &left, line, || {
+                        format!("the right operand of '+' on {}", left)
+                    });
+                    left
+                } else {
+                    self.error(line, format!("Can't apply '+' to {}", left));
+                    Ty::Any
+                }
+            }
+            TokenType::EqualEqual | TokenType::BangEqual | TokenType::In => Ty::Boolean,
+            TokenType::Lt | TokenType::Le | TokenType::Gt | TokenType::Ge => Ty::Boolean,
+            TokenType::And | TokenType::Or => Ty::union(vec![left, right]),
+            TokenType::QuestionQuestion => match left {
+                Ty::Option(inner) => Ty::union(vec![*inner, right]),
+                left if left.may_be_nil() => Ty::union(vec![left.without_nil(), right]),
+                _ => Ty::Any,
+            },
+            TokenType::DotDot | TokenType::DotDotEqual => {
+                for ty in [&left, &right] {
+                    self.expect(ty, &Ty::Number, line, || format!("a bound of '{}'", lexeme));
+                }
+                Ty::Range
+            }
+            _ => {
+                for ty in [&left, &right] {
+                    self.expect(ty, &Ty::Number, line, || {
+                        format!("an operand of '{}'", lexeme)
+                    });
+                }
+                Ty::Number
+            }
+        }
+    }
+
+    /// Type of calling `callee` with positional arguments of types `args`, checking them against
+    /// the spec of the function, the spec of the method or the field types of the struct called.
+    fn call(&mut self, callee: &Expr, args: &[(&Expr, Ty)], keywords: &[Token], line: u32) -> Ty {
+        if let Expr::Get { object, name } = callee {
+            let receiver = self.expr(object);
+            self.nil_check(object, &receiver, name.line, || {
+                format!("when calling '.{}()'", name.lexeme)
+            });
+            let Ty::Struct(owner) = &receiver else {
+                return Ty::Any;
+            };
+            let Some((method, spec)) = self.method(owner, &name.lexeme) else {
+                return Ty::Any;
+            };
+            let mut all = vec![(&**object, receiver.clone())];
+            all.extend_from_slice(args);
+            self.arguments(&method, &spec, &all, keywords, line);
+            return spec.returns;
+        }
+        let global = match callee {
+            Expr::Name(name) if self.local(&name.lexeme).is_some() => {
+                let ty = self.local(&name.lexeme).cloned().unwrap_or(Ty::Any);
+                self.nil_check(callee, &ty, line, || "when called".into());
+                let Some(spec) = self.local_spec(&name.lexeme) else {
+                    return Ty::Any;
+                };
+                self.arguments(&name.lexeme, &spec, args, keywords, line);
+                return spec.returns;
+            }
+            Expr::Name(name) => self.resolve(&name.lexeme),
+            Expr::Path { .. } => match path(callee) {
+                Some(path) => self.resolve(&path),
+                None => return Ty::Any,
+            },
+            _ => {
+                self.expr(callee);
+                return Ty::Any;
+            }
+        };
+        if let Some(spec) = self.specs.get(&global).cloned() {
+            self.arguments(&global, &spec, args, keywords, line);
+            return spec.returns;
+        }
+        if let Some(info) = self.structs.get(&global) {
+            let constructor = Signature {
+                params: info.types.iter().map(|(_, ty)| ty.clone()).collect(),
+                returns: Ty::Struct(global.clone()),
+                declared: Some(
+                    info.types
+                        .iter()
+                        .map(|(field, _)| Param::Name(field.clone()))
+                        .collect(),
+                ),
+            };
+            self.arguments(&global, &constructor, args, keywords, line);
+            return constructor.returns;
+        }
+        match global.as_str() {
+            "len" => Ty::Number,
+            "type_of" => Ty::String,
+            "parse_number" => Ty::Result(Box::new(Ty::Number), Box::new(Ty::String)),
+            _ => Ty::Any,
+        }
+    }
+
+    /// Checks the arguments of a call to `function` against its spec, `args` ends with one for
+    /// each of `keywords`. Without the function's parameters only the positional arguments are
+    /// checked, in order.
+    fn arguments(
+        &mut self,
+        function: &str,
+        spec: &Signature,
+        args: &[(&Expr, Ty)],
+        keywords: &[Token],
+        line: u32,
+    ) {
+        let positional = args.len() - keywords.len();
+        let slots = match &spec.declared {
+            Some(params) => match bind(params, positional, keywords) {
+                Ok(slots) => slots,
+                Err(message) => {
+                    self.error(line, format!("{} {}", function, message));
+                    return;
+                }
+            },
+            None => (0..positional).collect(),
+        };
+        for (i, ((expr, arg), slot)) in args.iter().zip(slots).enumerate() {
+            let Some(param) = spec.params.get(slot) else {
+                continue;
+            };
+            let argument = match i.checked_sub(positional) {
+                Some(k) => format!("argument '{}'", keywords[k].lexeme),
+                None => format!("argument {}", i + 1),
+            };
+            self.expect(arg, param, line, || format!("{} of {}", argument, function));
+            // a nil argument to a parameter typed without nil is a type error
+            if *arg != Ty::Nil && *param != Ty::Any && !param.may_be_nil() {
+                self.nil_check(expr, arg, line, || {
+                    format!("as {} of {}, which expects {}", argument, function, param)
+                });
+            }
+        }
+    }
+
+    /// Spec of `method` on the struct `owner` or the nearest ancestor declaring it.
+    fn method(&self, owner: &str, method: &str) -> Option<(String, Signature)> {
+        let mut seen = HashSet::new();
+        let mut current = Some(owner.to_string());
+        while let Some(name) = current.filter(|name| seen.insert(name.clone())) {
+            let global = format!("{}.{}", name, method);
+            if let Some(spec) = self.specs.get(&global) {
+                return Some((global, spec.clone()));
+            }
+            current = self.structs.get(&name).and_then(|info| info.parent.clone());
+        }
+        None
+    }
+
+    /// Declared type of `field` on instances of `object`'s struct.
+    fn field(&self, object: &Ty, field: &str) -> Option<Ty> {
+        let Ty::Struct(owner) = object else {
+            return None;
+        };
+        let info = self.structs.get(owner)?;
+        info.types
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, ty)| ty.clone())
+    }
+}
+
+/// Type of every element of a list or map literal, any() unless they all have the same one.
+fn common(types: Vec<Ty>) -> Ty {
+    match types.first() {
+        Some(first) if types.iter().all(|t| t == first) => first.clone(),
+        _ => Ty::Any,
+    }
+}
+
+/// How warnings refer to `expr`.
+fn describe(expr: &Expr) -> String {
+    match expr {
+        Expr::Grouping(inner) => describe(inner),
+        Expr::Call { callee, .. } => format!("the result of {}", describe(callee)),
+        _ => match dotted(expr) {
+            Some(name) => format!("'{}'", name),
+            None => "the value".into(),
+        },
+    }
+}
+
+/// `a.b.c` of a field access on a name, or of a path.
+fn dotted(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Get { object, name } => Some(format!("{}.{}", dotted(object)?, name.lexeme)),
+        _ => path(expr),
+    }
+}
+
+/// Local compared to nil by `left` and `right` of an `==` or `!=`.
+fn nil_comparison<'a>(left: &'a Expr, right: &'a Expr) -> Option<&'a str> {
+    match (left, right) {
+        (Expr::Name(name), Expr::Literal(Value::Nil))
+        | (Expr::Literal(Value::Nil), Expr::Name(name)) => Some(&name.lexeme),
+        _ => None,
+    }
+}
+
+/// Whether `expr` always returns or throws before it finishes.
+fn diverges(expr: &Expr) -> bool {
+    match expr {
+        Expr::Block {
+            statements,
+            value: None,
+        } => match statements.last() {
+            Some(Stmt::Return { .. } | Stmt::Throw { .. }) => true,
+            Some(Stmt::Expression(expr)) => diverges(expr),
+            _ => false,
+        },
+        Expr::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => diverges(then_branch) && diverges(else_branch),
+        _ => false,
+    }
+}
+
+/// Parameter each argument of a call binds to, the positional ones and then one for each of
+/// `keywords`, or the error the call fails with. Arguments past the fixed parameters all bind to
+/// the rest parameter, whose spec type is that of each of them.
+fn bind(params: &[Param], positional: usize, keywords: &[Token]) -> Result<Vec<usize>, String> {
+    let rest = params.iter().position(|p| matches!(p, Param::Rest(_)));
+    let fixed = rest.unwrap_or(params.len());
+    if positional > fixed && rest.is_none() {
+        return Err(arity_error(params, positional + keywords.len()));
+    }
+    let mut slots: Vec<usize> = (0..positional).map(|i| i.min(fixed)).collect();
+    let mut bound: Vec<bool> = (0..params.len()).map(|i| i < positional).collect();
+    for keyword in keywords {
+        let Some(i) = params
+            .iter()
+            .position(|p| p.keyword() == Some(&keyword.lexeme))
+        else {
+            return Err(format!(
+                "got an unexpected keyword argument '{}'",
+                keyword.lexeme
+            ));
+        };
+        if bound[i] {
+            return Err(format!(
+                "got multiple values for argument '{}'",
+                keyword.lexeme
+            ));
+        }
+        bound[i] = true;
+        slots.push(i);
+    }
+    let missing = params.iter().zip(&bound).find(|(param, bound)| {
+        !**bound && matches!(param, Param::Name(_) | Param::Literal(_) | Param::Some(_))
+    });
+    match missing {
+        Some((Param::Name(name), _)) if !keywords.is_empty() => {
+            Err(format!("missing argument '{}'", name))
+        }
+        Some(_) => Err(arity_error(params, positional + keywords.len())),
+        None => Ok(slots),
+    }
+}
+/// `a::b::c` of a path expression made of names.
+fn path(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Name(name) => Some(name.lexeme.clone()),
+        Expr::Path { object, name } => Some(format!("{}::{}", path(object)?, name.lexeme)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex::Lexer, parse::Parser};
+
+    fn check(source: &str) -> Report {
+        let lexed = Lexer::scan_tokens(source);
+        assert!(lexed.errors.is_empty(), "{:?}", lexed.errors);
+        Checker::check(&Parser::parse(&lexed.tokens).expect("source parses"))
+    }
+
+    #[test]
+    fn spec_mismatches_are_errors() {
+        let report = check(
+            "@spec add(number(), number()) -> number()
+            fn add(a, b) { a + b }
+            add(1, \"two\");
+            @spec name() -> string()
+            fn name() { return 1; }
+            struct P { x: number() }
+            P(\"a\");",
+        );
+        assert_eq!(
+            report.errors,
+            [
+                "Type Error :: line 3 => Expected number() for argument 2 of add but got string()",
+                "Type Error :: line 5 => Expected string() for the return value of name but got number()",
+                "Type Error :: line 7 => Expected number() for argument 1 of P but got string()",
+            ]
+        );
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn maps_are_typed_by_their_entries() {
+        let report = check(
+            "@spec total(map(string(), number())) -> number()
+            fn total(m) { m[\"a\"] }
+            total(%{\"a\" => 1});
+            total(%{1 => 1});
+            total(%{k => 1 for k in [\"a\"]});
+            total([1]);",
+        );
+        assert_eq!(
+            report.errors,
+            [
+                "Type Error :: line 4 => Expected map(string(), number()) for argument 1 of total but got map(number(), number())",
+                "Type Error :: line 6 => Expected map(string(), number()) for argument 1 of total but got list(number())",
+            ]
+        );
+    }
+
+    #[test]
+    fn keyword_arguments_are_checked_by_name() {
+        let report = check(
+            "@spec f(number(), string()) -> nil
+            fn f(a, b) { }
+            f(1, b: 2);
+            f(b: \"x\", a: 1);
+            f(1, c: \"x\");
+            struct P { x: number(), y: string() }
+            P(y: 1, x: 2);",
+        );
+        assert_eq!(
+            report.errors,
+            [
+                "Type Error :: line 3 => Expected string() for argument 'b' of f but got number()",
+                "Type Error :: line 5 => f got an unexpected keyword argument 'c'",
+                "Type Error :: line 7 => Expected string() for argument 'y' of P but got number()",
+            ]
+        );
+    }
+
+    #[test]
+    fn rest_arguments_are_checked_against_the_element_type() {
+        let report = check(
+            "@spec sum(string(), number()) -> number()
+            fn sum(label, ...xs) { xs[0] }
+            sum(\"a\", 1, 2, 3);
+            sum(\"a\");
+            sum(\"a\", 1, \"b\");",
+        );
+        assert_eq!(
+            report.errors,
+            ["Type Error :: line 5 => Expected number() for argument 3 of sum but got string()"]
+        );
+    }
+
+    #[test]
+    fn arity_mismatches_are_errors() {
+        let report = check(
+            "@spec f(number(), number(), number()) -> nil
+            fn f(a, b, c) { }
+            f(1, 2);
+            f(1, 2, 3, 4);
+            f(1, 2, b: 3);
+            @spec g(number(), number()) -> nil
+            fn g(a, b = 1) { }
+            g(1);
+            g(a: 1);
+            g(b: 1);",
+        );
+        assert_eq!(
+            report.errors,
+            [
+                "Type Error :: line 3 => f expected 3 argument(s) but got 2",
+                "Type Error :: line 4 => f expected 3 argument(s) but got 4",
+                "Type Error :: line 5 => f got multiple values for argument 'b'",
+                "Type Error :: line 10 => g missing argument 'a'",
+            ]
+        );
+    }
+
+    #[test]
+    fn unannotated_code_is_not_reported() {
+        let report = check("fn add(a, b) { a + b } print add(1, \"two\");");
+        assert!(report.errors.is_empty());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn nil_checks_narrow() {
+        let report = check(
+            "fn load(c) { let x; if c { x = 1; } x }
+            let a;
+            if load(true) { a = 1; }
+            if a != nil { print a + 1; }
+            if a { print a + 1; }
+            if !a { } else { print a + 1; }
+            fn early() {
+                let b;
+                if load(true) { b = 1; }
+                if b == nil { return; }
+                print b + 1;
+            }
+            print a + 1;",
+        );
+        assert_eq!(
+            report.warnings,
+            ["Nil Warning :: line 13 => 'a' may be nil in '+'"]
+        );
+        let report = check("let a; print a + 1;");
+        assert_eq!(
+            report.errors,
+            ["Type Error :: line 1 => Can't apply '+' to nil"]
+        );
+    }
+
+    #[test]
+    fn branches_join() {
+        let report = check(
+            "let c;
+            if true { c = 1; } else { c = 2; }
+            print c + 1;
+            let d;
+            if true { d = 1; }
+            print d + 1;
+            let e = 1;
+            try { e = nil; } catch (err) {}
+            print e + 1;",
+        );
+        assert_eq!(
+            report.warnings,
+            [
+                "Nil Warning :: line 6 => 'd' may be nil in '+'",
+                "Nil Warning :: line 9 => 'e' may be nil in '+'",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_values_keep_nil() {
+        let report = check(
+            "fn load() { 1 }
+            let c = true;
+            let b;
+            if c { b = load(); }
+            print b * 2;",
+        );
+        assert_eq!(
+            report.warnings,
+            ["Nil Warning :: line 5 => 'b' may be nil in '*'"]
+        );
+        assert_eq!(Ty::union(vec![Ty::Number, Ty::Any]), Ty::Any);
+    }
+
+    #[test]
+    fn loop_bodies_see_their_earlier_iterations() {
+        let report = check(
+            "let a = 1;
+            for i in 0..3 { print a + 1; a = nil; }
+            let b = 1;
+            for i in 0..3 { print b + 1; b = b + 1; }",
+        );
+        assert_eq!(
+            report.warnings,
+            ["Nil Warning :: line 2 => 'a' may be nil in '+'"]
+        );
+    }
+}
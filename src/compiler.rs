@@ -1,11 +1,23 @@
-use std::ops::Add;
+use std::{
+    collections::HashMap,
+    ops::Add,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::bail;
 use log::debug;
 
 use crate::{
-    lex::Lexer,
-    value::{Object, Token, TokenType, Value},
+    attr::{Attributes, Type},
+    lex::{tokens_to_source, Lexer},
+    macros::{self, Expansion},
+    module::{top_level_items, Module},
+    native::Operator,
+    value::{
+        check_params, Clause, EnumType, Function, FunctionBody, Object, Param, StructType, Token,
+        TokenType, Trait, Value, Variant, VariantFields,
+    },
     vm::{Opcode, OpcodeType, VM},
 };
 
@@ -15,83 +27,1171 @@ struct Parser {
     i: usize,
     bytecode: Chunk,
     compiler: Compiler,
+    /// Struct whose methods are being compiled
+    current_struct: Option<CurrentStruct>,
+    /// Script being compiled followed by the modules whose files are being compiled into it
+    modules: Vec<Module>,
+    /// Macro expansions in `tokens`
+    expansions: Vec<Expansion>,
+    /// Attributes parsed for the item declaration that follows them
+    attributes: Attributes,
+    /// Number of compile errors reported, the chunk is only run when there are none
+    errors: usize,
+}
+
+#[derive(Debug, Clone)]
+struct CurrentStruct {
+    name: Token,
+    has_parent: bool,
 }
 
 impl Parser {
     #[inline]
     fn advance(&mut self, n: usize) {
         self.i += n;
+        self.bytecode.set_line(self.prev().line as u64);
     }
 
     fn declaration(&mut self) -> anyhow::Result<()> {
-        let result = if let TokenType::Let = self.current().ty {
-            self.advance(1);
-            self.let_declaration()
-        } else {
-            self.statement()
+        let result = match self.current().ty {
+            TokenType::Let => {
+                self.advance(1);
+                self.let_declaration()
+            }
+            TokenType::Enum => {
+                self.advance(1);
+                self.enum_declaration()
+            }
+            TokenType::Fn => {
+                self.advance(1);
+                self.fn_declaration()
+            }
+            TokenType::Struct => {
+                self.advance(1);
+                self.struct_declaration()
+            }
+            TokenType::Trait => {
+                self.advance(1);
+                self.trait_declaration()
+            }
+            TokenType::Impl => {
+                self.advance(1);
+                self.impl_declaration()
+            }
+            TokenType::Mod => {
+                self.advance(1);
+                self.mod_declaration()
+            }
+            TokenType::Pub => {
+                self.advance(1);
+                self.pub_declaration()
+            }
+            TokenType::Use => {
+                self.advance(1);
+                self.use_declaration()
+            }
+            TokenType::At => self.attributed_declaration(),
+            _ => self.statement(),
         };
 
         match result {
             Ok(_) => Ok(()),
             Err(err) => {
-                println!("Compiler::Parser => [ERROR]: {}", err);
+                self.report_error(&err);
+                // attributes of an item that failed to compile don't carry over to the next
+                self.attributes = Attributes::default();
                 self.synchronize()
             }
         }
     }
 
-    fn let_declaration(&mut self) -> anyhow::Result<()> {
-        let global = self.parse_variable()?;
-
-        if let TokenType::Equal = self.current().ty {
-            self.advance(1);
-            self.expression()?;
+    /// @doc "text" @spec name(types) -> type, followed by the item they are put on.
+    ///
+    /// The item's declaration takes them and stores them on the function or type it builds.
+    fn attributed_declaration(&mut self) -> anyhow::Result<()> {
+        self.attributes = self.attributes()?;
+        let item = match self.current().ty {
+            TokenType::Pub => self.tokens.get(self.i + 1).map(|t| t.ty),
+            ty => Some(ty),
+        };
+        if !matches!(
+            item,
+            Some(TokenType::Fn | TokenType::Struct | TokenType::Enum | TokenType::Trait)
+        ) {
+            bail!("Compiler::Parser => Attributes can only precede a fn, struct, enum or trait");
+        }
+        self.declaration()
+    }
+
+    /// Attributes starting at the current token, if it is an '@'.
+    fn attributes(&mut self) -> anyhow::Result<Attributes> {
+        if self.current().ty != TokenType::At {
+            return Ok(Attributes::default());
+        }
+        let (attributes, end) = match Attributes::parse(&self.tokens, self.i) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                // synchronize stops at an '@', skip it so it doesn't stop at the same one again
+                self.advance(1);
+                return Err(err);
+            }
+        };
+        self.advance(end - self.i);
+        Ok(attributes)
+    }
+
+    /// Attributes parsed for the declaration being compiled. They are taken before its body, so
+    /// items declared inside it don't get them, and checked after it so an error doesn't make
+    /// synchronize resume in the middle of the body.
+    fn take_attributes(&mut self) -> Attributes {
+        std::mem::take(&mut self.attributes)
+    }
+
+    /// Prints a compile error, along with the macro call the current token was expanded from.
+    fn report_error(&mut self, err: &anyhow::Error) {
+        self.errors += 1;
+        let origin = macros::origin(&self.expansions, self.i)
+            .or_else(|| macros::origin(&self.expansions, self.i.checked_sub(1)?));
+        match origin {
+            Some(origin) => println!("Compiler::Parser => [ERROR]: {} ({})", err, origin),
+            None => println!("Compiler::Parser => [ERROR]: {}", err),
+        }
+    }
+
+    /// mod name;
+    ///
+    /// Compiles the module's file in place, so its top-level code runs where it is declared.
+    /// Its items are globals named `name::item`, see `global_name` and `module_path`.
+    fn mod_declaration(&mut self) -> anyhow::Result<()> {
+        self.expect(TokenType::Ident, "Expected module name after 'mod'")?;
+        let name = self.prev().lexeme.clone();
+        self.expect(TokenType::Semicolon, "Expected ';' after module name")?;
+        if self.compiler.scope_depth > 0 {
+            bail!("Compiler::Parser => Modules can only be declared at the top level");
+        }
+        let (module, expanded) = Module::load(&self.modules, &name)?;
+        let enclosing_tokens = std::mem::replace(&mut self.tokens, expanded.tokens);
+        let enclosing_expansions = std::mem::replace(&mut self.expansions, expanded.expansions);
+        let enclosing_i = std::mem::replace(&mut self.i, 0);
+        self.modules.push(module);
+        let mut result = Ok(());
+        while result.is_ok() && self.current().ty != TokenType::Eof {
+            result = self.declaration();
+        }
+        let module = self.modules.pop().expect("Module pushed above");
+        if result.is_ok() {
+            warn_unused_imports(&module);
+        }
+        self.tokens = enclosing_tokens;
+        self.expansions = enclosing_expansions;
+        self.i = enclosing_i;
+        self.bytecode.set_line(self.prev().line as u64);
+        self.module_mut().modules.insert(name, module);
+        result
+    }
+
+    /// pub item; whether items are `pub` is collected when their module is loaded.
+    fn pub_declaration(&mut self) -> anyhow::Result<()> {
+        if self.compiler.scope_depth > 0 {
+            bail!("Compiler::Parser => 'pub' is only allowed on top-level items");
+        }
+        if !matches!(
+            self.current().ty,
+            TokenType::Let
+                | TokenType::Fn
+                | TokenType::Struct
+                | TokenType::Enum
+                | TokenType::Trait
+                | TokenType::Mod
+        ) {
+            bail!(
+                "Compiler::Parser => Expected an item after 'pub'; got: {}",
+                self.current()
+            );
+        }
+        self.declaration()
+    }
+
+    /// use module::item; use module::{item, item as alias}; use module::*;
+    ///
+    /// Imports are resolved here, so they apply to the code after the declaration.
+    fn use_declaration(&mut self) -> anyhow::Result<()> {
+        if self.compiler.scope_depth > 0 {
+            bail!("Compiler::Parser => 'use' is only allowed at the top level");
+        }
+        self.expect(TokenType::Ident, "Expected module name after 'use'")?;
+        let mut path = vec![self.prev().clone()];
+        self.expect(TokenType::DoubleColon, "Expected '::' after module name")?;
+        let names = loop {
+            match self.current().ty {
+                TokenType::Star => {
+                    self.advance(1);
+                    break None;
+                }
+                TokenType::LeftBrace => {
+                    self.advance(1);
+                    let mut names = Vec::new();
+                    while self.current().ty != TokenType::RightBrace {
+                        names.push(self.use_name()?);
+                        if self.current().ty != TokenType::Comma {
+                            break;
+                        }
+                        self.advance(1);
+                    }
+                    self.expect(TokenType::RightBrace, "Expected '}' after imported names")?;
+                    break Some(names);
+                }
+                _ => {
+                    let name = self.use_name()?;
+                    if self.current().ty != TokenType::DoubleColon {
+                        break Some(vec![name]);
+                    }
+                    self.advance(1);
+                    path.push(name.0);
+                }
+            }
+        };
+        self.expect(TokenType::Semicolon, "Expected ';' after use declaration")?;
+        self.module_mut().use_items(&path, names.as_deref())?;
+        Ok(())
+    }
+
+    /// name or name as alias, in a use declaration
+    fn use_name(&mut self) -> anyhow::Result<(Token, Token)> {
+        self.expect(TokenType::Ident, "Expected name to import")?;
+        let name = self.prev().clone();
+        if self.current().ty != TokenType::As {
+            return Ok((name.clone(), name));
+        }
+        self.advance(1);
+        self.expect(TokenType::Ident, "Expected alias after 'as'")?;
+        Ok((name, self.prev().clone()))
+    }
+
+    /// Module whose file is being compiled.
+    fn module(&self) -> &Module {
+        self.modules
+            .last()
+            .expect("Module stack always holds the script")
+    }
+
+    fn module_mut(&mut self) -> &mut Module {
+        self.modules
+            .last_mut()
+            .expect("Module stack always holds the script")
+    }
+
+    /// Global of `name` when it is an item of, or imported into, the module being compiled,
+    /// other names are globals as they are.
+    fn global_name(&mut self, name: &Token) -> Token {
+        match self.module_mut().resolve(&name.lexeme) {
+            Some(global) => Token {
+                lexeme: global,
+                ..name.clone()
+            },
+            None => name.clone(),
+        }
+    }
+
+    /// module::item, or module::inner::item, when `name` is a module declared in, or imported
+    /// into, the module being compiled. Consumes the path up to the item and returns its global,
+    /// a '::' after the item is left to `path`, for enum variants.
+    fn module_path(&mut self, name: &Token) -> anyhow::Result<Option<Token>> {
+        let Some(mut module) = self.module_mut().module(&name.lexeme) else {
+            return Ok(None);
+        };
+        loop {
+            self.advance(1);
+            self.expect(TokenType::Ident, "Expected item name after '::'")?;
+            let item = self.prev().clone();
+            let global = module.item(&item.lexeme)?;
+            match module.modules.get(&item.lexeme) {
+                Some(inner) if self.current().ty == TokenType::DoubleColon => {
+                    let inner = inner.clone();
+                    module = inner;
+                }
+                _ => {
+                    return Ok(Some(Token {
+                        lexeme: global,
+                        ..item
+                    }))
+                }
+            }
+        }
+    }
+
+    fn let_declaration(&mut self) -> anyhow::Result<()> {
+        self.expect(TokenType::Ident, "Expected name for let declaration")?;
+        let name = self.prev().clone();
+
+        if let TokenType::Equal = self.current().ty {
+            self.advance(1);
+            self.expression()?;
+        } else {
+            self.bytecode.add_opcode(OpcodeType::Nil.into());
+        };
+        self.expect(
+            TokenType::Semicolon,
+            "let_declaration :: Expected ';' after let declaration",
+        )?;
+        // a local is only declared once its value is on the stack, so the locals of a block in
+        // the initializer take the slots from there and `let x = x;` reads the outer x
+        if self.compiler.scope_depth > 0 {
+            self.compiler.push_local(name);
+        } else {
+            let global = self.global_name(&name);
+            let global = self.bytecode.add_constant_ident(&global);
+            self.define_variable(global);
+        }
+        Ok(())
+    }
+
+    /// enum Name { Unit, Tuple(a, b), Struct { a, b } }
+    ///
+    /// The enum type is built at compile time and bound to its name like a let declaration.
+    fn enum_declaration(&mut self) -> anyhow::Result<()> {
+        let global = self.parse_variable("Expected name for enum declaration")?;
+        let name = self.prev().lexeme.clone();
+        let attributes = self.take_attributes();
+        self.expect(TokenType::LeftBrace, "Expected '{' after enum name")?;
+
+        let mut variants: Vec<Variant> = Vec::new();
+        while self.current().ty != TokenType::RightBrace {
+            self.expect(TokenType::Ident, "Expected variant name")?;
+            let variant = self.prev().lexeme.clone();
+            if variants.iter().any(|v| v.name == variant) {
+                bail!("Duplicate variant '{}' in enum {}", variant, name);
+            }
+            let fields = match self.current().ty {
+                TokenType::LeftParen => {
+                    self.advance(1);
+                    VariantFields::Tuple(self.variant_fields(TokenType::RightParen)?)
+                }
+                TokenType::LeftBrace => {
+                    self.advance(1);
+                    VariantFields::Struct(self.variant_fields(TokenType::RightBrace)?)
+                }
+                _ => VariantFields::Unit,
+            };
+            variants.push(Variant {
+                name: variant,
+                fields,
+            });
+            if self.current().ty != TokenType::Comma {
+                break;
+            }
+            self.advance(1);
+        }
+        self.expect(TokenType::RightBrace, "Expected '}' after enum variants")?;
+        attributes.check("enum", &name)?;
+
+        let ty = EnumType {
+            name,
+            variants,
+            attributes,
+        };
+        self.bytecode
+            .add_constant(Value::Obj(Object::Enum(Arc::new(ty))));
+        self.define_variable(global);
+        Ok(())
+    }
+
+    /// fn name(params) where guard { body }
+    ///
+    /// Declarations of the same name that directly follow each other are compiled as clauses of
+    /// one function. Every clause gets its own chunk, with the function itself in slot 0 (so it
+    /// can call itself) followed by one slot per parameter. `fn* name` declares a generator.
+    fn fn_declaration(&mut self) -> anyhow::Result<()> {
+        let generator = self.generator_star();
+        let global = self.parse_variable("Expected function name after 'fn'")?;
+        let name = self.prev().clone();
+        let attributes = self.take_attributes();
+        let mut function = self.function(&name, generator)?;
+        attributes.check("fn", &name.lexeme)?;
+        function.attributes = attributes;
+        self.bytecode
+            .add_constant(Value::Obj(Object::Function(Arc::new(function))));
+        self.define_variable(global);
+        Ok(())
+    }
+
+    /// Consumes the `*` of a `fn*` generator declaration, if there is one.
+    fn generator_star(&mut self) -> bool {
+        let generator = self.current().ty == TokenType::Star;
+        if generator {
+            self.advance(1);
+        }
+        generator
+    }
+
+    /// Compiles the clauses of the function whose first name token was just consumed, `name`
+    /// is what the function is called in its slot 0 and in errors. Further clauses have to be
+    /// declared with the same `fn` or `fn*`.
+    fn function(&mut self, name: &Token, generator: bool) -> anyhow::Result<Function> {
+        let declared = self.prev().lexeme.clone();
+        let kind = if generator {
+            FunctionKind::Generator
+        } else {
+            FunctionKind::Function
+        };
+        let star = usize::from(generator);
+        let mut clauses = vec![self.function_clause(name, kind)?];
+        while self.current().ty == TokenType::Fn
+            && (!generator || self.tokens.get(self.i + 1).map(|t| t.ty) == Some(TokenType::Star))
+            && self
+                .tokens
+                .get(self.i + 1 + star)
+                .is_some_and(|t| t.ty == TokenType::Ident && t.lexeme == declared)
+        {
+            self.advance(2 + star);
+            clauses.push(self.function_clause(name, kind)?);
+        }
+        Ok(Function {
+            name: self.module().global(&name.lexeme),
+            clauses,
+            generator,
+            attributes: Attributes::default(),
+        })
+    }
+
+    /// struct Name : Parent { fields, fn method(self) { ... } }
+    ///
+    /// The struct type is built at compile time like an enum, with its methods compiled as
+    /// functions named `Name.method`. A struct with a parent is linked to it at runtime by
+    /// Inherit, which also prepends the parent's fields.
+    fn struct_declaration(&mut self) -> anyhow::Result<()> {
+        let global = self.parse_variable("Expected name for struct declaration")?;
+        let name = self.prev().clone();
+        let attributes = self.take_attributes();
+        let parent = if self.current().ty == TokenType::Colon {
+            self.advance(1);
+            self.expect(TokenType::Ident, "Expected parent struct name after ':'")?;
+            Some(self.prev().clone())
+        } else {
+            None
+        };
+        self.expect(TokenType::LeftBrace, "Expected '{' after struct name")?;
+
+        let enclosing = self.current_struct.replace(CurrentStruct {
+            name: name.clone(),
+            has_parent: parent.is_some(),
+        });
+        let result = self.struct_body(&name);
+        self.current_struct = enclosing;
+        let mut ty = result?;
+        attributes.check("struct", &name.lexeme)?;
+        ty.attributes = attributes;
+        let ty = Value::Obj(Object::Struct(Arc::new(ty)));
+        if let Some(parent) = parent {
+            self.named_variable(&parent, false)?;
+            self.bytecode.add_constant(ty);
+            self.bytecode.add_opcode(OpcodeType::Inherit.into());
+        } else {
+            self.bytecode.add_constant(ty);
+        }
+        self.define_variable(global);
+        Ok(())
+    }
+
+    /// trait Name { fn required(self); fn provided(self) { ... } }
+    ///
+    /// Built at compile time like a struct, methods with a body are the defaults implementations
+    /// get when they don't declare their own.
+    fn trait_declaration(&mut self) -> anyhow::Result<()> {
+        let global = self.parse_variable("Expected name for trait declaration")?;
+        let name = self.prev().clone();
+        let attributes = self.take_attributes();
+        self.expect(TokenType::LeftBrace, "Expected '{' after trait name")?;
+
+        let enclosing = self.current_struct.replace(CurrentStruct {
+            name: name.clone(),
+            has_parent: false,
+        });
+        let result = self.trait_body(&name);
+        self.current_struct = enclosing;
+        let mut tr = result?;
+        attributes.check("trait", &name.lexeme)?;
+        if Operator::is_trait_name(&name.lexeme) {
+            bail!(
+                "Compiler::Parser => Cannot redeclare the operator trait '{}'",
+                name.lexeme
+            );
+        }
+        tr.attributes = attributes;
+        self.bytecode
+            .add_constant(Value::Obj(Object::Trait(Arc::new(tr))));
+        self.define_variable(global);
+        Ok(())
+    }
+
+    fn trait_body(&mut self, name: &Token) -> anyhow::Result<Trait> {
+        let mut required: Vec<String> = Vec::new();
+        let mut defaults: HashMap<String, Arc<Function>> = HashMap::new();
+        while self.current().ty != TokenType::RightBrace && self.current().ty != TokenType::Eof {
+            let attributes = self.attributes()?;
+            self.expect(TokenType::Fn, "Expected method in trait")?;
+            let generator = self.generator_star();
+            self.expect(TokenType::Ident, "Expected method name after 'fn'")?;
+            let method = self.prev().lexeme.clone();
+            if required.contains(&method) || defaults.contains_key(&method) {
+                bail!("Duplicate method '{}' in trait {}", method, name.lexeme);
+            }
+            if self.is_method_signature() {
+                attributes.check("fn", &method)?;
+                // fn name(params);
+                while self.current().ty != TokenType::Semicolon {
+                    self.advance(1);
+                }
+                self.advance(1);
+                required.push(method);
+                continue;
+            }
+            let qualified = Token {
+                lexeme: format!("{}.{}", name.lexeme, method),
+                ..self.prev().clone()
+            };
+            let function = self.method(&qualified, generator, attributes)?;
+            defaults.insert(method, Arc::new(function));
+        }
+        self.expect(TokenType::RightBrace, "Expected '}' after trait body")?;
+        Ok(Trait {
+            name: name.lexeme.clone(),
+            required,
+            defaults,
+            attributes: Attributes::default(),
+        })
+    }
+
+    /// Compiles the clauses of a method, whose name was just consumed, with its attributes.
+    fn method(
+        &mut self,
+        qualified: &Token,
+        generator: bool,
+        attributes: Attributes,
+    ) -> anyhow::Result<Function> {
+        let method = self.prev().lexeme.clone();
+        let mut function = self.function(qualified, generator)?;
+        attributes.check("fn", &method)?;
+        function.attributes = attributes;
+        Ok(function)
+    }
+
+    /// Whether the method head starting at the current '(' ends in a ';' rather than a body.
+    fn is_method_signature(&self) -> bool {
+        let mut depth = 0;
+        for token in &self.tokens[self.i..] {
+            match token.ty {
+                TokenType::LeftParen => depth += 1,
+                TokenType::RightParen => depth -= 1,
+                TokenType::Semicolon if depth == 0 => return true,
+                TokenType::LeftBrace | TokenType::Where | TokenType::Eof if depth == 0 => {
+                    return false
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// impl Trait for Struct { fn method(self) { ... } }
+    ///
+    /// Methods are compiled like the struct's own. Impl checks them against the trait and
+    /// registers them with the struct at runtime, so the struct and trait can be declared in
+    /// any order before it runs.
+    fn impl_declaration(&mut self) -> anyhow::Result<()> {
+        self.expect(TokenType::Ident, "Expected trait name after 'impl'")?;
+        let trait_name = self.prev().clone();
+        self.expect(TokenType::For, "Expected 'for' after trait name")?;
+        self.expect(TokenType::Ident, "Expected struct name after 'for'")?;
+        let ty = self.prev().clone();
+        self.expect(TokenType::LeftBrace, "Expected '{' after impl struct name")?;
+
+        self.named_variable(&trait_name, false)?;
+        self.named_variable(&ty, false)?;
+        let enclosing = self.current_struct.replace(CurrentStruct {
+            name: ty.clone(),
+            has_parent: true,
+        });
+        let result = self.impl_body(&ty);
+        self.current_struct = enclosing;
+        let count = result?;
+        self.bytecode
+            .add_opcodes(OpcodeType::Impl.into(), Opcode(count));
+        Ok(())
+    }
+
+    /// Pushes the name and function of every method of an impl, returning how many there are.
+    fn impl_body(&mut self, ty: &Token) -> anyhow::Result<usize> {
+        let mut methods: Vec<String> = Vec::new();
+        while self.current().ty != TokenType::RightBrace && self.current().ty != TokenType::Eof {
+            let attributes = self.attributes()?;
+            self.expect(TokenType::Fn, "Expected method in impl")?;
+            let generator = self.generator_star();
+            self.expect(TokenType::Ident, "Expected method name after 'fn'")?;
+            let method = self.prev().lexeme.clone();
+            if methods.contains(&method) {
+                bail!("Duplicate method '{}' in impl for {}", method, ty.lexeme);
+            }
+            let qualified = Token {
+                lexeme: format!("{}.{}", ty.lexeme, method),
+                ..self.prev().clone()
+            };
+            let function = self.method(&qualified, generator, attributes)?;
+            self.bytecode
+                .add_constant(Value::Obj(Object::String(method.clone())));
+            self.bytecode
+                .add_constant(Value::Obj(Object::Function(Arc::new(function))));
+            methods.push(method);
+        }
+        self.expect(TokenType::RightBrace, "Expected '}' after impl body")?;
+        Ok(methods.len())
+    }
+
+    /// Fields and methods of a struct up to its closing '}', the parent is linked by Inherit.
+    fn struct_body(&mut self, name: &Token) -> anyhow::Result<StructType> {
+        let mut fields: Vec<String> = Vec::new();
+        let mut methods: HashMap<String, Arc<Function>> = HashMap::new();
+        while self.current().ty != TokenType::RightBrace && self.current().ty != TokenType::Eof {
+            if matches!(self.current().ty, TokenType::Fn | TokenType::At) {
+                let attributes = self.attributes()?;
+                self.expect(TokenType::Fn, "Expected method after attributes")?;
+                let generator = self.generator_star();
+                self.expect(TokenType::Ident, "Expected method name after 'fn'")?;
+                let method = self.prev().lexeme.clone();
+                if methods.contains_key(&method) {
+                    bail!("Duplicate method '{}' in struct {}", method, name.lexeme);
+                }
+                let qualified = Token {
+                    lexeme: format!("{}.{}", name.lexeme, method),
+                    ..self.prev().clone()
+                };
+                let function = self.method(&qualified, generator, attributes)?;
+                methods.insert(method, Arc::new(function));
+                continue;
+            }
+            self.expect(TokenType::Ident, "Expected field or method in struct")?;
+            let field = self.prev().lexeme.clone();
+            if fields.contains(&field) {
+                bail!("Duplicate field '{}' in struct {}", field, name.lexeme);
+            }
+            fields.push(field);
+            if self.current().ty == TokenType::Colon {
+                // field types are only checked by `corrosion check`
+                self.advance(1);
+                let (_, end) = Type::parse(&self.tokens, self.i)?;
+                self.advance(end - self.i);
+            }
+            if self.current().ty == TokenType::Comma {
+                self.advance(1);
+            } else if !matches!(
+                self.current().ty,
+                TokenType::RightBrace | TokenType::Fn | TokenType::At
+            ) {
+                bail!(
+                    "Compiler::Parser => Expected ',' after struct field; got: {}",
+                    self.current()
+                );
+            }
+        }
+        self.expect(TokenType::RightBrace, "Expected '}' after struct body")?;
+        Ok(StructType::new(name.lexeme.clone(), fields, methods))
+    }
+
+    fn function_clause(&mut self, name: &Token, kind: FunctionKind) -> anyhow::Result<Clause> {
+        let enclosing_bytecode = std::mem::take(&mut self.bytecode);
+        let enclosing_compiler =
+            std::mem::replace(&mut self.compiler, Compiler::function(name, kind));
+        let head_start = self.i - 1;
+        let result = self.function_clause_body();
+        let bytecode = std::mem::replace(&mut self.bytecode, enclosing_bytecode);
+        self.compiler = enclosing_compiler;
+
+        let (params, head_end) = result?;
+        Ok(Clause {
+            params,
+            signature: tokens_to_source(&self.tokens[head_start..head_end]),
+            body: FunctionBody::Chunk(Arc::new(bytecode)),
+        })
+    }
+
+    /// Compiles a clause's parameters, guard and body into the current chunk. Returns the
+    /// parameters and the index of the token ending the clause head.
+    fn function_clause_body(&mut self) -> anyhow::Result<(Vec<Param>, usize)> {
+        self.expect(TokenType::LeftParen, "Expected '(' after function name")?;
+        let mut params = Vec::new();
+        while self.current().ty != TokenType::RightParen {
+            params.push(self.param()?);
+            if self.current().ty != TokenType::Comma {
+                break;
+            }
+            self.advance(1);
+        }
+        self.expect(TokenType::RightParen, "Expected ')' after parameters")?;
+        if let Err(message) = check_params(&params) {
+            bail!("Compiler::Parser => {}", message);
+        }
+
+        if self.current().ty == TokenType::Where {
+            self.advance(1);
+            self.expression()?;
+            self.bytecode.add_opcode(OpcodeType::Guard.into());
+        }
+        let head_end = self.i;
+        self.expect(TokenType::LeftBrace, "Expected '{' before function body")?;
+        if !self.block_body()? {
+            self.bytecode.add_opcode(OpcodeType::Nil.into());
+        }
+        self.bytecode.add_opcode(OpcodeType::Return.into());
+        Ok((params, head_end))
+    }
+
+    /// A parameter is either a name the argument is bound to, a literal the argument has to
+    /// equal for the clause to be called, a name with a default or a rest parameter.
+    ///
+    /// Defaults are compiled in parameter order at the start of the clause, each one skipped by
+    /// DefaultArg when the caller passed that argument.
+    fn param(&mut self) -> anyhow::Result<Param> {
+        self.advance(1);
+        let token = self.prev().clone();
+        let param = match token.ty {
+            TokenType::Ident if self.current().ty == TokenType::Equal => {
+                self.advance(1);
+                let slot = self.compiler.locals.len();
+                self.bytecode
+                    .add_opcodes(OpcodeType::DefaultArg.into(), slot.into());
+                let skip_jump = self.emit_jump_target();
+                self.expression()?;
+                self.bytecode
+                    .add_opcodes(OpcodeType::SetLocal.into(), slot.into());
+                self.bytecode.add_opcode(OpcodeType::Pop.into());
+                self.patch_jump_target(skip_jump);
+                Param::Default(token.lexeme.clone())
+            }
+            TokenType::Ident | TokenType::ThisSelf => Param::Name(token.lexeme.clone()),
+            TokenType::DotDotDot => {
+                self.expect(TokenType::Ident, "Expected rest parameter name after '...'")?;
+                Param::Rest(self.prev().lexeme.clone())
+            }
+            TokenType::Number | TokenType::String | TokenType::Atom => {
+                Param::Literal(token.literal.clone())
+            }
+            TokenType::Minus if self.current().ty == TokenType::Number => {
+                self.advance(1);
+                Param::Literal(Value::Number(-self.prev().literal.as_number()?))
+            }
+            TokenType::True => Param::Literal(Value::Boolean(true)),
+            TokenType::False => Param::Literal(Value::Boolean(false)),
+            TokenType::Nil => Param::Literal(Value::Nil),
+            TokenType::None => Param::Literal(Value::none()),
+            TokenType::Some => {
+                self.expect(TokenType::LeftParen, "Expected '(' after Some in a pattern")?;
+                self.expect(TokenType::Ident, "Expected a name inside Some(...)")?;
+                let name = self.prev().lexeme.clone();
+                self.expect(TokenType::RightParen, "Expected ')' after Some(name")?;
+                Param::Some(name)
+            }
+            _ => bail!(
+                "Compiler::Parser => Expected parameter name or pattern; got: {}",
+                token
+            ),
+        };
+        // literal patterns still take up the argument's slot
+        let local = match param {
+            Param::Name(_) | Param::Default(_) => token,
+            Param::Rest(_) => self.prev().clone(),
+            Param::Some(_) => self.tokens[self.i - 2].clone(),
+            Param::Literal(_) => Token {
+                ty: TokenType::Ident,
+                literal: Value::Nil,
+                line: token.line,
+                lexeme: "pattern argument".into(),
+            },
+        };
+        self.compiler.push_local(local);
+        Ok(param)
+    }
+
+    /// Comma separated field names of a tuple or struct variant up to the closing delimiter.
+    fn variant_fields(&mut self, close: TokenType) -> anyhow::Result<Vec<String>> {
+        let mut fields: Vec<String> = Vec::new();
+        while self.current().ty != close {
+            self.expect(TokenType::Ident, "Expected variant field name")?;
+            let field = self.prev().lexeme.clone();
+            if fields.contains(&field) {
+                bail!("Duplicate variant field '{}'", field);
+            }
+            fields.push(field);
+            if self.current().ty != TokenType::Comma {
+                break;
+            }
+            self.advance(1);
+        }
+        self.expect(close, "Expected end of variant fields")?;
+        Ok(fields)
+    }
+
+    fn statement(&mut self) -> anyhow::Result<()> {
+        match self.current().ty {
+            TokenType::Print => {
+                self.advance(1);
+                self.print_statement()
+            }
+            TokenType::For => {
+                self.advance(1);
+                self.for_statement()
+            }
+            TokenType::Try => {
+                self.advance(1);
+                self.try_statement()
+            }
+            TokenType::Throw => {
+                self.advance(1);
+                self.throw_statement()
+            }
+            TokenType::Return => {
+                self.advance(1);
+                self.return_statement()
+            }
+            TokenType::Yield => {
+                self.advance(1);
+                self.yield_statement()
+            }
+            _ => self.expression_statement(),
+        }
+    }
+
+    fn expression(&mut self) -> anyhow::Result<()> {
+        self.parse_precedence(Precedence::Assignment)
+    }
+
+    fn block(&mut self) -> anyhow::Result<()> {
+        while self.current().ty != TokenType::RightBrace && self.current().ty != TokenType::Eof {
+            self.declaration()?;
+        }
+        self.expect(TokenType::RightBrace, "Expected '}' after block statement")
+    }
+
+    /// Compiles the declarations of a block up to its '}'. A final expression without a ';' is
+    /// left on the stack as the value of the block, returns whether there was one.
+    fn block_body(&mut self) -> anyhow::Result<bool> {
+        while self.current().ty != TokenType::RightBrace && self.current().ty != TokenType::Eof {
+            if starts_statement(self.current().ty) {
+                self.declaration()?;
+                continue;
+            }
+            match self.expression_or_tail() {
+                Ok(true) => {
+                    self.advance(1);
+                    return Ok(true);
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    self.report_error(&err);
+                    self.synchronize()?;
+                }
+            }
+        }
+        self.expect(TokenType::RightBrace, "Expected '}' after block")?;
+        Ok(false)
+    }
+
+    /// Compiles an expression statement, or the expression ending a block when a '}' follows
+    /// it, in which case its value is kept and true is returned.
+    fn expression_or_tail(&mut self) -> anyhow::Result<bool> {
+        self.expression()?;
+        if self.current().ty == TokenType::RightBrace {
+            return Ok(true);
+        }
+        self.end_expression_statement()?;
+        Ok(false)
+    }
+
+    /// { declarations; value }
+    ///
+    /// The value replaces the block's first local, the remaining locals are popped above it.
+    fn block_expression(&mut self, _: bool) -> anyhow::Result<()> {
+        self.begin_scope();
+        if !self.block_body()? {
+            self.bytecode.add_opcode(OpcodeType::Nil.into());
+        }
+
+        self.compiler.scope_depth -= 1;
+        let depth = self.compiler.scope_depth;
+        if let Some(first) = self.compiler.locals.iter().position(|l| l.depth > depth) {
+            self.bytecode
+                .add_opcodes(OpcodeType::SetLocal.into(), first.into());
+            for _ in first..self.compiler.locals.len() {
+                self.bytecode.add_opcode(OpcodeType::Pop.into());
+            }
+            self.compiler.locals.truncate(first);
+        }
+        Ok(())
+    }
+
+    /// if condition { then } else if condition { ... } else { otherwise }
+    ///
+    /// Evaluates to the value of the branch taken, or nil when there is no else branch.
+    fn if_expression(&mut self, _: bool) -> anyhow::Result<()> {
+        self.expression()?;
+        self.bytecode.add_opcode(OpcodeType::JumpIfFalse.into());
+        let else_jump = self.emit_jump_target();
+        self.bytecode.add_opcode(OpcodeType::Pop.into());
+        self.expect(TokenType::LeftBrace, "Expected '{' after if condition")?;
+        self.block_expression(false)?;
+        self.bytecode.add_opcode(OpcodeType::Jump.into());
+        let end_jump = self.emit_jump_target();
+
+        self.patch_jump_target(else_jump);
+        self.bytecode.add_opcode(OpcodeType::Pop.into());
+        if self.current().ty == TokenType::Else {
+            self.advance(1);
+            if self.current().ty == TokenType::If {
+                self.advance(1);
+                self.if_expression(false)?;
+            } else {
+                self.expect(TokenType::LeftBrace, "Expected '{' after else")?;
+                self.block_expression(false)?;
+            }
+        } else {
+            self.bytecode.add_opcode(OpcodeType::Nil.into());
+        }
+        self.patch_jump_target(end_jump);
+        Ok(())
+    }
+
+    /// for name in iterable { body }
+    ///
+    /// The iterable is stored in a hidden local that ForIter advances on every pass, pushing the
+    /// next value as the loop variable, or jumping past the loop once the iterable is exhausted.
+    fn for_statement(&mut self) -> anyhow::Result<()> {
+        self.begin_scope();
+        self.expect(TokenType::Ident, "Expected loop variable name after 'for'")?;
+        let name = self.prev().clone();
+        self.expect(TokenType::In, "Expected 'in' after for loop variable")?;
+        self.expression()?;
+        let iter_slot = self.compiler.locals.len();
+        self.compiler.push_local(Token {
+            ty: TokenType::Ident,
+            literal: Value::Nil,
+            line: name.line,
+            lexeme: "for iterator".into(),
+        });
+
+        let loop_start = self.bytecode.instructions_len();
+        self.bytecode
+            .add_opcodes(OpcodeType::ForIter.into(), iter_slot.into());
+        let exit_jump = self.emit_jump_target();
+
+        self.begin_scope();
+        self.compiler.push_local(name);
+        self.expect(TokenType::LeftBrace, "Expected '{' before for loop body")?;
+        self.block()?;
+        self.end_scope();
+        self.bytecode
+            .add_opcodes(OpcodeType::Jump.into(), loop_start.into());
+
+        self.patch_jump_target(exit_jump);
+        self.end_scope();
+        Ok(())
+    }
+
+    /// try { body } catch (name) { handler } finally { cleanup }
+    ///
+    /// PushHandler registers where to go when an error is raised before the matching PopHandler.
+    /// The VM unwinds the value stack to where it was when the handler was pushed and pushes the
+    /// error in its place, which the catch block binds to `name`. The finally block runs on every
+    /// path with a hidden local holding the error to rethrow once it completes, or nil. PushFinally
+    /// registers the finally block so a return from inside the try or catch block runs it first.
+    fn try_statement(&mut self) -> anyhow::Result<()> {
+        let has_finally = self.try_has_finally();
+        let mut finally_handler = None;
+        if has_finally {
+            self.bytecode.add_opcode(OpcodeType::PushFinally.into());
+            finally_handler = Some(self.emit_jump_target());
+        }
+        self.bytecode.add_opcode(OpcodeType::PushHandler.into());
+        let try_handler = self.emit_jump_target();
+        self.scoped_block("Expected '{' after try")?;
+        self.bytecode.add_opcode(OpcodeType::PopHandler.into());
+
+        let has_catch = self.current().ty == TokenType::Catch;
+        if !has_catch && !has_finally {
+            bail!(
+                "Compiler::Parser => Expected 'catch' or 'finally' after try block; got: {}",
+                self.current()
+            );
+        }
+
+        if has_finally {
+            self.bytecode.add_opcode(OpcodeType::Nil.into());
+        }
+        if has_catch {
+            self.advance(1);
+            self.bytecode.add_opcode(OpcodeType::Jump.into());
+            let try_exit = self.emit_jump_target();
+
+            self.patch_jump_target(try_handler);
+            self.expect(TokenType::LeftParen, "Expected '(' after catch")?;
+            self.expect(TokenType::Ident, "Expected error name in catch")?;
+            let name = self.prev().clone();
+            self.expect(TokenType::RightParen, "Expected ')' after catch error name")?;
+            self.begin_scope();
+            let error_slot = self.compiler.locals.len();
+            self.compiler.push_local(name);
+
+            let mut catch_handler = None;
+            if has_finally {
+                self.bytecode.add_opcode(OpcodeType::PushHandler.into());
+                catch_handler = Some(self.emit_jump_target());
+            }
+            self.scoped_block("Expected '{' after catch")?;
+            if has_finally {
+                self.bytecode.add_opcode(OpcodeType::PopHandler.into());
+            }
+            self.end_scope();
+
+            if let Some(catch_handler) = catch_handler {
+                self.bytecode.add_opcode(OpcodeType::Nil.into());
+                self.bytecode.add_opcode(OpcodeType::Jump.into());
+                let catch_exit = self.emit_jump_target();
+                // an error from the catch block lands on top of the caught error, move it into
+                // the caught error's slot so it becomes the error pending in finally
+                self.patch_jump_target(catch_handler);
+                self.bytecode
+                    .add_opcodes(OpcodeType::SetLocal.into(), error_slot.into());
+                self.bytecode.add_opcode(OpcodeType::Pop.into());
+                self.patch_jump_target(catch_exit);
+            }
+            self.patch_jump_target(try_exit);
+        } else {
+            self.patch_jump_target(try_handler);
+        }
+
+        if let Some(finally_handler) = finally_handler {
+            self.bytecode.add_opcode(OpcodeType::PopHandler.into());
+            // a return enters here with the value to return in place of the pending error
+            self.patch_jump_target(finally_handler);
+            self.expect(TokenType::Finally, "Expected 'finally' after catch block")?;
+            self.begin_scope();
+            let pending_slot = self.compiler.locals.len();
+            self.compiler.push_local(Token {
+                ty: TokenType::Ident,
+                literal: Value::Nil,
+                line: self.prev().line,
+                lexeme: "finally pending error".into(),
+            });
+            self.scoped_block("Expected '{' after finally")?;
+            self.bytecode
+                .add_opcodes(OpcodeType::GetLocal.into(), pending_slot.into());
+            self.bytecode.add_opcode(OpcodeType::EndFinally.into());
+            self.end_scope();
+        }
+        Ok(())
+    }
+
+    /// Whether the try statement starting at the current token has a finally block.
+    fn try_has_finally(&self) -> bool {
+        // try { ... } catch (name) { ... } finally
+        let try_end = self.matching_brace(self.i);
+        match self.tokens.get(try_end + 1).map(|t| t.ty) {
+            Some(TokenType::Catch) => {
+                let catch_end = self.matching_brace(try_end + 5);
+                self.tokens.get(catch_end + 1).map(|t| t.ty) == Some(TokenType::Finally)
+            }
+            ty => ty == Some(TokenType::Finally),
+        }
+    }
+
+    fn return_statement(&mut self) -> anyhow::Result<()> {
+        if self.compiler.kind == FunctionKind::Script {
+            bail!("Compiler::Parser => Cannot return from top-level code");
+        }
+        if self.current().ty == TokenType::Semicolon {
+            self.bytecode.add_opcode(OpcodeType::Nil.into());
         } else {
+            self.expression()?;
+        }
+        self.expect(TokenType::Semicolon, "Expected ';' after return value")?;
+        self.bytecode.add_opcode(OpcodeType::Return.into());
+        Ok(())
+    }
+
+    /// yield value; suspends the generator, the value is what resuming it produces.
+    fn yield_statement(&mut self) -> anyhow::Result<()> {
+        if self.compiler.kind != FunctionKind::Generator {
+            bail!("Compiler::Parser => Cannot use 'yield' outside of a generator function");
+        }
+        if self.current().ty == TokenType::Semicolon {
             self.bytecode.add_opcode(OpcodeType::Nil.into());
-        };
+        } else {
+            self.expression()?;
+        }
+        self.expect(TokenType::Semicolon, "Expected ';' after yield value")?;
+        self.bytecode.add_opcode(OpcodeType::Yield.into());
+        Ok(())
+    }
+
+    fn throw_statement(&mut self) -> anyhow::Result<()> {
+        self.expression()?;
         self.expect(
             TokenType::Semicolon,
-            "let_declaration :: Expected ';' after let declaration",
+            "throw_statement :: Expected ';' at end of statement",
         )?;
-        self.define_variable(global);
+        self.bytecode.add_opcode(OpcodeType::Throw.into());
         Ok(())
     }
 
-    fn statement(&mut self) -> anyhow::Result<()> {
-        match self.current().ty {
-            TokenType::Print => {
-                self.advance(1);
-                self.print_statement()
-            }
-            TokenType::LeftBrace => {
-                self.advance(1);
-                self.begin_scope();
+    /// Expects a '{' and compiles the block it opens in a new scope.
+    fn scoped_block(&mut self, message: &str) -> anyhow::Result<()> {
+        self.expect(TokenType::LeftBrace, message)?;
+        self.begin_scope();
+        self.block()?;
+        self.end_scope();
+        Ok(())
+    }
 
-                self.block()?;
-                self.end_scope();
-                Ok(())
+    /// Index of the '}' closing the '{' at `open`, or of the Eof token if it is never closed.
+    fn matching_brace(&self, open: usize) -> usize {
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate().skip(open) {
+            match token.ty {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 1 => return i,
+                TokenType::RightBrace => depth -= 1,
+                TokenType::Eof => return i,
+                _ => {}
             }
-            _ => self.expression_statement(),
         }
+        self.tokens.len() - 1
     }
 
-    fn expression(&mut self) -> anyhow::Result<()> {
-        self.parse_precedence(Precedence::Assignment)
+    /// Emits a placeholder jump target operand, returning its index so it can be patched once
+    /// the target is known.
+    fn emit_jump_target(&mut self) -> usize {
+        self.bytecode.add_opcode(Opcode(0));
+        self.bytecode.instructions_len() - 1
     }
 
-    fn block(&mut self) -> anyhow::Result<()> {
-        while self.current().ty != TokenType::RightBrace && self.current().ty != TokenType::Eof {
-            self.declaration()?;
-        }
-        self.expect(TokenType::RightBrace, "Expected '}' after block statement")
+    /// Points the jump target at `index` to the next instruction to be emitted.
+    fn patch_jump_target(&mut self, index: usize) {
+        let target = self.bytecode.instructions_len();
+        self.bytecode.set_opcode(index, target.into());
     }
 
     fn expression_statement(&mut self) -> anyhow::Result<()> {
         self.expression()?;
-        self.expect(
-            TokenType::Semicolon,
-            "expression_statement :: Expected ';' at end of statement",
-        )?;
+        self.end_expression_statement()
+    }
+
+    /// Discards the value of an expression statement. The ';' is optional after an expression
+    /// ending in a block, like `if` or `{ ... }`.
+    fn end_expression_statement(&mut self) -> anyhow::Result<()> {
+        if self.prev().ty != TokenType::RightBrace || self.current().ty == TokenType::Semicolon {
+            self.expect(
+                TokenType::Semicolon,
+                "expression_statement :: Expected ';' at end of statement",
+            )?;
+        }
         self.bytecode.add_opcode(OpcodeType::Pop.into());
         Ok(())
     }
@@ -130,14 +1230,19 @@ impl Parser {
         Ok(())
     }
 
-    fn parse_variable(&mut self) -> anyhow::Result<usize> {
-        self.expect(TokenType::Ident, "Expected name for let declaration")?;
+    fn atom(&mut self, _: bool) -> anyhow::Result<()> {
+        self.bytecode.add_constant(self.prev().literal.clone());
+        Ok(())
+    }
+
+    fn parse_variable(&mut self, message: &str) -> anyhow::Result<usize> {
+        self.expect(TokenType::Ident, message)?;
 
         self.declare_variable()?;
         if self.compiler.scope_depth > 0 {
             return Ok(0);
         }
-        let prev_tok = self.prev().clone();
+        let prev_tok = self.global_name(&self.prev().clone());
         let name_index = self.bytecode.add_constant_ident(&prev_tok);
         Ok(name_index)
     }
@@ -149,12 +1254,19 @@ impl Parser {
             } else {
                 match self.current().ty {
                     TokenType::Struct
+                    | TokenType::Enum
                     | TokenType::Fn
                     | TokenType::Let
                     | TokenType::For
                     | TokenType::If
                     | TokenType::While
                     | TokenType::Print
+                    | TokenType::Try
+                    | TokenType::Throw
+                    | TokenType::Mod
+                    | TokenType::Pub
+                    | TokenType::Use
+                    | TokenType::At
                     | TokenType::Return => return Ok(()),
                     _ => self.advance(1),
                 };
@@ -164,7 +1276,13 @@ impl Parser {
     }
 
     fn variable(&mut self, can_assign: bool) -> anyhow::Result<()> {
-        self.named_variable(&self.prev().clone(), can_assign)
+        let name = self.prev().clone();
+        if self.current().ty == TokenType::DoubleColon {
+            if let Some(global) = self.module_path(&name)? {
+                return self.named_variable(&global, false);
+            }
+        }
+        self.named_variable(&name, can_assign)
     }
 
     fn named_variable(&mut self, name: &Token, can_assign: bool) -> anyhow::Result<()> {
@@ -177,10 +1295,11 @@ impl Parser {
                     i,
                 )
             } else {
+                let global = self.global_name(name);
                 (
                     Opcode::from(OpcodeType::GetGlobal),
                     Opcode::from(OpcodeType::SetGlobal),
-                    self.bytecode.add_constant_ident(name),
+                    self.bytecode.add_constant_ident(&global),
                 )
                 // (Opcode::from(OpcodeType::GetGlobal, Opcode::from(OpcodeType::SetGlobal))
             }
@@ -191,6 +1310,15 @@ impl Parser {
             self.advance(1);
             self.expression()?;
             self.bytecode.add_opcodes(set, arg.into());
+        } else if let (true, Some(op)) = (can_assign, compound_assignment_op(self.current().ty)) {
+            // target is read once, combined with the right hand side, then written back
+            self.advance(1);
+            self.bytecode.add_opcodes(get, arg.into());
+            self.compiler.push_temporary();
+            self.expression()?;
+            self.compiler.pop_temporaries(1);
+            self.bytecode.add_opcode(op.into());
+            self.bytecode.add_opcodes(set, arg.into());
         } else {
             self.bytecode.add_opcodes(get, arg.into());
         };
@@ -198,12 +1326,317 @@ impl Parser {
         Ok(())
     }
 
-    // true, false, nil
+    // Some(value), Ok(value), Err(value)
+    fn wrapper(&mut self, _: bool) -> anyhow::Result<()> {
+        let wrapper = self.prev().clone();
+        self.expect(
+            TokenType::LeftParen,
+            &format!("Expected '(' after {}", wrapper.lexeme),
+        )?;
+        self.expression()?;
+        self.expect(
+            TokenType::RightParen,
+            &format!("Expected ')' after {} value", wrapper.lexeme),
+        )?;
+        match wrapper.ty {
+            TokenType::Some => self.bytecode.add_opcode(OpcodeType::Some.into()),
+            TokenType::Ok => self.bytecode.add_opcode(OpcodeType::Ok.into()),
+            TokenType::Err => self.bytecode.add_opcode(OpcodeType::Err.into()),
+            _ => unreachable!("Unreachable branch in compiler::Parser::wrapper"),
+        };
+        Ok(())
+    }
+
+    /// callee(positional, name: keyword)
+    ///
+    /// Keyword arguments follow the positional ones, their names are passed to CallKeywords as a
+    /// List constant in the same order.
+    fn call(&mut self, _: bool) -> anyhow::Result<()> {
+        let mut argc = 0;
+        self.compiler.push_temporary();
+        let mut keywords: Vec<Value> = Vec::new();
+        while self.current().ty != TokenType::RightParen {
+            let is_keyword = self.current().ty == TokenType::Ident
+                && self.tokens.get(self.i + 1).map(|t| t.ty) == Some(TokenType::Colon);
+            if is_keyword {
+                let keyword = Value::Obj(Object::String(self.current().lexeme.clone()));
+                if keywords.contains(&keyword) {
+                    bail!(
+                        "Compiler::Parser => Duplicate keyword argument '{}'",
+                        keyword
+                    );
+                }
+                keywords.push(keyword);
+                self.advance(2);
+            } else if !keywords.is_empty() {
+                bail!(
+                    "Compiler::Parser => Positional argument can't follow keyword arguments; got: {}",
+                    self.current()
+                );
+            }
+            self.expression()?;
+            self.compiler.push_temporary();
+            argc += 1;
+            if self.current().ty != TokenType::Comma {
+                break;
+            }
+            self.advance(1);
+        }
+        self.expect(TokenType::RightParen, "Expected ')' after arguments")?;
+        self.compiler.pop_temporaries(argc + 1);
+        if keywords.is_empty() {
+            self.bytecode
+                .add_opcodes(OpcodeType::Call.into(), Opcode(argc));
+        } else {
+            let keywords = self.bytecode.push_constant(Value::list(keywords));
+            self.bytecode
+                .add_opcodes(OpcodeType::CallKeywords.into(), Opcode(argc));
+            self.bytecode.add_opcode(Opcode(keywords));
+        }
+        Ok(())
+    }
+
+    // [a, b, c]
+    fn list(&mut self, _: bool) -> anyhow::Result<()> {
+        if let Some(first_for) = self.comprehension_for() {
+            return self.comprehension(first_for, false);
+        }
+        let mut len = 0;
+        while self.current().ty != TokenType::RightBracket {
+            self.expression()?;
+            self.compiler.push_temporary();
+            len += 1;
+            if self.current().ty != TokenType::Comma {
+                break;
+            }
+            self.advance(1);
+        }
+        self.expect(TokenType::RightBracket, "Expected ']' after list elements")?;
+        self.compiler.pop_temporaries(len);
+        self.bytecode
+            .add_opcodes(OpcodeType::List.into(), Opcode(len));
+        Ok(())
+    }
+
+    // %{a => 1, b => 2}
+    fn map(&mut self, _: bool) -> anyhow::Result<()> {
+        self.expect(
+            TokenType::LeftBrace,
+            "Expected '{' after '%' to start a map",
+        )?;
+        if let Some(first_for) = self.comprehension_for() {
+            return self.comprehension(first_for, true);
+        }
+        let mut len = 0;
+        while self.current().ty != TokenType::RightBrace {
+            self.expression()?;
+            self.compiler.push_temporary();
+            self.expect(TokenType::FatArrow, "Expected '=>' after map key")?;
+            self.expression()?;
+            self.compiler.push_temporary();
+            len += 1;
+            if self.current().ty != TokenType::Comma {
+                break;
+            }
+            self.advance(1);
+        }
+        self.expect(TokenType::RightBrace, "Expected '}' after map entries")?;
+        self.compiler.pop_temporaries(len * 2);
+        self.bytecode
+            .add_opcodes(OpcodeType::Map.into(), Opcode(len));
+        Ok(())
+    }
+
+    /// Index of the `for` starting the clauses of a comprehension, when the list or map literal
+    /// whose '[' or '{' was just consumed is one.
+    fn comprehension_for(&self) -> Option<usize> {
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate().skip(self.i) {
+            match token.ty {
+                TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => depth += 1,
+                TokenType::RightBracket | TokenType::RightBrace | TokenType::Comma
+                    if depth == 0 =>
+                {
+                    return None
+                }
+                TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => {
+                    depth -= 1
+                }
+                TokenType::For if depth == 0 => return Some(i),
+                TokenType::Eof => return None,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// [element for name in iterable if condition for ...]
+    /// %{key => element for name in iterable ...} when `map` is set
+    ///
+    /// Compiled into nested loops like for statements, with the list being built in a hidden
+    /// local that Append adds each element to, or Insert each entry of the map. The element comes
+    /// first in the source but is compiled inside the innermost loop, so its tokens are skipped
+    /// and compiled once the clauses have been.
+    fn comprehension(&mut self, first_for: usize, map: bool) -> anyhow::Result<()> {
+        let element = self.i;
+        self.advance(first_for - self.i);
+        self.begin_scope();
+        let list_slot = self.compiler.locals.len();
+        let empty = if map {
+            OpcodeType::Map
+        } else {
+            OpcodeType::List
+        };
+        self.bytecode.add_opcodes(empty.into(), Opcode(0));
+        self.compiler.push_local(Token {
+            ty: TokenType::Ident,
+            literal: Value::Nil,
+            line: self.current().line,
+            lexeme: "comprehension list".into(),
+        });
+        self.comprehension_clauses(element, list_slot, map)?;
+        // the loops have popped everything above the list, which is left as the value
+        self.compiler.scope_depth -= 1;
+        self.compiler.locals.truncate(list_slot);
+        Ok(())
+    }
+
+    /// Compiles the `for` and `if` clauses from the current token, then the element.
+    fn comprehension_clauses(
+        &mut self,
+        element: usize,
+        list_slot: usize,
+        map: bool,
+    ) -> anyhow::Result<()> {
+        match self.current().ty {
+            TokenType::For => {
+                self.advance(1);
+                self.expect(TokenType::Ident, "Expected loop variable name after 'for'")?;
+                let name = self.prev().clone();
+                self.expect(TokenType::In, "Expected 'in' after for loop variable")?;
+                self.expression()?;
+                let iter_slot = self.compiler.locals.len();
+                self.compiler.push_local(Token {
+                    lexeme: "for iterator".into(),
+                    ..name.clone()
+                });
+                let loop_start = self.bytecode.instructions_len();
+                self.bytecode
+                    .add_opcodes(OpcodeType::ForIter.into(), iter_slot.into());
+                let exit_jump = self.emit_jump_target();
+
+                self.begin_scope();
+                self.compiler.push_local(name);
+                self.comprehension_clauses(element, list_slot, map)?;
+                self.end_scope();
+                self.bytecode
+                    .add_opcodes(OpcodeType::Jump.into(), loop_start.into());
+                self.patch_jump_target(exit_jump);
+                // the iterator
+                self.compiler.pop_local();
+                self.bytecode.add_opcode(OpcodeType::Pop.into());
+            }
+            TokenType::If => {
+                self.advance(1);
+                self.expression()?;
+                self.bytecode.add_opcode(OpcodeType::JumpIfFalse.into());
+                let skip_jump = self.emit_jump_target();
+                self.bytecode.add_opcode(OpcodeType::Pop.into());
+                self.comprehension_clauses(element, list_slot, map)?;
+                self.bytecode.add_opcode(OpcodeType::Jump.into());
+                let end_jump = self.emit_jump_target();
+                self.patch_jump_target(skip_jump);
+                self.bytecode.add_opcode(OpcodeType::Pop.into());
+                self.patch_jump_target(end_jump);
+            }
+            _ if map => {
+                self.expect(
+                    TokenType::RightBrace,
+                    "Expected 'for', 'if' or '}' in map comprehension",
+                )?;
+                let end = self.i;
+                self.i = element;
+                self.expression()?;
+                self.expect(TokenType::FatArrow, "Expected '=>' after map key")?;
+                self.expression()?;
+                if self.current().ty != TokenType::For {
+                    bail!(
+                        "Compiler::Parser => Expected 'for' after map comprehension entry; got: {}",
+                        self.current()
+                    );
+                }
+                self.bytecode
+                    .add_opcodes(OpcodeType::Insert.into(), list_slot.into());
+                self.i = end;
+                self.bytecode.set_line(self.prev().line as u64);
+            }
+            _ => {
+                self.expect(
+                    TokenType::RightBracket,
+                    "Expected 'for', 'if' or ']' in list comprehension",
+                )?;
+                let end = self.i;
+                self.i = element;
+                self.expression()?;
+                if self.current().ty != TokenType::For {
+                    bail!(
+                        "Compiler::Parser => Expected 'for' after list comprehension element; got: {}",
+                        self.current()
+                    );
+                }
+                self.bytecode
+                    .add_opcodes(OpcodeType::Append.into(), list_slot.into());
+                self.i = end;
+                self.bytecode.set_line(self.prev().line as u64);
+            }
+        }
+        Ok(())
+    }
+
+    // value?
+    fn try_unwrap(&mut self, _: bool) -> anyhow::Result<()> {
+        self.bytecode.add_opcode(OpcodeType::Try.into());
+        Ok(())
+    }
+
+    /// left ?? right, right is only evaluated when left is nil or None.
+    fn coalesce(&mut self, _: bool) -> anyhow::Result<()> {
+        self.bytecode.add_opcode(OpcodeType::Coalesce.into());
+        let end_jump = self.emit_jump_target();
+        self.parse_precedence(Precedence::Coalesce.next())?;
+        self.patch_jump_target(end_jump);
+        Ok(())
+    }
+
+    /// left and right, right is only evaluated when left is truthy.
+    fn and(&mut self, _: bool) -> anyhow::Result<()> {
+        self.bytecode.add_opcode(OpcodeType::JumpIfFalse.into());
+        let end_jump = self.emit_jump_target();
+        self.bytecode.add_opcode(OpcodeType::Pop.into());
+        self.parse_precedence(Precedence::And.next())?;
+        self.patch_jump_target(end_jump);
+        Ok(())
+    }
+
+    /// left or right, right is only evaluated when left is falsey.
+    fn or(&mut self, _: bool) -> anyhow::Result<()> {
+        self.bytecode.add_opcode(OpcodeType::JumpIfTrue.into());
+        let end_jump = self.emit_jump_target();
+        self.bytecode.add_opcode(OpcodeType::Pop.into());
+        self.parse_precedence(Precedence::Or.next())?;
+        self.patch_jump_target(end_jump);
+        Ok(())
+    }
+
+    // true, false, nil, None
     fn literal(&mut self, _: bool) -> anyhow::Result<()> {
         match self.prev().ty {
             TokenType::Nil => self.bytecode.add_opcode(OpcodeType::Nil.into()),
             TokenType::False => self.bytecode.add_opcode(OpcodeType::False.into()),
             TokenType::True => self.bytecode.add_opcode(OpcodeType::True.into()),
+            TokenType::None => {
+                self.bytecode.add_constant(Value::none());
+            }
             _ => unreachable!(
                 "Expected token to be a literal (true, false, nil), got: {}",
                 self.prev()
@@ -230,11 +1663,12 @@ impl Parser {
     fn unary(&mut self, _: bool) -> anyhow::Result<()> {
         let op_type = self.prev().ty;
 
-        self.expression()?;
+        self.parse_precedence(Precedence::Unary)?;
 
         match op_type {
             TokenType::Minus => self.bytecode.add_opcode(OpcodeType::Negate.into()),
             TokenType::Bang => self.bytecode.add_opcode(OpcodeType::Not.into()),
+            TokenType::Tilde => self.bytecode.add_opcode(OpcodeType::BitNot.into()),
             _ => unreachable!("Unreachable branch in compiler::Parser::unary"),
         };
         Ok(())
@@ -244,12 +1678,30 @@ impl Parser {
         let op_type = self.prev().ty;
         let rule = get_parse_rule(op_type);
 
-        self.parse_precedence(rule.precedence.next())?;
+        // ** is right associative, so its right operand is parsed at the same precedence
+        self.compiler.push_temporary();
+        if op_type == TokenType::StarStar {
+            self.parse_precedence(rule.precedence)?;
+        } else {
+            self.parse_precedence(rule.precedence.next())?;
+        }
+        self.compiler.pop_temporaries(1);
         match op_type {
             TokenType::Plus => self.bytecode.add_opcode(OpcodeType::Add.into()),
             TokenType::Minus => self.bytecode.add_opcode(OpcodeType::Subtract.into()),
             TokenType::Star => self.bytecode.add_opcode(OpcodeType::Mult.into()),
             TokenType::ForwardSlash => self.bytecode.add_opcode(OpcodeType::Div.into()),
+            TokenType::Percent => self.bytecode.add_opcode(OpcodeType::Modulo.into()),
+            TokenType::StarStar => self.bytecode.add_opcode(OpcodeType::Power.into()),
+            TokenType::TildeSlash => self.bytecode.add_opcode(OpcodeType::IntDiv.into()),
+            TokenType::Ampersand => self.bytecode.add_opcode(OpcodeType::BitAnd.into()),
+            TokenType::Pipe => self.bytecode.add_opcode(OpcodeType::BitOr.into()),
+            TokenType::Caret => self.bytecode.add_opcode(OpcodeType::BitXor.into()),
+            TokenType::ShiftLeft => self.bytecode.add_opcode(OpcodeType::ShiftLeft.into()),
+            TokenType::ShiftRight => self.bytecode.add_opcode(OpcodeType::ShiftRight.into()),
+            TokenType::DotDot => self.bytecode.add_opcode(OpcodeType::Range.into()),
+            TokenType::DotDotEqual => self.bytecode.add_opcode(OpcodeType::RangeInclusive.into()),
+            TokenType::In => self.bytecode.add_opcode(OpcodeType::In.into()),
             TokenType::BangEqual => self
                 .bytecode
                 .add_opcodes(OpcodeType::Equal.into(), OpcodeType::Not.into()),
@@ -267,6 +1719,146 @@ impl Parser {
         Ok(())
     }
 
+    // value.name, value.0, value.name = x, value.name += x
+    fn dot(&mut self, can_assign: bool) -> anyhow::Result<()> {
+        if self.current().ty == TokenType::Number {
+            self.advance(1);
+        } else {
+            self.expect(TokenType::Ident, "Expected property name after '.'")?;
+        }
+        let name = self.bytecode.add_constant_ident(&self.prev().clone());
+        if can_assign && self.current().ty == TokenType::Equal {
+            self.advance(1);
+            self.compiler.push_temporary();
+            self.expression()?;
+            self.compiler.pop_temporaries(1);
+            self.bytecode
+                .add_opcodes(OpcodeType::SetProperty.into(), name.into());
+        } else if let (true, Some(op)) = (can_assign, compound_assignment_op(self.current().ty)) {
+            // the object stays below the current value so the result can be written back to it
+            self.advance(1);
+            self.bytecode.add_opcode(OpcodeType::Dup.into());
+            self.bytecode
+                .add_opcodes(OpcodeType::GetProperty.into(), name.into());
+            self.compiler.push_temporary();
+            self.compiler.push_temporary();
+            self.expression()?;
+            self.compiler.pop_temporaries(2);
+            self.bytecode.add_opcode(op.into());
+            self.bytecode
+                .add_opcodes(OpcodeType::SetProperty.into(), name.into());
+        } else {
+            self.bytecode
+                .add_opcodes(OpcodeType::GetProperty.into(), name.into());
+        }
+        Ok(())
+    }
+
+    // self
+    fn this(&mut self, _: bool) -> anyhow::Result<()> {
+        let name = self.prev().clone();
+        let Some(slot) = self.compiler.resolve_local(&name) else {
+            bail!("Compiler::Parser => Cannot use 'self' outside of a method");
+        };
+        self.bytecode
+            .add_opcodes(OpcodeType::GetLocal.into(), slot.into());
+        Ok(())
+    }
+
+    /// super.method, the method is looked up from the parent of the struct being declared and
+    /// bound to `self`.
+    fn super_(&mut self, _: bool) -> anyhow::Result<()> {
+        let keyword = self.prev().clone();
+        let Some(CurrentStruct { name, has_parent }) = self.current_struct.clone() else {
+            bail!("Compiler::Parser => Cannot use 'super' outside of a method");
+        };
+        if !has_parent {
+            bail!(
+                "Compiler::Parser => Cannot use 'super' in {}, it has no parent",
+                name.lexeme
+            );
+        }
+        self.expect(TokenType::Dot, "Expected '.' after 'super'")?;
+        self.expect(TokenType::Ident, "Expected method name after 'super.'")?;
+        let method = self.bytecode.add_constant_ident(&self.prev().clone());
+        let this = Token {
+            ty: TokenType::ThisSelf,
+            lexeme: "self".into(),
+            ..keyword
+        };
+        let Some(slot) = self.compiler.resolve_local(&this) else {
+            bail!("Compiler::Parser => Cannot use 'super' in a method without 'self'");
+        };
+        self.named_variable(&name, false)?;
+        self.bytecode
+            .add_opcodes(OpcodeType::GetLocal.into(), slot.into());
+        self.bytecode
+            .add_opcodes(OpcodeType::GetSuper.into(), method.into());
+        Ok(())
+    }
+
+    // Enum::Variant
+    fn path(&mut self, _: bool) -> anyhow::Result<()> {
+        self.expect(TokenType::Ident, "Expected name after '::'")?;
+        let name = self.bytecode.add_constant_ident(&self.prev().clone());
+        self.bytecode
+            .add_opcodes(OpcodeType::GetPath.into(), name.into());
+        Ok(())
+    }
+
+    // value?.name, value?.name(args), the value itself when it is nil or None
+    fn safe_dot(&mut self, _: bool) -> anyhow::Result<()> {
+        self.bytecode.add_opcode(OpcodeType::SafeNav.into());
+        let end_jump = self.emit_jump_target();
+        if self.current().ty == TokenType::Number {
+            self.advance(1);
+        } else {
+            self.expect(TokenType::Ident, "Expected property name after '?.'")?;
+        }
+        let name = self.bytecode.add_constant_ident(&self.prev().clone());
+        self.bytecode
+            .add_opcodes(OpcodeType::GetProperty.into(), name.into());
+        // a method call is skipped along with the property
+        if self.current().ty == TokenType::LeftParen {
+            self.advance(1);
+            self.call(false)?;
+        }
+        self.patch_jump_target(end_jump);
+        Ok(())
+    }
+
+    // value[index], value[index] = x, value[index] += x
+    fn index(&mut self, can_assign: bool) -> anyhow::Result<()> {
+        self.compiler.push_temporary();
+        self.expression()?;
+        self.compiler.pop_temporaries(1);
+        self.expect(TokenType::RightBracket, "Expected ']' after index")?;
+        if can_assign && self.current().ty == TokenType::Equal {
+            self.advance(1);
+            self.compiler.push_temporary();
+            self.compiler.push_temporary();
+            self.expression()?;
+            self.compiler.pop_temporaries(2);
+            self.bytecode.add_opcode(OpcodeType::SetIndex.into());
+        } else if let (true, Some(op)) = (can_assign, compound_assignment_op(self.current().ty)) {
+            // the value and index stay below the current element so the result can be written
+            // back to it
+            self.advance(1);
+            self.bytecode.add_opcode(OpcodeType::Dup2.into());
+            self.bytecode.add_opcode(OpcodeType::Index.into());
+            for _ in 0..3 {
+                self.compiler.push_temporary();
+            }
+            self.expression()?;
+            self.compiler.pop_temporaries(3);
+            self.bytecode.add_opcode(op.into());
+            self.bytecode.add_opcode(OpcodeType::SetIndex.into());
+        } else {
+            self.bytecode.add_opcode(OpcodeType::Index.into());
+        }
+        Ok(())
+    }
+
     fn expect(&mut self, ty: TokenType, message: &str) -> anyhow::Result<()> {
         if self.current().ty == ty {
             self.advance(1);
@@ -331,13 +1923,20 @@ impl Parser {
 pub enum Precedence {
     None = 0,
     Assignment, // =
+    Coalesce,   // ??
     Or,         // or
     And,        // and
     Equality,   // == !=
-    Comparison, // < > <= >=
+    Comparison, // < > <= >= in
+    Range,      // .. ..=
+    BitOr,      // |
+    BitXor,     // ^
+    BitAnd,     // &
+    Shift,      // << >>
     Term,       // + -
-    Factor,     // * /
-    Unary,      // ! -
+    Factor,     // * / % ~/
+    Unary,      // ! - ~
+    Exponent,   // **
     Call,       // . ()
     Primary,
     PrecedenceCount,
@@ -347,14 +1946,21 @@ impl Precedence {
     const fn next(&self) -> Self {
         match self {
             Precedence::None => Precedence::Assignment,
-            Precedence::Assignment => Precedence::Or,
+            Precedence::Assignment => Precedence::Coalesce,
+            Precedence::Coalesce => Precedence::Or,
             Precedence::Or => Precedence::And,
             Precedence::And => Precedence::Equality,
             Precedence::Equality => Precedence::Comparison,
-            Precedence::Comparison => Precedence::Term,
+            Precedence::Comparison => Precedence::Range,
+            Precedence::Range => Precedence::BitOr,
+            Precedence::BitOr => Precedence::BitXor,
+            Precedence::BitXor => Precedence::BitAnd,
+            Precedence::BitAnd => Precedence::Shift,
+            Precedence::Shift => Precedence::Term,
             Precedence::Term => Precedence::Factor,
             Precedence::Factor => Precedence::Unary,
-            Precedence::Unary => Precedence::Call,
+            Precedence::Unary => Precedence::Exponent,
+            Precedence::Exponent => Precedence::Call,
             Precedence::Call => Precedence::Primary,
             Precedence::Primary => Precedence::PrecedenceCount,
             _ => Precedence::None,
@@ -424,12 +2030,17 @@ fn get_parse_rule(ty: TokenType) -> ParseRule {
     match ty {
         TokenType::Print => ParseRule::none(),
         TokenType::FatArrow => ParseRule::none(),
-        TokenType::LeftBrace => ParseRule::none(),
+        TokenType::LeftBrace => ParseRule::with_prefix(Parser::block_expression, None),
         TokenType::RightBrace => ParseRule::none(),
-        TokenType::LeftParen => ParseRule::with_prefix(Parser::grouping, None),
+        TokenType::LeftParen => ParseRule {
+            prefix: Some(Parser::grouping),
+            infix: Some(Parser::call),
+            precedence: Precedence::Call,
+        },
         TokenType::RightParen => ParseRule::none(),
         TokenType::Comma => ParseRule::none(),
-        TokenType::Dot => ParseRule::none(),
+        TokenType::Dot => ParseRule::with_infix(Parser::dot, Some(Precedence::Call)),
+        TokenType::QuestionDot => ParseRule::with_infix(Parser::safe_dot, Some(Precedence::Call)),
         TokenType::Minus => ParseRule {
             prefix: Some(Parser::unary),
             infix: Some(Parser::binary),
@@ -439,6 +2050,22 @@ fn get_parse_rule(ty: TokenType) -> ParseRule {
         TokenType::Semicolon => ParseRule::none(),
         TokenType::ForwardSlash => ParseRule::with_infix(Parser::binary, Some(Precedence::Factor)),
         TokenType::Star => ParseRule::with_infix(Parser::binary, Some(Precedence::Factor)),
+        TokenType::StarStar => ParseRule::with_infix(Parser::binary, Some(Precedence::Exponent)),
+        TokenType::Percent => {
+            ParseRule::new(Some(Parser::map), Some(Parser::binary), Precedence::Factor)
+        }
+        TokenType::TildeSlash => ParseRule::with_infix(Parser::binary, Some(Precedence::Factor)),
+        TokenType::Tilde => ParseRule::with_prefix(Parser::unary, None),
+        TokenType::Ampersand => ParseRule::with_infix(Parser::binary, Some(Precedence::BitAnd)),
+        TokenType::Pipe => ParseRule::with_infix(Parser::binary, Some(Precedence::BitOr)),
+        TokenType::Caret => ParseRule::with_infix(Parser::binary, Some(Precedence::BitXor)),
+        TokenType::ShiftLeft => ParseRule::with_infix(Parser::binary, Some(Precedence::Shift)),
+        TokenType::ShiftRight => ParseRule::with_infix(Parser::binary, Some(Precedence::Shift)),
+        TokenType::PlusEqual => ParseRule::none(),
+        TokenType::MinusEqual => ParseRule::none(),
+        TokenType::StarEqual => ParseRule::none(),
+        TokenType::SlashEqual => ParseRule::none(),
+        TokenType::PercentEqual => ParseRule::none(),
         TokenType::Bang => ParseRule::with_prefix(Parser::unary, None),
         TokenType::Equal => ParseRule::none(),
         TokenType::BangEqual => ParseRule::with_infix(Parser::binary, Some(Precedence::Equality)),
@@ -450,20 +2077,28 @@ fn get_parse_rule(ty: TokenType) -> ParseRule {
         TokenType::Ident => ParseRule::with_prefix(Parser::variable, None),
         TokenType::String => ParseRule::with_prefix(Parser::string, None),
         TokenType::Number => ParseRule::with_prefix(Parser::number, None),
-        TokenType::And => ParseRule::none(),
+        TokenType::And => ParseRule::with_infix(Parser::and, Some(Precedence::And)),
         TokenType::Struct => ParseRule::none(),
         TokenType::Trait => ParseRule::none(),
         TokenType::Impl => ParseRule::none(),
+        TokenType::Mod => ParseRule::none(),
+        TokenType::Pub => ParseRule::none(),
+        TokenType::Use => ParseRule::none(),
+        TokenType::As => ParseRule::none(),
+        TokenType::Macro => ParseRule::none(),
+        TokenType::At => ParseRule::none(),
+        TokenType::Arrow => ParseRule::none(),
         TokenType::Else => ParseRule::none(),
         TokenType::False => ParseRule::with_prefix(Parser::literal, None),
         TokenType::True => ParseRule::with_prefix(Parser::literal, None),
         TokenType::Fn => ParseRule::none(),
-        TokenType::If => ParseRule::none(),
+        TokenType::If => ParseRule::with_prefix(Parser::if_expression, None),
         TokenType::Nil => ParseRule::with_prefix(Parser::literal, None),
-        TokenType::Or => ParseRule::none(),
+        TokenType::Or => ParseRule::with_infix(Parser::or, Some(Precedence::Or)),
         TokenType::Return => ParseRule::none(),
-        TokenType::Super => ParseRule::none(),
-        TokenType::ThisSelf => ParseRule::none(),
+        TokenType::Yield => ParseRule::none(),
+        TokenType::Super => ParseRule::with_prefix(Parser::super_, None),
+        TokenType::ThisSelf => ParseRule::with_prefix(Parser::this, None),
         TokenType::Let => ParseRule::none(),
         TokenType::Const => ParseRule::none(),
         TokenType::Eof => ParseRule::none(),
@@ -476,7 +2111,68 @@ fn get_parse_rule(ty: TokenType) -> ParseRule {
         TokenType::Comment => ParseRule::none(),
         TokenType::Unknown => ParseRule::none(),
         TokenType::Colon => ParseRule::none(),
-        TokenType::DoubleColon => ParseRule::none(),
+        TokenType::DoubleColon => ParseRule::with_infix(Parser::path, Some(Precedence::Call)),
+        TokenType::DotDot => ParseRule::with_infix(Parser::binary, Some(Precedence::Range)),
+        TokenType::DotDotEqual => ParseRule::with_infix(Parser::binary, Some(Precedence::Range)),
+        TokenType::DotDotDot => ParseRule::none(),
+        TokenType::In => ParseRule::with_infix(Parser::binary, Some(Precedence::Comparison)),
+        TokenType::LeftBracket => ParseRule {
+            prefix: Some(Parser::list),
+            infix: Some(Parser::index),
+            precedence: Precedence::Call,
+        },
+        TokenType::RightBracket => ParseRule::none(),
+        TokenType::Some => ParseRule::with_prefix(Parser::wrapper, None),
+        TokenType::Ok => ParseRule::with_prefix(Parser::wrapper, None),
+        TokenType::Err => ParseRule::with_prefix(Parser::wrapper, None),
+        TokenType::Question => ParseRule::with_infix(Parser::try_unwrap, Some(Precedence::Call)),
+        TokenType::Try => ParseRule::none(),
+        TokenType::Catch => ParseRule::none(),
+        TokenType::Finally => ParseRule::none(),
+        TokenType::Throw => ParseRule::none(),
+        TokenType::Atom => ParseRule::with_prefix(Parser::atom, None),
+        TokenType::Enum => ParseRule::none(),
+        TokenType::Where => ParseRule::none(),
+        TokenType::None => ParseRule::with_prefix(Parser::literal, None),
+        TokenType::QuestionQuestion => {
+            ParseRule::with_infix(Parser::coalesce, Some(Precedence::Coalesce))
+        }
+    }
+}
+
+/// Tokens starting a declaration or statement rather than an expression.
+fn starts_statement(ty: TokenType) -> bool {
+    matches!(
+        ty,
+        TokenType::Let
+            | TokenType::Enum
+            | TokenType::Fn
+            | TokenType::Struct
+            | TokenType::Trait
+            | TokenType::Impl
+            | TokenType::Mod
+            | TokenType::Pub
+            | TokenType::Use
+            | TokenType::At
+            | TokenType::Print
+            | TokenType::For
+            | TokenType::Try
+            | TokenType::Throw
+            | TokenType::Yield
+            | TokenType::Return
+    )
+}
+
+/// Maps a compound assignment operator (+=, -=, ...) to the opcode that combines the target with
+/// the right hand side.
+fn compound_assignment_op(ty: TokenType) -> Option<OpcodeType> {
+    match ty {
+        TokenType::PlusEqual => Some(OpcodeType::Add),
+        TokenType::MinusEqual => Some(OpcodeType::Subtract),
+        TokenType::StarEqual => Some(OpcodeType::Mult),
+        TokenType::SlashEqual => Some(OpcodeType::Div),
+        TokenType::PercentEqual => Some(OpcodeType::Modulo),
+        _ => None,
     }
 }
 
@@ -484,7 +2180,10 @@ fn get_parse_rule(ty: TokenType) -> ParseRule {
 pub struct Chunk {
     instructions: Vec<Opcode>,
     constants: Vec<Value>,
+    /// Source line of each instruction, parallel to `instructions`
     lines: Vec<u64>,
+    /// Source line recorded for instructions added from now on
+    line: u64,
 }
 
 impl Chunk {
@@ -493,6 +2192,7 @@ impl Chunk {
             instructions: Vec::with_capacity(8),
             constants: Vec::with_capacity(8),
             lines: Vec::with_capacity(8),
+            line: 0,
         }
     }
 
@@ -514,12 +2214,28 @@ impl Chunk {
     #[inline]
     pub fn add_opcode(&mut self, code: Opcode) {
         self.instructions.push(code);
+        self.lines.push(self.line);
+    }
+
+    #[inline]
+    pub fn set_line(&mut self, line: u64) {
+        self.line = line;
+    }
+
+    #[inline]
+    pub fn line_at(&self, index: usize) -> u64 {
+        self.lines[index]
+    }
+
+    #[inline]
+    pub fn set_opcode(&mut self, index: usize, code: Opcode) {
+        self.instructions[index] = code;
     }
 
     #[inline]
     pub fn add_opcodes(&mut self, a: Opcode, b: Opcode) {
-        self.instructions.push(a);
-        self.instructions.push(b);
+        self.add_opcode(a);
+        self.add_opcode(b);
     }
 
     pub fn add_constant(&mut self, v: Value) -> usize {
@@ -577,10 +2293,18 @@ struct Local {
     depth: usize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Generator,
+}
+
 #[derive(Debug, Clone)]
 pub struct Compiler {
     locals: Vec<Local>,
     scope_depth: usize,
+    kind: FunctionKind,
 }
 
 impl Compiler {
@@ -588,6 +2312,19 @@ impl Compiler {
         Self {
             locals: Vec::with_capacity(256),
             scope_depth: 0,
+            kind: FunctionKind::Script,
+        }
+    }
+
+    /// Compiler for a function clause, slot 0 holds the function being called.
+    fn function(name: &Token, kind: FunctionKind) -> Self {
+        Self {
+            locals: vec![Local {
+                name: name.clone(),
+                depth: 0,
+            }],
+            scope_depth: 1,
+            kind,
         }
     }
 
@@ -611,6 +2348,20 @@ impl Compiler {
         });
     }
 
+    /// Stands in for a value an expression is still holding on the stack, so the locals of a
+    /// block later in the same expression get the slots above it.
+    fn push_temporary(&mut self) {
+        self.push_local(Token {
+            ty: TokenType::Ident,
+            lexeme: "expression temporary".into(),
+            ..Token::empty()
+        });
+    }
+
+    fn pop_temporaries(&mut self, count: usize) {
+        self.locals.truncate(self.locals.len() - count);
+    }
+
     fn locals_top(&self) -> &Local {
         self.locals
             .last()
@@ -631,11 +2382,35 @@ impl Compiler {
     }
 
     pub fn compile(tokens: &[Token]) -> anyhow::Result<Chunk> {
+        Self::compile_module(tokens, Module::default())
+    }
+
+    /// Compiles the script at `path`, modules are also searched for in `search_paths`.
+    pub fn compile_file(path: &Path, search_paths: &[PathBuf]) -> anyhow::Result<Chunk> {
+        let source = std::fs::read_to_string(path)?;
+        let result = Lexer::scan_tokens(source.trim());
+        if !result.errors.is_empty() {
+            bail!("LEX ERROR(S): {:?}", result.errors)
+        }
+        Self::compile_module(&result.tokens, Module::script(path, search_paths))
+    }
+
+    fn compile_module(tokens: &[Token], script: Module) -> anyhow::Result<Chunk> {
+        let expanded = macros::expand(tokens)?;
+        let script = Module {
+            items: top_level_items(&expanded.tokens),
+            ..script
+        };
         let mut p = Parser {
-            tokens: tokens.to_vec(),
+            tokens: expanded.tokens,
+            expansions: expanded.expansions,
+            attributes: Attributes::default(),
+            errors: 0,
             i: 0,
             bytecode: Chunk::new(),
             compiler: Compiler::new(),
+            current_struct: None,
+            modules: vec![script],
         };
 
         // p.advance(1);
@@ -646,7 +2421,21 @@ impl Compiler {
         // TokenType::Eof,
         // &format!("Expected end of file token, got {:?}", p.current().ty),
         // )?;
+        warn_unused_imports(p.module());
+        // code after an error may be left with unpatched jumps, so it must not run
+        if p.errors > 0 {
+            bail!(
+                "Compiler::Parser => Compilation failed with {} error(s)",
+                p.errors
+            );
+        }
         p.bytecode.add_opcode(OpcodeType::Return.into());
         Ok(p.bytecode)
     }
 }
+
+fn warn_unused_imports(module: &Module) {
+    for warning in module.unused_imports() {
+        println!("Compiler::Parser => [WARNING]: {}", warning);
+    }
+}
@@ -0,0 +1,778 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::{FnClause, Method, RequiredMethod, Stmt},
+    attr::Attributes,
+    parse::Parser,
+    value::VariantFields,
+};
+
+/// Documentation for the scripts in a directory and the modules they declare, built from their
+/// parsed statements by `corrosion doc <dir>` and written as HTML and Markdown pages.
+///
+/// Each script and module gets a page listing its functions, structs, enums and traits with
+/// their signatures and attributes, only the `pub` ones for modules. Names quoted like `name` in
+/// a @doc, and struct, enum and trait names in a @spec, link to the item they refer to.
+pub struct Site {
+    /// Directory being documented, source files are shown relative to it
+    dir: PathBuf,
+    pages: Vec<Page>,
+    /// Global of every item documented on a module page to its page and index there
+    globals: HashMap<String, ItemRef>,
+}
+
+/// Page and index of an item on it.
+type ItemRef = (usize, usize);
+
+struct Page {
+    /// File name of the page without its extension
+    slug: String,
+    /// Path of the module, empty for a script
+    path: String,
+    /// Source file, relative to the documented directory when it is inside it
+    file: PathBuf,
+    items: Vec<Item>,
+    /// Paths of the modules it declares
+    modules: Vec<String>,
+    /// Names brought in by `use` to the globals they refer to
+    imports: HashMap<String, String>,
+    impls: Vec<(String, String, Vec<Item>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Function,
+    Struct,
+    Enum,
+    Trait,
+    Method,
+}
+
+struct Item {
+    kind: Kind,
+    name: String,
+    anchor: String,
+    /// Declaration without its body, a line per clause for functions
+    signature: Vec<String>,
+    attributes: Attributes,
+    parent: Option<String>,
+    methods: Vec<Item>,
+    /// Methods a trait requires implementations to provide
+    required: Vec<Item>,
+    /// `impl Trait for Struct` blocks of a struct, with the trait if it is documented
+    impls: Vec<Impl>,
+    /// Structs implementing a trait
+    implementors: Vec<ItemRef>,
+}
+
+struct Impl {
+    trait_name: String,
+    tr: Option<ItemRef>,
+    methods: Vec<Item>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Html,
+    Markdown,
+}
+
+impl Format {
+    pub const ALL: [Format; 2] = [Format::Html, Format::Markdown];
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Html => "html",
+            Format::Markdown => "md",
+        }
+    }
+}
+
+impl Kind {
+    fn keyword(self) -> &'static str {
+        match self {
+            Kind::Function | Kind::Method => "fn",
+            Kind::Struct => "struct",
+            Kind::Enum => "enum",
+            Kind::Trait => "trait",
+        }
+    }
+
+    fn section(self) -> &'static str {
+        match self {
+            Kind::Function => "Functions",
+            Kind::Struct => "Structs",
+            Kind::Enum => "Enums",
+            Kind::Trait => "Traits",
+            Kind::Method => "Methods",
+        }
+    }
+}
+
+impl Item {
+    fn new(kind: Kind, name: &str, signature: Vec<String>, attributes: &Attributes) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            anchor: format!("{}.{}", kind.keyword(), name),
+            signature,
+            attributes: attributes.clone(),
+            parent: None,
+            methods: Vec::new(),
+            required: Vec::new(),
+            impls: Vec::new(),
+            implementors: Vec::new(),
+        }
+    }
+
+    fn function(kind: Kind, name: &str, clauses: &[FnClause], attributes: &Attributes) -> Self {
+        let signature = clauses
+            .iter()
+            .map(|clause| {
+                let star = if clause.generator { "*" } else { "" };
+                format!("fn{} {}", star, clause.signature)
+            })
+            .collect();
+        Self::new(kind, name, signature, attributes)
+    }
+
+    /// Methods of `owner`, anchored under its name.
+    fn methods(owner: &str, methods: &[Method]) -> Vec<Item> {
+        methods
+            .iter()
+            .map(|method| {
+                let mut item = Self::function(
+                    Kind::Method,
+                    &method.name.lexeme,
+                    &method.clauses,
+                    &method.attributes,
+                );
+                item.anchor = format!("method.{}.{}", owner, method.name.lexeme);
+                item
+            })
+            .collect()
+    }
+
+    fn required(owner: &str, required: &[RequiredMethod]) -> Vec<Item> {
+        required
+            .iter()
+            .map(|method| {
+                let signature = vec![format!("fn {}", method.signature)];
+                let mut item = Self::new(
+                    Kind::Method,
+                    &method.name.lexeme,
+                    signature,
+                    &method.attributes,
+                );
+                item.anchor = format!("method.{}.{}", owner, method.name.lexeme);
+                item
+            })
+            .collect()
+    }
+}
+
+impl Site {
+    /// Parses every `.cor` file under `dir`, skipping `out`, and fails if any of them doesn't
+    /// parse. Files no other file declares with `mod` are documented as scripts, along with the
+    /// modules they declare.
+    pub fn build(dir: &Path, out: &Path, search_paths: &[PathBuf]) -> anyhow::Result<Site> {
+        let mut files = Vec::new();
+        find_sources(dir, &canonical(out), &mut files)?;
+        files.sort();
+        let mut parsed = Vec::new();
+        for file in files {
+            let stmts = Parser::parse_file(&file, search_paths)
+                .map_err(|err| anyhow::anyhow!("Unable to document {}: {}", file.display(), err))?;
+            parsed.push((file, stmts));
+        }
+        let mut module_files = HashSet::new();
+        for (_, stmts) in &parsed {
+            collect_module_files(stmts, &mut module_files);
+        }
+        let mut site = Site {
+            dir: canonical(dir),
+            pages: Vec::new(),
+            globals: HashMap::new(),
+        };
+        for (file, stmts) in &parsed {
+            if module_files.contains(&canonical(file)) {
+                continue;
+            }
+            let relative = file.strip_prefix(dir).unwrap_or(file).with_extension("");
+            let slug = relative
+                .iter()
+                .map(|part| part.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(".");
+            site.add_page(slug, String::new(), file.clone(), None, stmts);
+        }
+        site.link_impls();
+        Ok(site)
+    }
+
+    /// Writes an index and every page in each format to `out`, returning the files written.
+    pub fn write(&self, out: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(out)?;
+        let mut written = Vec::new();
+        for format in Format::ALL {
+            let mut pages = vec![("index".to_string(), self.render_index(format))];
+            for (i, page) in self.pages.iter().enumerate() {
+                pages.push((page.slug.clone(), self.render_page(format, i)));
+            }
+            for (slug, contents) in pages {
+                let file = out.join(format!("{}.{}", slug, format.extension()));
+                std::fs::write(&file, contents)?;
+                written.push(file);
+            }
+        }
+        Ok(written)
+    }
+
+    /// Adds the page of the script or module whose statements are `stmts`. `items` holds
+    /// whether each item of a module is `pub`, everything in a script is documented.
+    fn add_page(
+        &mut self,
+        slug: String,
+        path: String,
+        file: PathBuf,
+        items: Option<&HashMap<String, bool>>,
+        stmts: &[Stmt],
+    ) {
+        let public = |name: &str| items.is_none_or(|items| items.get(name) == Some(&true));
+        let file = canonical(&file);
+        let mut page = Page {
+            slug,
+            path,
+            file: file
+                .strip_prefix(&self.dir)
+                .map_or(file.clone(), Path::to_path_buf),
+            items: Vec::new(),
+            modules: Vec::new(),
+            imports: HashMap::new(),
+            impls: Vec::new(),
+        };
+        for stmt in stmts {
+            match stmt {
+                Stmt::Function {
+                    name,
+                    clauses,
+                    attributes,
+                } if public(&name.lexeme) => page.items.push(Item::function(
+                    Kind::Function,
+                    &name.lexeme,
+                    clauses,
+                    attributes,
+                )),
+                Stmt::Struct {
+                    name,
+                    parent,
+                    fields,
+                    field_types,
+                    methods,
+                    attributes,
+                } if public(&name.lexeme) => {
+                    let fields: Vec<String> = fields
+                        .iter()
+                        .zip(field_types)
+                        .map(|(field, ty)| match ty {
+                            Some(ty) => format!("{}: {}", field.lexeme, ty),
+                            None => field.lexeme.clone(),
+                        })
+                        .collect();
+                    let extends = match parent {
+                        Some(parent) => format!(" : {}", parent.lexeme),
+                        None => String::new(),
+                    };
+                    let signature = format!(
+                        "struct {}{} {{ {} }}",
+                        name.lexeme,
+                        extends,
+                        fields.join(", ")
+                    );
+                    let mut item =
+                        Item::new(Kind::Struct, &name.lexeme, vec![signature], attributes);
+                    item.parent = parent.as_ref().map(|p| p.lexeme.clone());
+                    item.methods = Item::methods(&name.lexeme, methods);
+                    page.items.push(item);
+                }
+                Stmt::Enum {
+                    name,
+                    variants,
+                    attributes,
+                } if public(&name.lexeme) => {
+                    let variants: Vec<String> = variants
+                        .iter()
+                        .map(|(variant, fields)| match fields {
+                            VariantFields::Unit => variant.lexeme.clone(),
+                            VariantFields::Tuple(fields) => {
+                                format!("{}({})", variant.lexeme, fields.join(", "))
+                            }
+                            VariantFields::Struct(fields) => {
+                                format!("{} {{ {} }}", variant.lexeme, fields.join(", "))
+                            }
+                        })
+                        .collect();
+                    let signature = format!("enum {} {{ {} }}", name.lexeme, variants.join(", "));
+                    page.items.push(Item::new(
+                        Kind::Enum,
+                        &name.lexeme,
+                        vec![signature],
+                        attributes,
+                    ));
+                }
+                Stmt::Trait {
+                    name,
+                    required,
+                    defaults,
+                    attributes,
+                } if public(&name.lexeme) => {
+                    let signature = format!("trait {}", name.lexeme);
+                    let mut item =
+                        Item::new(Kind::Trait, &name.lexeme, vec![signature], attributes);
+                    item.required = Item::required(&name.lexeme, required);
+                    item.methods = Item::methods(&name.lexeme, defaults);
+                    page.items.push(item);
+                }
+                Stmt::Impl {
+                    trait_name,
+                    ty,
+                    methods,
+                } => page.impls.push((
+                    trait_name.lexeme.clone(),
+                    ty.lexeme.clone(),
+                    Item::methods(&ty.lexeme, methods),
+                )),
+                Stmt::Use { imports, .. } => page.imports.extend(imports.iter().cloned()),
+                Stmt::Mod {
+                    name,
+                    path,
+                    file,
+                    items,
+                    body,
+                } if public(&name.lexeme) => {
+                    page.modules.push(path.clone());
+                    // a module declared by several scripts is documented once
+                    if !self.pages.iter().any(|p| p.path == *path) {
+                        let slug = path.replace("::", ".");
+                        self.add_page(slug, path.clone(), file.clone(), Some(items), body);
+                    }
+                }
+                _ => {}
+            }
+        }
+        while self.pages.iter().any(|p| p.slug == page.slug) || page.slug == "index" {
+            page.slug.push('_');
+        }
+        let index = self.pages.len();
+        if !page.path.is_empty() {
+            for (i, item) in page.items.iter().enumerate() {
+                self.globals
+                    .insert(format!("{}::{}", page.path, item.name), (index, i));
+            }
+        }
+        self.pages.push(page);
+    }
+
+    /// Attaches each impl block to the struct it is for, and the struct to the trait.
+    fn link_impls(&mut self) {
+        for page in 0..self.pages.len() {
+            for (trait_name, ty, methods) in std::mem::take(&mut self.pages[page].impls) {
+                let Some(owner) = self.resolve(page, &ty) else {
+                    continue;
+                };
+                let tr = self.resolve(page, &trait_name);
+                if let Some((tp, ti)) = tr {
+                    self.pages[tp].items[ti].implementors.push(owner);
+                }
+                self.pages[owner.0].items[owner.1].impls.push(Impl {
+                    trait_name,
+                    tr,
+                    methods,
+                });
+            }
+        }
+    }
+
+    /// Item `name` refers to on `page`, one of its own items, an import or a `module::item`
+    /// path relative to the page's module or the root.
+    fn resolve(&self, page: usize, name: &str) -> Option<ItemRef> {
+        let current = &self.pages[page];
+        if let Some(i) = current.items.iter().position(|item| item.name == name) {
+            return Some((page, i));
+        }
+        let (first, rest) = match name.split_once("::") {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (name, None),
+        };
+        let mut global = match current.imports.get(first) {
+            Some(global) => global.clone(),
+            None if current.path.is_empty() => first.to_string(),
+            None => format!("{}::{}", current.path, first),
+        };
+        if let Some(rest) = rest {
+            global = format!("{}::{}", global, rest);
+        }
+        self.globals
+            .get(&global)
+            .or_else(|| self.globals.get(name))
+            .copied()
+    }
+
+    /// URL of the item `name` refers to on `page`, `Type.method` for methods.
+    fn url(&self, format: Format, page: usize, name: &str) -> Option<String> {
+        let (ty, method) = match name.split_once('.') {
+            Some((ty, method)) => (ty, Some(method)),
+            None => (name, None),
+        };
+        let (target, i) = self.resolve(page, ty)?;
+        let item = &self.pages[target].items[i];
+        let anchor = match method {
+            None => item.anchor.clone(),
+            Some(method) => item
+                .methods
+                .iter()
+                .chain(item.impls.iter().flat_map(|i| &i.methods))
+                .find(|m| m.name == method)?
+                .anchor
+                .clone(),
+        };
+        Some(self.item_url(format, target, &anchor))
+    }
+
+    fn item_url(&self, format: Format, page: usize, anchor: &str) -> String {
+        format!(
+            "{}.{}#{}",
+            self.pages[page].slug,
+            format.extension(),
+            anchor
+        )
+    }
+
+    fn title(&self, page: usize) -> String {
+        let page = &self.pages[page];
+        if page.path.is_empty() {
+            format!("Script {}", page.slug)
+        } else {
+            format!("Module {}", page.path)
+        }
+    }
+
+    fn render_index(&self, format: Format) -> String {
+        let mut out = Writer::new(format);
+        out.heading(1, None, "Documentation");
+        for (heading, modules) in [("Scripts", false), ("Modules", true)] {
+            let pages: Vec<usize> = (0..self.pages.len())
+                .filter(|&i| self.pages[i].path.is_empty() != modules)
+                .collect();
+            if pages.is_empty() {
+                continue;
+            }
+            out.heading(2, None, heading);
+            let entries: Vec<String> = pages
+                .iter()
+                .map(|&i| {
+                    let page = &self.pages[i];
+                    let name = if modules { &page.path } else { &page.slug };
+                    let url = format!("{}.{}", page.slug, format.extension());
+                    out.link(name, Some(&url))
+                })
+                .collect();
+            out.list(&entries);
+        }
+        out.finish("Documentation")
+    }
+
+    fn render_page(&self, format: Format, index: usize) -> String {
+        let page = &self.pages[index];
+        let title = self.title(index);
+        let mut out = Writer::new(format);
+        out.text(&out.link("Index", Some(&format!("index.{}", format.extension()))));
+        out.heading(1, None, &title);
+        out.text(&format!(
+            "Source: {}",
+            out.escape(&page.file.display().to_string())
+        ));
+        if !page.modules.is_empty() {
+            out.heading(2, None, "Modules");
+            let modules: Vec<String> = page
+                .modules
+                .iter()
+                .map(|path| {
+                    let target = self.pages.iter().find(|p| p.path == *path);
+                    let url = target.map(|p| format!("{}.{}", p.slug, format.extension()));
+                    out.link(path, url.as_deref())
+                })
+                .collect();
+            out.list(&modules);
+        }
+        for kind in [Kind::Function, Kind::Struct, Kind::Enum, Kind::Trait] {
+            let items: Vec<&Item> = page.items.iter().filter(|i| i.kind == kind).collect();
+            if items.is_empty() {
+                continue;
+            }
+            out.heading(2, None, kind.section());
+            for item in items {
+                self.render_item(&mut out, index, item, 3);
+            }
+        }
+        out.finish(&title)
+    }
+
+    fn render_item(&self, out: &mut Writer, page: usize, item: &Item, level: usize) {
+        let title = format!("{} {}", item.kind.keyword(), item.name);
+        out.heading(level, Some(&item.anchor), &title);
+        out.code(&item.signature.join("\n"), None);
+        if let Some(spec) = &item.attributes.spec {
+            out.code(&format!("@spec {}", spec), Some((self, page)));
+        }
+        if let Some(doc) = &item.attributes.doc {
+            self.render_doc(out, page, doc);
+        }
+        if let Some(parent) = &item.parent {
+            let url = self.url(out.format, page, parent);
+            out.text(&format!("Extends {}", out.link(parent, url.as_deref())));
+        }
+        if !item.required.is_empty() {
+            let required: Vec<String> = item
+                .required
+                .iter()
+                .map(|r| {
+                    out.link_code(
+                        &out.inline(&r.name),
+                        &self.item_url(out.format, page, &r.anchor),
+                    )
+                })
+                .collect();
+            out.text(&format!("Required methods: {}", required.join(", ")));
+        }
+        if !item.implementors.is_empty() {
+            let implementors: Vec<String> = item
+                .implementors
+                .iter()
+                .map(|&(p, i)| {
+                    let implementor = &self.pages[p].items[i];
+                    let url = self.item_url(out.format, p, &implementor.anchor);
+                    out.link(&implementor.name, Some(&url))
+                })
+                .collect();
+            out.text(&format!("Implemented by {}", implementors.join(", ")));
+        }
+        for method in item.required.iter().chain(&item.methods) {
+            self.render_item(out, page, method, level + 1);
+        }
+        for imp in &item.impls {
+            let url = imp
+                .tr
+                .map(|(p, i)| self.item_url(out.format, p, &self.pages[p].items[i].anchor));
+            let heading = format!("impl {} for {}", imp.trait_name, item.name);
+            out.heading(level + 1, None, &heading);
+            out.text(&format!(
+                "Implements {}",
+                out.link(&imp.trait_name, url.as_deref())
+            ));
+            for method in &imp.methods {
+                self.render_item(out, page, method, level + 2);
+            }
+        }
+    }
+
+    /// Paragraphs of a @doc, with names quoted like `name` linked to the item they refer to.
+    fn render_doc(&self, out: &mut Writer, page: usize, doc: &str) {
+        for paragraph in doc.split("\n\n") {
+            let mut text = String::new();
+            for (i, part) in paragraph.trim().split('`').enumerate() {
+                if i % 2 == 0 {
+                    text.push_str(&out.escape(part));
+                    continue;
+                }
+                let code = out.inline(part);
+                match self.url(out.format, page, part) {
+                    Some(url) => text.push_str(&out.link_code(&code, &url)),
+                    None => text.push_str(&code),
+                }
+            }
+            out.text(&text);
+        }
+    }
+}
+
+/// Builds a page in either format.
+struct Writer {
+    format: Format,
+    body: String,
+}
+
+impl Writer {
+    fn new(format: Format) -> Self {
+        Self {
+            format,
+            body: String::new(),
+        }
+    }
+
+    fn escape(&self, text: &str) -> String {
+        match self.format {
+            Format::Html => text
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;"),
+            Format::Markdown => text.to_string(),
+        }
+    }
+
+    fn heading(&mut self, level: usize, anchor: Option<&str>, title: &str) {
+        let title = self.escape(title);
+        match (self.format, anchor) {
+            (Format::Html, Some(anchor)) => self.body.push_str(&format!(
+                "<h{0} id=\"{1}\">{2}</h{0}>\n",
+                level, anchor, title
+            )),
+            (Format::Html, None) => self
+                .body
+                .push_str(&format!("<h{0}>{1}</h{0}>\n", level, title)),
+            (Format::Markdown, anchor) => {
+                let anchor = anchor
+                    .map(|a| format!("<a id=\"{}\"></a>\n\n", a))
+                    .unwrap_or_default();
+                self.body
+                    .push_str(&format!("{}{} {}\n\n", anchor, "#".repeat(level), title));
+            }
+        }
+    }
+
+    /// Paragraph of already escaped text.
+    fn text(&mut self, text: &str) {
+        match self.format {
+            Format::Html => self.body.push_str(&format!("<p>{}</p>\n", text)),
+            Format::Markdown => self.body.push_str(&format!("{}\n\n", text)),
+        }
+    }
+
+    /// Block of code, with struct, enum and trait names linked in HTML when `links` gives the
+    /// page to resolve them on.
+    fn code(&mut self, code: &str, links: Option<(&Site, usize)>) {
+        match self.format {
+            Format::Html => {
+                let mut html = String::new();
+                let mut rest = code;
+                while let Some(start) = rest.find(|c: char| c.is_alphabetic() || c == '_') {
+                    html.push_str(&self.escape(&rest[..start]));
+                    rest = &rest[start..];
+                    let end = rest
+                        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+                        .unwrap_or(rest.len());
+                    let name = &rest[..end];
+                    let url = links.and_then(|(site, page)| {
+                        let (p, i) = site.resolve(page, name)?;
+                        let item = &site.pages[p].items[i];
+                        (item.kind != Kind::Function)
+                            .then(|| site.item_url(self.format, p, &item.anchor))
+                    });
+                    match url {
+                        Some(url) => html.push_str(&format!("<a href=\"{}\">{}</a>", url, name)),
+                        None => html.push_str(&self.escape(name)),
+                    }
+                    rest = &rest[end..];
+                }
+                html.push_str(&self.escape(rest));
+                self.body
+                    .push_str(&format!("<pre><code>{}</code></pre>\n", html));
+            }
+            Format::Markdown => self.body.push_str(&format!("```\n{}\n```\n\n", code)),
+        }
+    }
+
+    fn inline(&self, code: &str) -> String {
+        match self.format {
+            Format::Html => format!("<code>{}</code>", self.escape(code)),
+            Format::Markdown => format!("`{}`", code),
+        }
+    }
+
+    /// Link with `text` to `url`, or just the text when there is nothing to link to.
+    fn link(&self, text: &str, url: Option<&str>) -> String {
+        match url {
+            Some(url) => self.link_code(&self.escape(text), url),
+            None => self.escape(text),
+        }
+    }
+
+    /// Link with already escaped or formatted `text` to `url`.
+    fn link_code(&self, text: &str, url: &str) -> String {
+        match self.format {
+            Format::Html => format!("<a href=\"{}\">{}</a>", url, text),
+            Format::Markdown => format!("[{}]({})", text, url),
+        }
+    }
+
+    fn list(&mut self, entries: &[String]) {
+        match self.format {
+            Format::Html => {
+                self.body.push_str("<ul>\n");
+                for entry in entries {
+                    self.body.push_str(&format!("<li>{}</li>\n", entry));
+                }
+                self.body.push_str("</ul>\n");
+            }
+            Format::Markdown => {
+                for entry in entries {
+                    self.body.push_str(&format!("- {}\n", entry));
+                }
+                self.body.push('\n');
+            }
+        }
+    }
+
+    fn finish(self, title: &str) -> String {
+        match self.format {
+            Format::Html => format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+                 <style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+                self.escape(title),
+                STYLE,
+                self.body
+            ),
+            Format::Markdown => self.body,
+        }
+    }
+}
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 60em; margin: auto; } \
+    pre { background: #f4f4f4; padding: 0.5em; } h3, h4, h5 { margin-bottom: 0.2em; }";
+
+fn canonical(file: &Path) -> PathBuf {
+    std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+}
+
+/// `.cor` files under `dir`, leaving out hidden directories and `out`.
+fn find_sources(dir: &Path, out: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| anyhow::anyhow!("Unable to read directory {}: {}", dir.display(), err))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if !hidden && canonical(&path) != out {
+                find_sources(&path, out, files)?;
+            }
+        } else if path.extension().is_some_and(|ext| ext == "cor") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn collect_module_files(stmts: &[Stmt], files: &mut HashSet<PathBuf>) {
+    for stmt in stmts {
+        if let Stmt::Mod { file, body, .. } = stmt {
+            files.insert(file.clone());
+            collect_module_files(body, files);
+        }
+    }
+}
@@ -61,6 +61,33 @@ impl Env {
         self.scope_stack.push(scope);
     }
 
+    /// Removes every scope but the global one so a function body can't see its caller's
+    /// bindings. The returned scopes are put back with `leave_function`.
+    pub fn enter_function(&mut self) -> Vec<Scope> {
+        self.scope_stack.split_off(1)
+    }
+
+    pub fn leave_function(&mut self, scopes: Vec<Scope>) {
+        self.scope_stack.truncate(1);
+        self.scope_stack.extend(scopes);
+    }
+
+    /// Whether no scope but the global one is open, as at the top level of a script or module.
+    pub fn at_top_level(&self) -> bool {
+        self.scope_stack.len() == 1
+    }
+
+    /// Whether `name` is bound in a scope other than the global one.
+    pub fn is_local(&self, name: &str) -> bool {
+        self.scope_stack[1..]
+            .iter()
+            .any(|scope| scope.values.contains_key(name))
+    }
+
+    pub fn is_global(&self, name: &str) -> bool {
+        self.top().values.contains_key(name)
+    }
+
     /// Defines variable at bottom level (inner-most) scope
     pub fn define(&mut self, name: &str, value: &Value) {
         self.bottom_mut()
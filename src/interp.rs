@@ -1,37 +1,172 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    sync::{
+        mpsc::{self, Receiver, SendError, SyncSender},
+        Arc,
+    },
+    thread,
+};
 
 use crate::{
-    ast::{self, AstWalkError, AstWalker, Expr, Stmt},
+    ast::{self, AstWalkError, AstWalker, ComprehensionClause, Expr, Stmt},
+    attr::Attributes,
     env::{Env, Scope},
-    value::{Object, Token, TokenType, Value},
+    module::Module,
+    native::{concat_operands, displayed, Operator, NATIVES},
+    value::{
+        implement, BoundMethod, Caller, Clause, EnumType, Function, FunctionBody, Generator,
+        GeneratorState, Iter, Object, Output, Param, Range, ScriptError, StructType, Thrown, Token,
+        TokenType, Trait, Value, Variant,
+    },
 };
 use anyhow::*;
+use thiserror::Error;
 
 #[derive(Debug)]
 pub struct Interpreter {
     env: Env,
+    /// Number of function calls being run, `?` only returns early from inside one
+    function_depth: usize,
+    /// Set on the Interpreter running a generator body on its own thread
+    yielder: Option<Yielder>,
+    /// Path of the module whose code is running, empty for the script
+    module: String,
+    /// Names each module has imported with `use` and the globals they refer to
+    imports: HashMap<String, HashMap<String, String>>,
+    output: Output,
+}
+
+/// Values a generator thread yields, along with the globals handed back to the code resuming it.
+type Yielded = (Scope, anyhow::Result<Option<Value>>);
+
+/// Generator thread's ends of its channels. The globals are handed over with every resume and
+/// yield so whichever side is running owns them.
+#[derive(Debug)]
+struct Yielder {
+    resume: Receiver<Scope>,
+    yielded: SyncSender<Yielded>,
+}
+
+/// Body of a `fn*` call running on its own thread, blocked until it is resumed.
+#[derive(Debug)]
+pub struct GeneratorThread {
+    resume: SyncSender<Scope>,
+    yielded: Receiver<Yielded>,
+}
+
+/// Unwinds a generator thread whose Generator was dropped, without running any more of its body.
+#[derive(Error, Debug)]
+#[error("Generator was dropped before it finished")]
+struct Abandoned;
+
+/// Runs script functions for natives and iterators, errors point at the call or loop running them.
+struct NativeCaller<'a> {
+    interpreter: &'a mut Interpreter,
+    token: &'a Token,
+}
+
+impl Caller for NativeCaller<'_> {
+    fn call(&mut self, callee: &Value, args: &[Value]) -> anyhow::Result<Value> {
+        self.interpreter
+            .call_value(callee, args, &[], self.token)
+            .map_err(|e| anyhow!(CallbackError(e)))
+    }
+
+    fn resume(&mut self, generator: &Arc<Generator>) -> anyhow::Result<Option<Value>> {
+        self.interpreter
+            .resume(generator)
+            .map_err(|e| anyhow!(CallbackError(e)))
+    }
+}
+
+/// Error of a function a native called, passed on as is instead of as the native's error.
+#[derive(Error, Debug)]
+#[error(transparent)]
+struct CallbackError(Error);
+
+/// Points an error of a native or iterator at `token`.
+fn native_error(e: Error, token: &Token) -> Error {
+    match e.downcast::<CallbackError>() {
+        Result::Ok(CallbackError(e)) => e,
+        Err(e) => anyhow!(AstWalkError::RuntimeError {
+            token: token.clone(),
+            message: e.to_string(),
+        }),
+    }
+}
+
+/// Points an error building a map at the '{' of its literal or comprehension.
+fn map_error(e: Error, brace: &Token) -> Error {
+    anyhow!(AstWalkError::RuntimeError {
+        token: brace.clone(),
+        message: e.to_string(),
+    })
 }
 
+/// Unwinds a function body to its call with the returned value.
+#[derive(Error, Debug)]
+#[error("Cannot return from top-level code")]
+struct Return(Value);
+
 impl Default for Interpreter {
     fn default() -> Self {
-        Self { env: Env::new() }
+        let mut env = Env::new();
+        for native in NATIVES {
+            env.define(native.name, &Value::Obj(Object::Native(*native)));
+        }
+        for (name, tr) in Operator::globals() {
+            env.define(name, &tr);
+        }
+        Self {
+            env,
+            function_depth: 0,
+            yielder: None,
+            module: String::new(),
+            imports: HashMap::new(),
+            output: Output::default(),
+        }
     }
 }
 
 impl Interpreter {
+    /// Deepest function call nesting before the script is stopped, every call recurses through
+    /// the Rust stack so this is well below what the VM allows.
+    const CALL_DEPTH_MAX: usize = 128;
+    /// Stack of the threads statements and generator bodies run on, a call nested in loops and
+    /// blocks takes over 100KB of it in debug builds so this leaves room for `CALL_DEPTH_MAX`.
+    const STACK_SIZE: usize = 64 * 1024 * 1024;
+
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Makes `print` write to `output` instead of stdout.
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    /// Runs `stmt` on a thread with a `STACK_SIZE` stack, so hitting `CALL_DEPTH_MAX` raises an
+    /// error instead of overflowing whichever stack the caller is on.
     pub fn execute(&mut self, stmt: &Stmt) -> anyhow::Result<()> {
-        stmt.walk(self)
+        thread::scope(|scope| {
+            let walk = thread::Builder::new()
+                .stack_size(Self::STACK_SIZE)
+                .spawn_scoped(scope, || stmt.walk(self))?;
+            walk.join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
     }
 
     pub fn execute_block(&mut self, statements: &[Stmt]) -> anyhow::Result<()> {
         self.env.push_scope(Scope::default());
         for stmt in statements {
-            if let Err(e) = self.execute(stmt) {
+            if let Err(e) = stmt.walk(self) {
                 self.env.pop_scope();
-                bail!("{}", e);
+                // returned as is so a thrown error can still be downcast by a catch block
+                return Err(e);
             };
         }
         self.env.pop_scope();
@@ -41,18 +176,530 @@ impl Interpreter {
     pub fn eval(&mut self, expr: &ast::Expr) -> anyhow::Result<Value> {
         expr.walk(self)
     }
+
+    /// Calls the method overloading the binary `operator` when the operand it dispatches on is a
+    /// struct instance implementing its trait, None otherwise. Mirrors how the VM compiles the
+    /// comparisons: `a > b` and `a <= b` call `b.lt(a)`, and `!=`, `<=`, `>=` negate the result.
+    fn overloaded_operator(
+        &mut self,
+        left: &Value,
+        operator: &Token,
+        right: &Value,
+    ) -> anyhow::Result<Option<Value>> {
+        let (op, args, negate) = match operator.ty {
+            TokenType::Plus => (Operator::Add, [left, right], false),
+            TokenType::EqualEqual => (Operator::Eq, [left, right], false),
+            TokenType::BangEqual => (Operator::Eq, [left, right], true),
+            TokenType::Lt => (Operator::Ord, [left, right], false),
+            TokenType::Ge => (Operator::Ord, [left, right], true),
+            TokenType::Gt => (Operator::Ord, [right, left], false),
+            TokenType::Le => (Operator::Ord, [right, left], true),
+            _ => return Ok(None),
+        };
+        let Some(method) = op.method_for(args[0]) else {
+            return Ok(None);
+        };
+        let result = self.call_function(&method, &args.map(Value::clone), &[], operator)?;
+        let result = op
+            .check_result(result)
+            .map_err(|e| AstWalkError::RuntimeError {
+                token: operator.clone(),
+                message: e.to_string(),
+            })?;
+        Ok(Some(if negate {
+            Value::Boolean(result.is_falsey())
+        } else {
+            result
+        }))
+    }
+
+    /// `object[index]`, through the struct's implementation of `Index` if it has one.
+    fn index(&mut self, object: Value, index: Value, bracket: &Token) -> anyhow::Result<Value> {
+        if let Some(method) = Operator::Index.method_for(&object) {
+            return self.call_function(&method, &[object, index], &[], bracket);
+        }
+        let value = object
+            .index(&index)
+            .map_err(|e| AstWalkError::RuntimeError {
+                token: bracket.clone(),
+                message: e.to_string(),
+            })?;
+        Ok(value)
+    }
+
+    /// Applies a binary operator to its evaluated operands.
+    fn binary(
+        &mut self,
+        lvalue: &Value,
+        operator: &Token,
+        rvalue: &Value,
+    ) -> anyhow::Result<Value> {
+        if let Some(value) = self.overloaded_operator(lvalue, operator, rvalue)? {
+            return Ok(value);
+        }
+        match operator.ty {
+            TokenType::Minus => eval_sub(lvalue, operator, rvalue),
+            TokenType::Plus => {
+                let mut caller = NativeCaller {
+                    interpreter: self,
+                    token: operator,
+                };
+                let (lvalue, rvalue) = concat_operands(&mut caller, lvalue.clone(), rvalue.clone())
+                    .map_err(|e| native_error(e, operator))?;
+                eval_plus(&lvalue, operator, &rvalue)
+            }
+            TokenType::ForwardSlash => eval_div(lvalue, operator, rvalue),
+            TokenType::Star => eval_mul(lvalue, operator, rvalue),
+            TokenType::Percent => eval_number_op(lvalue, operator, rvalue, |a, b| a % b),
+            TokenType::StarStar => eval_number_op(lvalue, operator, rvalue, f64::powf),
+            TokenType::TildeSlash => {
+                eval_integer_op(lvalue, operator, rvalue, |a, b| match a.checked_div(b) {
+                    Some(n) => Ok(n),
+                    None if b == 0 => bail!("Integer division by zero."),
+                    None => bail!("Integer division overflow: {} ~/ {}", a, b),
+                })
+            }
+            TokenType::Ampersand => eval_integer_op(lvalue, operator, rvalue, |a, b| Ok(a & b)),
+            TokenType::Pipe => eval_integer_op(lvalue, operator, rvalue, |a, b| Ok(a | b)),
+            TokenType::Caret => eval_integer_op(lvalue, operator, rvalue, |a, b| Ok(a ^ b)),
+            TokenType::ShiftLeft => eval_integer_op(lvalue, operator, rvalue, |a, b| {
+                eval_shift(a, b, i64::checked_shl)
+            }),
+            TokenType::ShiftRight => eval_integer_op(lvalue, operator, rvalue, |a, b| {
+                eval_shift(a, b, i64::checked_shr)
+            }),
+            TokenType::Lt => eval_lt(lvalue, operator, rvalue),
+            TokenType::Le => eval_le(lvalue, operator, rvalue),
+            TokenType::Gt => eval_gt(lvalue, operator, rvalue),
+            TokenType::Ge => eval_ge(lvalue, operator, rvalue),
+            TokenType::DotDot | TokenType::DotDotEqual => {
+                let inclusive = operator.ty == TokenType::DotDotEqual;
+                let range = Range::new(lvalue, rvalue, inclusive).map_err(|e| {
+                    AstWalkError::RuntimeError {
+                        token: operator.clone(),
+                        message: e.to_string(),
+                    }
+                })?;
+                Ok(Value::Obj(Object::Range(range)))
+            }
+            TokenType::In => {
+                let found = rvalue
+                    .contains(lvalue)
+                    .map_err(|e| AstWalkError::RuntimeError {
+                        token: operator.clone(),
+                        message: e.to_string(),
+                    })?;
+                Ok(Value::Boolean(found))
+            }
+            TokenType::EqualEqual => Ok(Value::Boolean(lvalue == rvalue)),
+            TokenType::BangEqual => Ok(Value::Boolean(lvalue != rvalue)),
+            _ => bail!(
+                "{}",
+                AstWalkError::RuntimeError {
+                    token: operator.clone(),
+                    message: "Unknown binary operator found".into()
+                }
+            ),
+        }
+    }
+
+    /// Calls any callable value, keyword arguments are at the end of `args`.
+    fn call_value(
+        &mut self,
+        callee: &Value,
+        args: &[Value],
+        keywords: &[String],
+        paren: &Token,
+    ) -> anyhow::Result<Value> {
+        match callee {
+            Value::Obj(Object::Function(function)) => {
+                return self.call_function(function, args, keywords, paren);
+            }
+            Value::Obj(Object::BoundMethod(method)) => {
+                return self.call_function(&method.method, &method.args(args), keywords, paren);
+            }
+            Value::Obj(Object::Struct(ty)) => {
+                return StructType::construct(ty, args, keywords).map_err(|e| {
+                    anyhow!(AstWalkError::RuntimeError {
+                        token: paren.clone(),
+                        message: e.to_string(),
+                    })
+                });
+            }
+            Value::Obj(Object::VariantConstructor(ty, variant)) => {
+                return EnumType::construct(ty, *variant, args, keywords).map_err(|e| {
+                    anyhow!(AstWalkError::RuntimeError {
+                        token: paren.clone(),
+                        message: e.to_string(),
+                    })
+                });
+            }
+            _ => {}
+        }
+        if !keywords.is_empty() {
+            bail!(
+                "{}",
+                AstWalkError::RuntimeError {
+                    token: paren.clone(),
+                    message: format!("{} does not accept keyword arguments", callee),
+                }
+            )
+        }
+        let mut caller = NativeCaller {
+            interpreter: self,
+            token: paren,
+        };
+        callee
+            .call(&mut caller, args)
+            .map_err(|e| native_error(e, paren))
+    }
+
+    /// Calls `function`, keyword arguments are at the end of `args`. A generator function
+    /// returns a Generator running its body instead.
+    fn call_function(
+        &mut self,
+        function: &Arc<Function>,
+        args: &[Value],
+        keywords: &[String],
+        paren: &Token,
+    ) -> anyhow::Result<Value> {
+        if function.generator {
+            self.spawn_generator(function, args, keywords, paren)
+        } else {
+            self.run_function(function, args, keywords, paren)
+        }
+    }
+
+    /// Starts the body of a `fn*` call on its own thread, where it waits to be resumed. Only the
+    /// parameters are checked here, guards run on the first resume.
+    fn spawn_generator(
+        &self,
+        function: &Arc<Function>,
+        args: &[Value],
+        keywords: &[String],
+        paren: &Token,
+    ) -> anyhow::Result<Value> {
+        if function.find_clause(0, args, keywords).is_none() {
+            bail!(
+                "{}",
+                AstWalkError::RuntimeError {
+                    token: paren.clone(),
+                    message: function.no_match(args, keywords).to_string(),
+                }
+            )
+        }
+        let (resume, resumed) = mpsc::sync_channel(0);
+        let (yield_to, yielded) = mpsc::sync_channel(0);
+        let mut body = Interpreter {
+            env: Env::new(),
+            // counted from the spawning call so recursive generators are limited like functions
+            function_depth: self.function_depth,
+            module: String::new(),
+            imports: self.imports.clone(),
+            output: self.output.clone(),
+            yielder: Some(Yielder {
+                resume: resumed,
+                yielded: yield_to,
+            }),
+        };
+        let (function, args, keywords, paren) = (
+            function.clone(),
+            args.to_vec(),
+            keywords.to_vec(),
+            paren.clone(),
+        );
+        let name = function.name.clone();
+        thread::Builder::new()
+            .name(name.clone())
+            .stack_size(Self::STACK_SIZE)
+            .spawn(move || body.run_generator(&function, &args, &keywords, &paren))?;
+        let state = GeneratorState::Thread(GeneratorThread { resume, yielded });
+        Ok(Value::Obj(Object::Generator(Arc::new(Generator::new(
+            &name, state,
+        )))))
+    }
+
+    /// Body of a generator thread, runs the function once first resumed and hands the globals
+    /// back along with how it ended.
+    fn run_generator(
+        &mut self,
+        function: &Arc<Function>,
+        args: &[Value],
+        keywords: &[String],
+        paren: &Token,
+    ) {
+        if self.wait_for_resume().is_err() {
+            return;
+        }
+        let result = self
+            .run_function(function, args, keywords, paren)
+            .map(|_| None);
+        if let Some(yielder) = &self.yielder {
+            // nothing is waiting for an abandoned generator
+            let _ = yielder
+                .yielded
+                .send((std::mem::take(self.env.top_mut()), result));
+        }
+    }
+
+    /// Blocks the generator thread until it is resumed, taking over the globals.
+    fn wait_for_resume(&mut self) -> anyhow::Result<()> {
+        let Some(yielder) = &self.yielder else {
+            bail!("Cannot use 'yield' outside of a generator function");
+        };
+        match yielder.resume.recv() {
+            Result::Ok(globals) => {
+                *self.env.top_mut() = globals;
+                Ok(())
+            }
+            Err(_) => Err(Abandoned.into()),
+        }
+    }
+
+    /// Hands `value` and the globals to the code resuming the generator and waits to be resumed
+    /// again.
+    fn yield_value(&mut self, value: Value) -> anyhow::Result<()> {
+        let Some(yielder) = &self.yielder else {
+            bail!("Cannot use 'yield' outside of a generator function");
+        };
+        let globals = std::mem::take(self.env.top_mut());
+        if yielder.yielded.send((globals, Ok(Some(value)))).is_err() {
+            return Err(Abandoned.into());
+        }
+        self.wait_for_resume()
+    }
+
+    /// Runs `generator`'s thread up to its next yield, it has the globals until then.
+    fn resume(&mut self, generator: &Arc<Generator>) -> anyhow::Result<Option<Value>> {
+        let thread = match generator.take()? {
+            Some(GeneratorState::Thread(thread)) => thread,
+            Some(_) => {
+                generator.finish();
+                bail!(
+                    "Generator '{}' was not created by the Interpreter",
+                    generator.name
+                )
+            }
+            None => return Ok(None),
+        };
+        let globals = std::mem::take(self.env.top_mut());
+        if let Err(SendError(globals)) = thread.resume.send(globals) {
+            *self.env.top_mut() = globals;
+            generator.finish();
+            bail!("Generator '{}' stopped unexpectedly", generator.name);
+        }
+        let Result::Ok((globals, result)) = thread.yielded.recv() else {
+            generator.finish();
+            bail!("Generator '{}' stopped unexpectedly", generator.name);
+        };
+        *self.env.top_mut() = globals;
+        match result {
+            Result::Ok(Some(value)) => {
+                generator.suspend(GeneratorState::Thread(thread));
+                Ok(Some(value))
+            }
+            result => {
+                generator.finish();
+                result
+            }
+        }
+    }
+
+    /// Runs the first clause of `function` whose parameters and guard accept `args`, keyword
+    /// arguments are at the end of `args`.
+    fn run_function(
+        &mut self,
+        function: &Arc<Function>,
+        args: &[Value],
+        keywords: &[String],
+        paren: &Token,
+    ) -> anyhow::Result<Value> {
+        if self.function_depth >= Self::CALL_DEPTH_MAX {
+            bail!(
+                "{}",
+                AstWalkError::RuntimeError {
+                    token: paren.clone(),
+                    message: format!("Stack overflow calling '{}'", function.name),
+                }
+            )
+        }
+        let mut start = 0;
+        while let Some((clause, bound)) = function.find_clause(start, args, keywords) {
+            let Clause { params, body, .. } = &function.clauses[clause];
+            let FunctionBody::Ast {
+                defaults,
+                guard,
+                body,
+            } = body
+            else {
+                bail!("Function '{}' was not parsed from source", function.name);
+            };
+            // the body runs in the module the function was declared in
+            let (module, name) = Module::split(&function.name);
+            let caller_module = std::mem::replace(&mut self.module, module.to_string());
+            let caller_scopes = self.env.enter_function();
+            self.env.push_scope(Scope::default());
+            self.env
+                .define(name, &Value::Obj(Object::Function(function.clone())));
+            self.function_depth += 1;
+            let result = self.run_clause(params, bound, defaults, guard.as_deref(), body);
+            self.function_depth -= 1;
+            self.env.leave_function(caller_scopes);
+            self.module = caller_module;
+
+            match result {
+                Result::Ok(Some(value)) => return Ok(value),
+                // guard failed, try the clauses after this one
+                Result::Ok(None) => start = clause + 1,
+                Err(err) => match err.downcast::<Return>() {
+                    Result::Ok(Return(value)) => return Ok(value),
+                    Err(err) => return Err(err),
+                },
+            }
+        }
+        bail!(
+            "{}",
+            AstWalkError::RuntimeError {
+                token: paren.clone(),
+                message: function.no_match(args, keywords).to_string(),
+            }
+        )
+    }
+
+    /// Global of `name` when it is declared at the top level of a module, where its items are
+    /// globals named `module::item`.
+    fn global_name(&self, name: &str) -> String {
+        if self.env.at_top_level() && !self.module.is_empty() {
+            format!("{}::{}", self.module, name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// Name a function or type declared in the running module displays with.
+    fn qualified(&self, name: &str) -> String {
+        if self.module.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}::{}", self.module, name)
+        }
+    }
+
+    /// Names an item of, or imported into, the running module by its global, unless a local
+    /// shadows it.
+    fn resolve<'a>(&self, name: &'a Token) -> Cow<'a, Token> {
+        if self.env.is_local(&name.lexeme) {
+            return Cow::Borrowed(name);
+        }
+        let own = format!("{}::{}", self.module, name.lexeme);
+        let global = if !self.module.is_empty() && self.env.is_global(&own) {
+            Some(own)
+        } else {
+            self.imports
+                .get(&self.module)
+                .and_then(|imports| imports.get(&name.lexeme))
+                .cloned()
+        };
+        match global {
+            Some(global) => Cow::Owned(Token {
+                lexeme: global,
+                ..name.clone()
+            }),
+            None => Cow::Borrowed(name),
+        }
+    }
+
+    /// Runs the comprehension clauses as nested loops, pushing the key, if there is one, and the
+    /// element onto `values` each time the innermost one is reached.
+    fn comprehension(
+        &mut self,
+        key: Option<&Expr>,
+        element: &Expr,
+        clauses: &[ComprehensionClause],
+        values: &mut Vec<Value>,
+    ) -> anyhow::Result<()> {
+        let Some((clause, rest)) = clauses.split_first() else {
+            if let Some(key) = key {
+                values.push(self.eval(key)?);
+            }
+            values.push(self.eval(element)?);
+            return Ok(());
+        };
+        match clause {
+            ComprehensionClause::For { name, iterable } => {
+                let iter =
+                    Iter::from_value(&self.eval(iterable)?).map_err(|e| native_error(e, name))?;
+                loop {
+                    let mut caller = NativeCaller {
+                        interpreter: self,
+                        token: name,
+                    };
+                    let Some(value) = iter.next(&mut caller).map_err(|e| native_error(e, name))?
+                    else {
+                        break;
+                    };
+                    self.env.push_scope(Scope::default());
+                    self.env.define(&name.lexeme, &value);
+                    let result = self.comprehension(key, element, rest, values);
+                    self.env.pop_scope();
+                    result?;
+                }
+            }
+            ComprehensionClause::If(condition) => {
+                if !self.eval(condition)?.is_falsey() {
+                    self.comprehension(key, element, rest, values)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds the arguments, running the defaults of missing ones in parameter order. Returns
+    /// None without running the body if the guard rejects the arguments.
+    fn run_clause(
+        &mut self,
+        params: &[Param],
+        bound: Vec<Option<Value>>,
+        defaults: &[Option<Expr>],
+        guard: Option<&Expr>,
+        body: &Expr,
+    ) -> anyhow::Result<Option<Value>> {
+        for ((param, value), default) in params.iter().zip(bound).zip(defaults) {
+            let (Param::Name(name) | Param::Default(name) | Param::Rest(name) | Param::Some(name)) =
+                param
+            else {
+                continue;
+            };
+            let value = match (value, default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => self.eval(default)?,
+                (None, None) => Value::Nil,
+            };
+            self.env.define(name, &value);
+        }
+        if let Some(guard) = guard {
+            if self.eval(guard)?.is_falsey() {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.eval(body)?))
+    }
 }
 
 impl AstWalker<Stmt, ()> for Interpreter {
     fn visit(&mut self, stmt: &ast::Stmt) -> anyhow::Result<()> {
         match stmt {
-            Stmt::Block(block) => self.execute_block(block)?,
             Stmt::Expression(expr) => {
                 let _ = self.eval(expr)?;
             }
-            Stmt::Print(expr) => {
-                let value = self.eval(expr)?;
-                println!("{}", value);
+            Stmt::Print { keyword, value } => {
+                let value = self.eval(value)?;
+                let mut caller = NativeCaller {
+                    interpreter: self,
+                    token: keyword,
+                };
+                let value = displayed(&mut caller, &value).map_err(|e| native_error(e, keyword))?;
+                self.output.println(&value)?;
             }
             Stmt::Let { name, initializer } => {
                 let value = if let Some(init) = initializer {
@@ -60,7 +707,201 @@ impl AstWalker<Stmt, ()> for Interpreter {
                 } else {
                     Value::Nil
                 };
-                self.env.define(&name.lexeme, &value);
+                self.env.define(&self.global_name(&name.lexeme), &value);
+            }
+            Stmt::Enum {
+                name,
+                variants,
+                attributes,
+            } => {
+                let variants = variants
+                    .iter()
+                    .map(|(variant, fields)| Variant {
+                        name: variant.lexeme.clone(),
+                        fields: fields.clone(),
+                    })
+                    .collect();
+                let ty = EnumType {
+                    name: name.lexeme.clone(),
+                    variants,
+                    attributes: attributes.clone(),
+                };
+                self.env.define(
+                    &self.global_name(&name.lexeme),
+                    &Value::Obj(Object::Enum(Arc::new(ty))),
+                );
+            }
+            Stmt::Function {
+                name,
+                clauses,
+                attributes,
+            } => {
+                let function = make_function(self.qualified(&name.lexeme), clauses, attributes);
+                self.env.define(
+                    &self.global_name(&name.lexeme),
+                    &Value::Obj(Object::Function(Arc::new(function))),
+                );
+            }
+            Stmt::Struct {
+                name,
+                parent,
+                fields,
+                methods,
+                attributes,
+                ..
+            } => {
+                let mut ty = StructType::new(
+                    name.lexeme.clone(),
+                    fields.iter().map(|f| f.lexeme.clone()).collect(),
+                    make_methods(&self.qualified(&name.lexeme), methods),
+                );
+                ty.attributes = attributes.clone();
+                if let Some(parent) = parent {
+                    let linked = match self.env.get(&self.resolve(parent))? {
+                        Value::Obj(Object::Struct(parent)) => ty.inherit(parent),
+                        value => Err(format!(
+                            "Struct {} can only extend a struct, got: {}",
+                            name.lexeme,
+                            value.type_string()
+                        )),
+                    };
+                    match linked {
+                        Result::Ok(linked) => ty = linked,
+                        Err(message) => bail!(
+                            "{}",
+                            AstWalkError::RuntimeError {
+                                token: parent.clone(),
+                                message,
+                            }
+                        ),
+                    }
+                }
+                self.env.define(
+                    &self.global_name(&name.lexeme),
+                    &Value::Obj(Object::Struct(Arc::new(ty))),
+                );
+            }
+            Stmt::Trait {
+                name,
+                required,
+                defaults,
+                attributes,
+            } => {
+                let tr = Trait {
+                    name: name.lexeme.clone(),
+                    required: required.iter().map(|m| m.name.lexeme.clone()).collect(),
+                    defaults: make_methods(&self.qualified(&name.lexeme), defaults),
+                    attributes: attributes.clone(),
+                };
+                self.env.define(
+                    &self.global_name(&name.lexeme),
+                    &Value::Obj(Object::Trait(Arc::new(tr))),
+                );
+            }
+            Stmt::Impl {
+                trait_name,
+                ty,
+                methods,
+            } => {
+                let tr = self.env.get(&self.resolve(trait_name))?;
+                let struct_ty = self.env.get(&self.resolve(ty))?;
+                let methods = make_methods(&self.qualified(&ty.lexeme), methods);
+                if let Err(message) = implement(&tr, &struct_ty, methods) {
+                    bail!(
+                        "{}",
+                        AstWalkError::RuntimeError {
+                            token: trait_name.clone(),
+                            message,
+                        }
+                    )
+                }
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Nil,
+                };
+                return Err(Return(value).into());
+            }
+            Stmt::Mod { path, body, .. } => {
+                let enclosing = std::mem::replace(&mut self.module, path.clone());
+                let result = body.iter().try_for_each(|stmt| stmt.walk(self));
+                self.module = enclosing;
+                result?;
+            }
+            Stmt::Use { imports, .. } => {
+                self.imports
+                    .entry(self.module.clone())
+                    .or_default()
+                    .extend(imports.iter().cloned());
+            }
+            Stmt::Yield { value, .. } => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Nil,
+                };
+                self.yield_value(value)?;
+            }
+            Stmt::Throw { keyword, value } => {
+                let value = self.eval(value)?;
+                let error = ScriptError::from_thrown(value, keyword.line as u64);
+                return Err(Thrown(error).into());
+            }
+            Stmt::Try {
+                keyword,
+                body,
+                catch,
+                finally,
+            } => {
+                let mut result = self.execute_block(body);
+                if let Some((name, handler)) = catch {
+                    result = match result {
+                        // returning out of a try block isn't an error to catch
+                        Err(err) if !err.is::<Return>() && !err.is::<Abandoned>() => {
+                            // runtime errors don't carry a line past their message, so they are
+                            // reported on the line of the try statement that caught them
+                            let error = ScriptError::from_runtime(err, keyword.line as u64);
+                            self.env.push_scope(Scope::default());
+                            self.env
+                                .define(&name.lexeme, &Value::Obj(Object::Error(error)));
+                            let result = self.execute_block(handler);
+                            self.env.pop_scope();
+                            result
+                        }
+                        result => result,
+                    };
+                }
+                if let Some(cleanup) = finally {
+                    // an abandoned generator stops without running any more of its body
+                    if !result.as_ref().is_err_and(|err| err.is::<Abandoned>()) {
+                        self.execute_block(cleanup)?;
+                    }
+                }
+                result?;
+            }
+            Stmt::For {
+                name,
+                iterable,
+                body,
+            } => {
+                let iter =
+                    Iter::from_value(&self.eval(iterable)?).map_err(|e| native_error(e, name))?;
+                loop {
+                    let mut caller = NativeCaller {
+                        interpreter: self,
+                        token: name,
+                    };
+                    let Some(value) = iter.next(&mut caller).map_err(|e| native_error(e, name))?
+                    else {
+                        break;
+                    };
+                    // loop variable lives in its own scope wrapping the body's scope
+                    self.env.push_scope(Scope::default());
+                    self.env.define(&name.lexeme, &value);
+                    let result = self.execute_block(body);
+                    self.env.pop_scope();
+                    result?;
+                }
             }
         };
         Ok(())
@@ -70,6 +911,27 @@ impl AstWalker<Stmt, ()> for Interpreter {
 impl AstWalker<Expr, Value> for Interpreter {
     fn visit(&mut self, expr: &ast::Expr) -> anyhow::Result<Value> {
         match expr {
+            ast::Expr::Binary {
+                left,
+                operator,
+                right,
+            } if operator.ty == TokenType::QuestionQuestion => match self.eval(left)?.coalesce() {
+                Some(value) => Ok(value),
+                None => self.eval(right),
+            },
+            ast::Expr::Binary {
+                left,
+                operator,
+                right,
+            } if matches!(operator.ty, TokenType::And | TokenType::Or) => {
+                let left = self.eval(left)?;
+                // the operand that decided the result is the value of the expression
+                if left.is_falsey() == (operator.ty == TokenType::And) {
+                    Ok(left)
+                } else {
+                    self.eval(right)
+                }
+            }
             ast::Expr::Binary {
                 left,
                 operator,
@@ -77,25 +939,7 @@ impl AstWalker<Expr, Value> for Interpreter {
             } => {
                 let lvalue = left.walk(self)?;
                 let rvalue = right.walk(self)?;
-                match operator.ty {
-                    TokenType::Minus => eval_sub(&lvalue, operator, &rvalue),
-                    TokenType::Plus => eval_plus(&lvalue, operator, &rvalue),
-                    TokenType::ForwardSlash => eval_div(&lvalue, operator, &rvalue),
-                    TokenType::Star => eval_mul(&lvalue, operator, &rvalue),
-                    TokenType::Lt => eval_lt(&lvalue, operator, &rvalue),
-                    TokenType::Le => eval_le(&lvalue, operator, &rvalue),
-                    TokenType::Gt => eval_gt(&lvalue, operator, &rvalue),
-                    TokenType::Ge => eval_ge(&lvalue, operator, &rvalue),
-                    TokenType::EqualEqual => Ok(Value::Boolean(lvalue == rvalue)),
-                    TokenType::BangEqual => Ok(Value::Boolean(lvalue != rvalue)),
-                    _ => bail!(
-                        "{}",
-                        AstWalkError::RuntimeError {
-                            token: operator.clone(),
-                            message: "Unknown binary operator found".into()
-                        }
-                    ),
-                }
+                self.binary(&lvalue, operator, &rvalue)
             }
             ast::Expr::Grouping(e) => Ok(e.walk(self)?),
             ast::Expr::Literal(lit) => Ok(lit.clone()),
@@ -103,6 +947,7 @@ impl AstWalker<Expr, Value> for Interpreter {
                 let value = right.walk(self)?;
                 match operator.ty {
                     TokenType::Minus => eval_minus(operator, &value),
+                    TokenType::Tilde => eval_bit_not(operator, &value),
                     _ => {
                         bail!(
                             "{}",
@@ -114,10 +959,223 @@ impl AstWalker<Expr, Value> for Interpreter {
                     }
                 }
             }
-            ast::Expr::Name(name) => self.env.get(name),
+            ast::Expr::Name(name) => self.env.get(&self.resolve(name)),
             ast::Expr::Assignment { name, value } => {
                 let value = self.eval(value)?;
-                self.env.assign(name, &value)?;
+                self.env.assign(&self.resolve(name), &value)?;
+                Ok(value)
+            }
+            ast::Expr::Get { object, name } => {
+                let object = self.eval(object)?;
+                let value =
+                    object
+                        .get_property(&name.lexeme)
+                        .map_err(|e| AstWalkError::RuntimeError {
+                            token: name.clone(),
+                            message: e.to_string(),
+                        })?;
+                Ok(value)
+            }
+            ast::Expr::SafeGet { object, name, call } => {
+                let object = self.eval(object)?;
+                let Some(inner) = object.coalesce() else {
+                    return Ok(object);
+                };
+                let value =
+                    inner
+                        .get_property(&name.lexeme)
+                        .map_err(|e| AstWalkError::RuntimeError {
+                            token: name.clone(),
+                            message: e.to_string(),
+                        })?;
+                let Some((paren, arguments, keywords)) = call else {
+                    return Ok(value);
+                };
+                let args = arguments
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<anyhow::Result<Vec<Value>>>()?;
+                let keywords: Vec<String> = keywords.iter().map(|k| k.lexeme.clone()).collect();
+                self.call_value(&value, &args, &keywords, paren)
+            }
+            ast::Expr::Set {
+                object,
+                name,
+                operator,
+                value,
+            } => {
+                let runtime_error = |e: Error| {
+                    anyhow!(AstWalkError::RuntimeError {
+                        token: name.clone(),
+                        message: e.to_string(),
+                    })
+                };
+                let object = self.eval(object)?;
+                let value = match operator {
+                    Some(operator) => {
+                        let current = object.get_property(&name.lexeme).map_err(runtime_error)?;
+                        let value = self.eval(value)?;
+                        self.binary(&current, operator, &value)?
+                    }
+                    None => self.eval(value)?,
+                };
+                object
+                    .set_property(&name.lexeme, value.clone())
+                    .map_err(runtime_error)?;
+                Ok(value)
+            }
+            ast::Expr::Path { object, name } => {
+                let object = self.eval(object)?;
+                let value =
+                    object
+                        .get_path(&name.lexeme)
+                        .map_err(|e| AstWalkError::RuntimeError {
+                            token: name.clone(),
+                            message: e.to_string(),
+                        })?;
+                Ok(value)
+            }
+            ast::Expr::Some(value) => Ok(Value::some(self.eval(value)?)),
+            ast::Expr::Ok(value) => Ok(Value::ok(self.eval(value)?)),
+            ast::Expr::Err(value) => Ok(Value::err(self.eval(value)?)),
+            ast::Expr::Try { value, question } => {
+                let value = self.eval(value)?;
+                let unwrapped = value.try_unwrap().map_err(|e| AstWalkError::RuntimeError {
+                    token: question.clone(),
+                    message: e.to_string(),
+                })?;
+                match unwrapped {
+                    Result::Ok(value) => Ok(value),
+                    // the Err or None itself is returned from the running function
+                    Err(err) if self.function_depth > 0 => Err(Return(err).into()),
+                    // returning early from the top level script ends it with the error
+                    Err(err) => bail!(
+                        "{}",
+                        AstWalkError::RuntimeError {
+                            token: question.clone(),
+                            message: format!("Script returned early from '?' with: {}", err)
+                        }
+                    ),
+                }
+            }
+            ast::Expr::Call {
+                callee,
+                paren,
+                arguments,
+                keywords,
+            } => {
+                let callee = self.eval(callee)?;
+                let args = arguments
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<anyhow::Result<Vec<Value>>>()?;
+                let keywords: Vec<String> = keywords.iter().map(|k| k.lexeme.clone()).collect();
+                self.call_value(&callee, &args, &keywords, paren)
+            }
+            ast::Expr::List { elements, .. } => {
+                let values = elements
+                    .iter()
+                    .map(|element| self.eval(element))
+                    .collect::<anyhow::Result<Vec<Value>>>()?;
+                Ok(Value::list(values))
+            }
+            ast::Expr::Map { brace, entries } => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| Ok((self.eval(key)?, self.eval(value)?)))
+                    .collect::<anyhow::Result<Vec<(Value, Value)>>>()?;
+                Value::map(entries).map_err(|e| map_error(e, brace))
+            }
+            ast::Expr::Comprehension {
+                bracket,
+                key,
+                element,
+                clauses,
+            } => {
+                let mut values = Vec::new();
+                self.comprehension(key.as_deref(), element, clauses, &mut values)?;
+                if key.is_none() {
+                    return Ok(Value::list(values));
+                }
+                let mut values = values.into_iter();
+                let entries = std::iter::from_fn(|| Some((values.next()?, values.next()?)));
+                Value::map(entries.collect()).map_err(|e| map_error(e, bracket))
+            }
+            ast::Expr::Super {
+                keyword,
+                owner,
+                method,
+            } => {
+                let owner = self.env.get(&self.resolve(owner))?;
+                let receiver = self.env.get(&Token {
+                    ty: TokenType::ThisSelf,
+                    lexeme: "self".into(),
+                    ..keyword.clone()
+                })?;
+                BoundMethod::find_super(&owner, receiver, &method.lexeme).map_err(|e| {
+                    anyhow!(AstWalkError::RuntimeError {
+                        token: method.clone(),
+                        message: e.to_string(),
+                    })
+                })
+            }
+            ast::Expr::Block { statements, value } => {
+                self.env.push_scope(Scope::default());
+                let result = statements
+                    .iter()
+                    .try_for_each(|stmt| stmt.walk(self))
+                    .and_then(|_| match value {
+                        Some(value) => self.eval(value),
+                        None => Ok(Value::Nil),
+                    });
+                self.env.pop_scope();
+                result
+            }
+            ast::Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if !self.eval(condition)?.is_falsey() {
+                    self.eval(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.eval(else_branch)
+                } else {
+                    Ok(Value::Nil)
+                }
+            }
+            ast::Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.eval(object)?;
+                let index = self.eval(index)?;
+                self.index(object, index, bracket)
+            }
+            ast::Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                operator,
+                value,
+            } => {
+                let object = self.eval(object)?;
+                let index = self.eval(index)?;
+                let value = match operator {
+                    Some(operator) => {
+                        let current = self.index(object.clone(), index.clone(), bracket)?;
+                        let value = self.eval(value)?;
+                        self.binary(&current, operator, &value)?
+                    }
+                    None => self.eval(value)?,
+                };
+                object.set_index(&index, value.clone()).map_err(|e| {
+                    AstWalkError::RuntimeError {
+                        token: bracket.clone(),
+                        message: e.to_string(),
+                    }
+                })?;
                 Ok(value)
             }
         }
@@ -126,6 +1184,41 @@ impl AstWalker<Expr, Value> for Interpreter {
 
 // TODO :: Refactor these eval_* functions into a single macro that can print out this code, or at
 // least define the eval_* functions with highly similar function bodies
+/// Function value of a `fn` declaration or struct method parsed into clauses.
+fn make_function(name: String, clauses: &[ast::FnClause], attributes: &Attributes) -> Function {
+    let generator = clauses.first().is_some_and(|clause| clause.generator);
+    let clauses = clauses
+        .iter()
+        .map(|clause| Clause {
+            params: clause.params.clone(),
+            signature: clause.signature.clone(),
+            body: FunctionBody::Ast {
+                defaults: clause.defaults.clone(),
+                guard: clause.guard.clone().map(Box::new),
+                body: Box::new(clause.body.clone()),
+            },
+        })
+        .collect();
+    Function {
+        name,
+        clauses,
+        generator,
+        attributes: attributes.clone(),
+    }
+}
+
+/// Methods declared in the body of `owner`, named `owner.method`.
+fn make_methods(owner: &str, methods: &[ast::Method]) -> HashMap<String, Arc<Function>> {
+    methods
+        .iter()
+        .map(|method| {
+            let name = format!("{}.{}", owner, method.name.lexeme);
+            let function = make_function(name, &method.clauses, &method.attributes);
+            (method.name.lexeme.clone(), Arc::new(function))
+        })
+        .collect()
+}
+
 pub fn eval_minus(minus_op: &Token, value: &Value) -> anyhow::Result<Value> {
     let num = value.as_number().map_err(|e| AstWalkError::RuntimeError {
         token: minus_op.clone(),
@@ -134,6 +1227,76 @@ pub fn eval_minus(minus_op: &Token, value: &Value) -> anyhow::Result<Value> {
     Ok(Value::Number(-num))
 }
 
+pub fn eval_bit_not(tilde_op: &Token, value: &Value) -> anyhow::Result<Value> {
+    let num = value.as_integer().map_err(|e| AstWalkError::RuntimeError {
+        token: tilde_op.clone(),
+        message: format!("Operand must be an integer, {}", e),
+    })?;
+    Ok(Value::Number(!num as f64))
+}
+
+/// Applies an arithmetic operator that is only defined for 2 numbers.
+pub fn eval_number_op(
+    left: &Value,
+    operator: &Token,
+    right: &Value,
+    op: fn(f64, f64) -> f64,
+) -> anyhow::Result<Value> {
+    match (left, right) {
+        (Value::Number(ln), Value::Number(rn)) => Ok(Value::Number(op(*ln, *rn))),
+        _ => bail!(
+            "{}",
+            AstWalkError::RuntimeError {
+                token: operator.clone(),
+                message: format!(
+                    "Operands of '{}' must be 2 numbers, got: {} and {}",
+                    operator.lexeme,
+                    left.type_string(),
+                    right.type_string()
+                )
+            }
+        ),
+    }
+}
+
+/// Applies an operator that is only defined for 2 integers, numbers with a fractional part are
+/// rejected rather than truncated.
+pub fn eval_integer_op(
+    left: &Value,
+    operator: &Token,
+    right: &Value,
+    op: fn(i64, i64) -> anyhow::Result<i64>,
+) -> anyhow::Result<Value> {
+    match (left.as_integer(), right.as_integer()) {
+        (Result::Ok(ln), Result::Ok(rn)) => {
+            let result = op(ln, rn).map_err(|e| AstWalkError::RuntimeError {
+                token: operator.clone(),
+                message: e.to_string(),
+            })?;
+            Ok(Value::Number(result as f64))
+        }
+        _ => bail!(
+            "{}",
+            AstWalkError::RuntimeError {
+                token: operator.clone(),
+                message: format!(
+                    "Operands of '{}' must be 2 integers, got: {} and {}",
+                    operator.lexeme,
+                    left.integer_type_string(),
+                    right.integer_type_string()
+                )
+            }
+        ),
+    }
+}
+
+fn eval_shift(a: i64, b: i64, op: fn(i64, u32) -> Option<i64>) -> anyhow::Result<i64> {
+    match u32::try_from(b).ok().and_then(|b| op(a, b)) {
+        Some(n) => Ok(n),
+        None => bail!("Shift amount must be between 0 and 63, got: {}", b),
+    }
+}
+
 pub fn eval_le(left: &Value, operator: &Token, right: &Value) -> anyhow::Result<Value> {
     match left {
         Value::Number(ln) => {
@@ -146,7 +1309,7 @@ pub fn eval_le(left: &Value, operator: &Token, right: &Value) -> anyhow::Result<
                     e
                 ),
             })?;
-            Ok(Value::Boolean(ln < &rn))
+            Ok(Value::Boolean(ln <= &rn))
         }
         _ => bail!(
             "{}",
@@ -173,7 +1336,7 @@ pub fn eval_lt(left: &Value, operator: &Token, right: &Value) -> anyhow::Result<
                     e
                 ),
             })?;
-            Ok(Value::Boolean(ln > &rn))
+            Ok(Value::Boolean(ln < &rn))
         }
         _ => bail!(
             "{}",
@@ -337,21 +1500,29 @@ pub fn eval_plus(left: &Value, operator: &Token, right: &Value) -> anyhow::Resul
             })?;
             Ok(Value::Number(ln + rn))
         }
-        Value::Obj(obj) => match obj {
-            Object::String(ls) => {
-                let rs = right.as_string().map_err(|e| AstWalkError::RuntimeError {
-                    token: operator.clone(),
-                    message: format!(
-                        "mismatched addition operator: '{} + {}', {}",
-                        left.type_string(),
-                        right.type_string(),
-                        e
-                    ),
-                })?;
-                Ok(Value::Obj(Object::String(ls.clone() + &rs)))
-            }
-        },
+        Value::Obj(Object::String(ls)) => {
+            let rs = right.as_string().map_err(|e| AstWalkError::RuntimeError {
+                token: operator.clone(),
+                message: format!(
+                    "mismatched addition operator: '{} + {}', {}",
+                    left.type_string(),
+                    right.type_string(),
+                    e
+                ),
+            })?;
+            Ok(Value::Obj(Object::String(ls.clone() + &rs)))
+        }
 
-        _ => todo!(),
+        _ => bail!(
+            "{}",
+            AstWalkError::RuntimeError {
+                token: operator.clone(),
+                message: format!(
+                    "Addition operands must be 2 numbers or 2 strings, got: {} and {}",
+                    left.type_string(),
+                    right.type_string(),
+                )
+            }
+        ),
     }
 }
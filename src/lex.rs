@@ -57,28 +57,45 @@ use phf::phf_map;
 
 use crate::{
     sys::{is_alpha, is_alpha_numeric, is_digit},
-    value::{Object, Token, TokenType, Value},
+    value::{Atom, Object, Token, TokenType, Value},
 };
 
 pub static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "struct" => TokenType::Struct,
+    "enum" => TokenType::Enum,
     "trait" => TokenType::Trait,
     "impl" => TokenType::Impl,
+    "mod" => TokenType::Mod,
+    "pub" => TokenType::Pub,
+    "use" => TokenType::Use,
+    "as" => TokenType::As,
+    "macro" => TokenType::Macro,
     "if" => TokenType::If,
     "else" => TokenType::Else,
     "true" => TokenType::True,
     "false" => TokenType::False,
     "fn" => TokenType::Fn,
+    "where" => TokenType::Where,
     "nil" => TokenType::Nil,
+    "Some" => TokenType::Some,
+    "None" => TokenType::None,
+    "Ok" => TokenType::Ok,
+    "Err" => TokenType::Err,
+    "try" => TokenType::Try,
+    "catch" => TokenType::Catch,
+    "finally" => TokenType::Finally,
+    "throw" => TokenType::Throw,
     "and" => TokenType::And,
     "or" => TokenType::Or,
     "return" => TokenType::Return,
+    "yield" => TokenType::Yield,
     "super" => TokenType::Super,
     "self" => TokenType::ThisSelf,
     "let" => TokenType::Let,
     "const" => TokenType::Const,
     "loop" => TokenType::Loop,
     "for" => TokenType::For,
+    "in" => TokenType::In,
     "while" => TokenType::While,
     "break" => TokenType::Break,
     "switch" => TokenType::Switch,
@@ -95,8 +112,8 @@ impl Lexer {
     }
     pub fn scan_tokens(source_str: &str) -> LexResult {
         let mut lex = Self::new(source_str);
-        while !lex.is_cursor_at_end() {
-            let c = lex.next_token();
+        // trailing whitespace leaves no token to scan
+        while let Some(c) = lex.next_token() {
             let (ty, literal) = match c {
                 '{' => (TokenType::LeftBrace, None),
                 '}' => (TokenType::RightBrace, None),
@@ -104,20 +121,106 @@ impl Lexer {
                 ')' => (TokenType::RightParen, None),
 
                 ',' => (TokenType::Comma, None),
-                '.' => (TokenType::Dot, None),
-                '-' => (TokenType::Minus, None),
-                '+' => (TokenType::Plus, None),
+                '[' => (TokenType::LeftBracket, None),
+                ']' => (TokenType::RightBracket, None),
+                '.' => (
+                    if lex.match_next('.') {
+                        if lex.match_next('=') {
+                            TokenType::DotDotEqual
+                        } else if lex.match_next('.') {
+                            TokenType::DotDotDot
+                        } else {
+                            TokenType::DotDot
+                        }
+                    } else {
+                        TokenType::Dot
+                    },
+                    None,
+                ),
+                '-' => (
+                    if lex.match_next('=') {
+                        TokenType::MinusEqual
+                    } else if lex.match_next('>') {
+                        TokenType::Arrow
+                    } else {
+                        TokenType::Minus
+                    },
+                    None,
+                ),
+                '+' => (
+                    if lex.match_next('=') {
+                        TokenType::PlusEqual
+                    } else {
+                        TokenType::Plus
+                    },
+                    None,
+                ),
 
                 ';' => (TokenType::Semicolon, None),
-                ':' => (
+                ':' => {
                     if lex.match_next(':') {
-                        TokenType::DoubleColon
+                        (TokenType::DoubleColon, None)
+                    } else if !lex.is_cursor_at_end() && is_alpha(lex.peek()) {
+                        // :name is an atom, a colon followed by anything else is a plain colon
+                        (TokenType::Atom, lex.select_atom())
                     } else {
-                        TokenType::Colon
+                        (TokenType::Colon, None)
+                    }
+                }
+                '*' => (
+                    if lex.match_next('*') {
+                        TokenType::StarStar
+                    } else if lex.match_next('=') {
+                        TokenType::StarEqual
+                    } else {
+                        TokenType::Star
+                    },
+                    None,
+                ),
+                '?' => (
+                    if lex.match_next('?') {
+                        TokenType::QuestionQuestion
+                    } else if lex.match_next('.') {
+                        TokenType::QuestionDot
+                    } else {
+                        TokenType::Question
+                    },
+                    None,
+                ),
+                '%' => (
+                    if lex.match_next('=') {
+                        TokenType::PercentEqual
+                    } else {
+                        TokenType::Percent
+                    },
+                    None,
+                ),
+                '&' => (
+                    if lex.match_next('&') {
+                        TokenType::And
+                    } else {
+                        TokenType::Ampersand
+                    },
+                    None,
+                ),
+                '|' => (
+                    if lex.match_next('|') {
+                        TokenType::Or
+                    } else {
+                        TokenType::Pipe
+                    },
+                    None,
+                ),
+                '^' => (TokenType::Caret, None),
+                '@' => (TokenType::At, None),
+                '~' => (
+                    if lex.match_next('/') {
+                        TokenType::TildeSlash
+                    } else {
+                        TokenType::Tilde
                     },
                     None,
                 ),
-                '*' => (TokenType::Star, None),
                 '!' => (
                     if lex.match_next('=') {
                         TokenType::BangEqual
@@ -141,6 +244,8 @@ impl Lexer {
                 '<' => (
                     if lex.match_next('=') {
                         TokenType::Le
+                    } else if lex.match_next('<') {
+                        TokenType::ShiftLeft
                     } else {
                         TokenType::Lt
                     },
@@ -150,6 +255,8 @@ impl Lexer {
                 '>' => (
                     if lex.match_next('=') {
                         TokenType::Ge
+                    } else if lex.match_next('>') {
+                        TokenType::ShiftRight
                     } else {
                         TokenType::Gt
                     },
@@ -161,6 +268,8 @@ impl Lexer {
                             lex.advance_cursor(1);
                         }
                         TokenType::Comment
+                    } else if lex.match_next('=') {
+                        TokenType::SlashEqual
                     } else {
                         TokenType::ForwardSlash
                     };
@@ -235,6 +344,15 @@ impl Lexer {
         }
     }
 
+    fn select_atom(&mut self) -> Option<Value> {
+        while !self.is_cursor_at_end() && is_alpha_numeric(self.peek()) {
+            self.advance_cursor(1);
+        }
+        // skip the leading ':'
+        let name = &self.source_str[self.cursor.start + 1..self.cursor.i];
+        Some(Value::Atom(Atom::intern(name)))
+    }
+
     fn select_string(&mut self) -> Option<Value> {
         while !self.is_cursor_at_end() && self.peek() != '"' {
             if self.peek() == '\n' {
@@ -271,18 +389,16 @@ impl Lexer {
         }
     }
 
+    /// Character at the cursor, '\0' past the end of the source.
     fn peek(&self) -> char {
-        self.source[self.cursor.i] as char
+        self.peekn(0)
     }
     fn peekn(&self, n: usize) -> char {
-        let ci = self.cursor.i + n;
-        assert!(
-            ci < self.source.len(),
-            "cursor index out of range of source string buffer"
-        );
-        self.source[ci] as char
+        self.source
+            .get(self.cursor.i + n)
+            .map_or('\0', |&byte| byte as char)
     }
-    fn next_token(&mut self) -> char {
+    fn next_token(&mut self) -> Option<char> {
         while !self.is_cursor_at_end() {
             let c = self.peek();
             if c.is_whitespace() {
@@ -293,10 +409,10 @@ impl Lexer {
             } else {
                 self.cursor.start = self.cursor.i;
                 self.advance_cursor(1);
-                return c;
+                return Some(c);
             }
         }
-        '\0'
+        None
     }
     fn new(source_str: &str) -> Self {
         Self {
@@ -314,6 +430,39 @@ impl Lexer {
     }
 }
 
+/// Rebuilds readable source text from a run of tokens, used to show function heads in errors.
+pub fn tokens_to_source(tokens: &[Token]) -> String {
+    let mut source = String::new();
+    let mut prev: Option<TokenType> = None;
+    for token in tokens {
+        let glued_to_prev = matches!(
+            prev,
+            Some(TokenType::LeftParen | TokenType::LeftBracket | TokenType::Dot)
+                | Some(TokenType::DoubleColon | TokenType::Bang | TokenType::Tilde)
+        );
+        let glued_to_next = matches!(
+            token.ty,
+            TokenType::LeftParen
+                | TokenType::RightParen
+                | TokenType::LeftBracket
+                | TokenType::RightBracket
+                | TokenType::Comma
+                | TokenType::Dot
+                | TokenType::DoubleColon
+                | TokenType::Question
+        );
+        if prev.is_some() && !glued_to_prev && !glued_to_next {
+            source.push(' ');
+        }
+        match token.ty {
+            TokenType::String => source.push_str(&format!("\"{}\"", token.lexeme)),
+            _ => source.push_str(&token.lexeme),
+        }
+        prev = Some(token.ty);
+    }
+    source
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Cursor {
     pub start: usize,
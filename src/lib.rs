@@ -1,9 +1,15 @@
 pub mod ast;
+pub mod attr;
 
+pub mod check;
 pub mod compiler;
+pub mod doc;
 pub mod env;
 pub mod interp;
 pub mod lex;
+pub mod macros;
+pub mod module;
+pub mod native;
 pub mod parse;
 pub mod sys;
 pub mod value;
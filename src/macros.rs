@@ -0,0 +1,417 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use anyhow::bail;
+
+use crate::{
+    parse::starts_statement,
+    value::{Token, TokenType},
+};
+
+/// Macro calls allowed inside an expansion before a macro is assumed to expand into itself forever
+const MAX_DEPTH: usize = 64;
+
+/// `macro name(params) { body }`, expanded in place of each `name!(args)` call into its body,
+/// a block, with the parameters replaced by the tokens of the arguments.
+#[derive(Debug, Clone)]
+struct Macro {
+    name: Token,
+    params: Vec<String>,
+    /// Body including its braces
+    body: Vec<Token>,
+}
+
+/// Tokens a macro call expanded into, so errors inside them can point at both the call and the
+/// definition. The tokens themselves all get the line of the outermost call, so the errors of
+/// both backends point at the source that ran them.
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub name: String,
+    pub call_line: u32,
+    pub definition_line: u32,
+    pub tokens: Range<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Expanded {
+    pub tokens: Vec<Token>,
+    pub expansions: Vec<Expansion>,
+}
+
+/// The innermost of `expansions` the token at `index` is part of.
+pub fn origin(expansions: &[Expansion], index: usize) -> Option<String> {
+    expansions
+        .iter()
+        .filter(|expansion| expansion.tokens.contains(&index))
+        .min_by_key(|expansion| expansion.tokens.len())
+        .map(|expansion| {
+            format!(
+                "in the expansion of macro '{}' called on line {}, defined on line {}",
+                expansion.name, expansion.call_line, expansion.definition_line
+            )
+        })
+}
+
+/// Removes the top-level macro definitions from `tokens` and expands every call of them. Names a
+/// macro body binds with `let`, `for`, `fn` and `catch` are renamed apart on each call, so they
+/// can't capture or shadow the caller's variables.
+pub fn expand(tokens: &[Token]) -> anyhow::Result<Expanded> {
+    let (macros, rest) = definitions(tokens)?;
+    let mut expander = Expander {
+        macros,
+        expanded: Expanded::default(),
+        calls: 0,
+    };
+    expander.expand_into(&rest, 0)?;
+    let mut expanded = expander.expanded;
+    // an expansion is pushed after the ones nested in it, so the outermost call's line wins
+    for expansion in &expanded.expansions {
+        for token in &mut expanded.tokens[expansion.tokens.clone()] {
+            token.line = expansion.call_line;
+        }
+    }
+    Ok(expanded)
+}
+
+struct Expander {
+    macros: HashMap<String, Macro>,
+    expanded: Expanded,
+    /// Number of calls expanded so far, suffixed to the names each one binds
+    calls: usize,
+}
+
+impl Expander {
+    fn expand_into(&mut self, tokens: &[Token], depth: usize) -> anyhow::Result<()> {
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            if !is_call(tokens, i) {
+                self.expanded.tokens.push(token.clone());
+                i += 1;
+                continue;
+            }
+            let Some(mac) = self.macros.get(&token.lexeme).cloned() else {
+                bail!("No macro named '{}' (line {})", token.lexeme, token.line);
+            };
+            let close = matching(tokens, i + 2)?;
+            let args = arguments(&tokens[i + 3..close])?;
+            if args.len() != mac.params.len() {
+                bail!(
+                    "Macro '{}' takes {} argument(s) but {} were given (called on line {}, defined on line {})",
+                    mac.name.lexeme,
+                    mac.params.len(),
+                    args.len(),
+                    token.line,
+                    mac.name.line
+                );
+            }
+            if depth == MAX_DEPTH {
+                bail!(
+                    "Macro '{}' called on line {} expands into more than {} nested macro calls",
+                    mac.name.lexeme,
+                    token.line,
+                    MAX_DEPTH
+                );
+            }
+            let body = self.instantiate(&mac, &args);
+            let start = self.expanded.tokens.len();
+            self.expand_into(&body, depth + 1)?;
+            self.expanded.expansions.push(Expansion {
+                name: mac.name.lexeme.clone(),
+                call_line: token.line,
+                definition_line: mac.name.line,
+                tokens: start..self.expanded.tokens.len(),
+            });
+            i = close + 1;
+        }
+        Ok(())
+    }
+
+    /// Body of `mac` with its parameters replaced by `args` and the names it binds renamed apart.
+    fn instantiate(&mut self, mac: &Macro, args: &[&[Token]]) -> Vec<Token> {
+        self.calls += 1;
+        let bound = bindings(&mac.body, &mac.params);
+        let mut body = Vec::with_capacity(mac.body.len());
+        for (i, token) in mac.body.iter().enumerate() {
+            if token.ty != TokenType::Ident || is_field(&mac.body, i) {
+                body.push(token.clone());
+            } else if let Some(param) = mac.params.iter().position(|p| *p == token.lexeme) {
+                body.extend(argument(args[param]));
+            } else if bound.contains(&token.lexeme) {
+                body.push(Token {
+                    lexeme: format!("{}#{}", token.lexeme, self.calls),
+                    ..token.clone()
+                });
+            } else {
+                body.push(token.clone());
+            }
+        }
+        body
+    }
+}
+
+/// Splits the top-level macro definitions off `tokens`.
+fn definitions(tokens: &[Token]) -> anyhow::Result<(HashMap<String, Macro>, Vec<Token>)> {
+    let mut macros: HashMap<String, Macro> = HashMap::new();
+    let mut rest = Vec::with_capacity(tokens.len());
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        match token.ty {
+            TokenType::LeftBrace => depth += 1,
+            TokenType::RightBrace => depth = depth.saturating_sub(1),
+            TokenType::Macro if depth > 0 => {
+                bail!(
+                    "Macros can only be defined at the top level (line {})",
+                    token.line
+                )
+            }
+            TokenType::Macro => {
+                let (mac, end) = definition(tokens, i)?;
+                if let Some(existing) = macros.get(&mac.name.lexeme) {
+                    bail!(
+                        "Macro '{}' on line {} is already defined on line {}",
+                        mac.name.lexeme,
+                        mac.name.line,
+                        existing.name.line
+                    );
+                }
+                macros.insert(mac.name.lexeme.clone(), mac);
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        rest.push(token.clone());
+        i += 1;
+    }
+    Ok((macros, rest))
+}
+
+/// macro name(params) { body } starting at `start`, along with the index after it.
+fn definition(tokens: &[Token], start: usize) -> anyhow::Result<(Macro, usize)> {
+    let name = expect(tokens, start + 1, TokenType::Ident, "Expected macro name")?.clone();
+    expect(
+        tokens,
+        start + 2,
+        TokenType::LeftParen,
+        "Expected '(' after macro name",
+    )?;
+    let mut params: Vec<String> = Vec::new();
+    let mut i = start + 3;
+    while tokens.get(i).map(|t| t.ty) != Some(TokenType::RightParen) {
+        let param = expect(tokens, i, TokenType::Ident, "Expected macro parameter name")?;
+        if params.contains(&param.lexeme) {
+            bail!(
+                "Duplicate parameter '{}' of macro '{}' (line {})",
+                param.lexeme,
+                name.lexeme,
+                param.line
+            );
+        }
+        params.push(param.lexeme.clone());
+        i += 1;
+        if tokens.get(i).map(|t| t.ty) != Some(TokenType::Comma) {
+            expect(
+                tokens,
+                i,
+                TokenType::RightParen,
+                "Expected ')' after macro parameters",
+            )?;
+            break;
+        }
+        i += 1;
+    }
+    expect(
+        tokens,
+        i + 1,
+        TokenType::LeftBrace,
+        "Expected '{' before macro body",
+    )?;
+    let end = matching(tokens, i + 1)?;
+    let mac = Macro {
+        name,
+        params,
+        body: tokens[i + 1..=end].to_vec(),
+    };
+    Ok((mac, end + 1))
+}
+
+fn expect<'a>(
+    tokens: &'a [Token],
+    i: usize,
+    ty: TokenType,
+    message: &str,
+) -> anyhow::Result<&'a Token> {
+    match tokens.get(i) {
+        Some(token) if token.ty == ty => Ok(token),
+        Some(token) => bail!("{} on line {}, got '{}'", message, token.line, token.lexeme),
+        None => bail!("{}, got end of file", message),
+    }
+}
+
+/// name!( starting at `i`
+fn is_call(tokens: &[Token], i: usize) -> bool {
+    matches!(
+        tokens.get(i..i + 3).map(|t| [t[0].ty, t[1].ty, t[2].ty]),
+        Some([TokenType::Ident, TokenType::Bang, TokenType::LeftParen])
+    )
+}
+
+/// Index of the bracket closing the one at `open`.
+fn matching(tokens: &[Token], open: usize) -> anyhow::Result<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token.ty {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    bail!(
+        "Unclosed '{}' on line {}",
+        tokens[open].lexeme,
+        tokens[open].line
+    )
+}
+
+/// Arguments of a macro call, split on the commas outside of any brackets.
+fn arguments(tokens: &[Token]) -> anyhow::Result<Vec<&[Token]>> {
+    let mut args = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.ty {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => depth -= 1,
+            TokenType::Comma if depth == 0 => {
+                if start == i {
+                    bail!("Expected macro argument before ',' on line {}", token.line);
+                }
+                args.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    // a trailing comma is allowed
+    if start < tokens.len() {
+        args.push(&tokens[start..]);
+    }
+    Ok(args)
+}
+
+/// Tokens an argument is substituted with, grouped in parentheses unless it already is a single
+/// token or bracketed, so `x * x` with `1 + 2` for `x` keeps its meaning. Statements are
+/// substituted as they are, they can't be grouped.
+fn argument(tokens: &[Token]) -> Vec<Token> {
+    let grouped = tokens.len() == 1
+        || is_statement(tokens)
+        || matches!(
+            tokens[0].ty,
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket
+        ) && matching(tokens, 0).ok() == Some(tokens.len() - 1);
+    if grouped {
+        return tokens.to_vec();
+    }
+    let paren = |ty, lexeme: &str| Token {
+        ty,
+        lexeme: lexeme.into(),
+        ..tokens[0].clone()
+    };
+    std::iter::once(paren(TokenType::LeftParen, "("))
+        .chain(tokens.iter().cloned())
+        .chain(std::iter::once(paren(TokenType::RightParen, ")")))
+        .collect()
+}
+
+/// Whether `tokens` are statements rather than an expression, they start with a statement keyword
+/// or have a `;` outside any brackets.
+fn is_statement(tokens: &[Token]) -> bool {
+    let mut depth = 0usize;
+    starts_statement(tokens[0].ty)
+        || tokens.iter().any(|token| {
+            match token.ty {
+                TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+                TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                    depth = depth.saturating_sub(1)
+                }
+                TokenType::Semicolon => return depth == 0,
+                _ => {}
+            }
+            false
+        })
+}
+
+/// Whether the identifier at `i` names a field, method, keyword argument or path segment rather
+/// than a variable.
+fn is_field(tokens: &[Token], i: usize) -> bool {
+    let prev = i.checked_sub(1).map(|p| tokens[p].ty);
+    let next = tokens.get(i + 1).map(|t| t.ty);
+    matches!(prev, Some(TokenType::Dot | TokenType::DoubleColon))
+        || matches!(next, Some(TokenType::Colon | TokenType::DoubleColon))
+}
+
+/// Names `body` binds with let, for, fn (and its parameters) and catch, other than `params`.
+fn bindings(body: &[Token], params: &[String]) -> HashSet<String> {
+    let mut bound = HashSet::new();
+    let ident = |i: usize| {
+        body.get(i)
+            .filter(|t| t.ty == TokenType::Ident)
+            .map(|t| t.lexeme.clone())
+    };
+    for (i, token) in body.iter().enumerate() {
+        match token.ty {
+            TokenType::Let => bound.extend(ident(i + 1)),
+            TokenType::For => {
+                let names = body[i + 1..]
+                    .iter()
+                    .take_while(|t| t.ty != TokenType::In)
+                    .filter(|t| t.ty == TokenType::Ident);
+                bound.extend(names.map(|t| t.lexeme.clone()));
+            }
+            TokenType::Catch if body.get(i + 1).map(|t| t.ty) == Some(TokenType::LeftParen) => {
+                bound.extend(ident(i + 2))
+            }
+            TokenType::Fn => {
+                let mut j = i + 1;
+                if body.get(j).map(|t| t.ty) == Some(TokenType::Star) {
+                    j += 1;
+                }
+                bound.extend(ident(j));
+                let Some(open) = (j..body.len()).find(|&k| body[k].ty == TokenType::LeftParen)
+                else {
+                    continue;
+                };
+                let Ok(close) = matching(body, open) else {
+                    continue;
+                };
+                let mut depth = 0usize;
+                for (k, t) in body.iter().enumerate().take(close).skip(open) {
+                    match t.ty {
+                        TokenType::LeftParen | TokenType::LeftBracket => depth += 1,
+                        TokenType::RightParen | TokenType::RightBracket => depth -= 1,
+                        _ => {}
+                    }
+                    // parameters follow the '(' and the ',' of the parameter list
+                    if depth == 1 && matches!(t.ty, TokenType::LeftParen | TokenType::Comma) {
+                        bound.extend(ident(k + 1));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for param in params {
+        bound.remove(param);
+    }
+    bound
+}
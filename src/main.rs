@@ -1,19 +1,30 @@
 mod ast;
+mod attr;
 
+mod check;
 mod compiler;
+mod doc;
 mod env;
 mod interp;
 mod lex;
+mod macros;
+mod module;
+mod native;
 mod parse;
 mod sys;
 mod value;
 mod vm;
 
-use std::io::Write;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use ast::Expr;
-use clap::{arg, command, Command};
+use clap::{arg, command, ArgAction, ArgMatches, Command};
 
+use check::Checker;
+use doc::Site;
 use interp::Interpreter;
 use lex::{LexResult, Lexer};
 use log::debug;
@@ -31,11 +42,54 @@ fn main() -> anyhow::Result<()> {
         .version("0.0.1")
         // .subcommand_required(true)
         .arg(arg!([filepath] "path to script to run").required(false))
+        .arg(
+            arg!(-p --path <DIR> "directory to search for modules, before CORROSION_PATH")
+                .action(ArgAction::Append)
+                .global(true),
+        )
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("doc")
+                .about("Generate HTML and Markdown documentation for the scripts in a directory")
+                .arg(arg!(<dir> "directory of the scripts to document"))
+                .arg(arg!(-o --out <DIR> "directory to write the documentation to, <dir>/doc by default")),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Type check a script and its modules against their @spec and field types")
+                .arg(arg!(<filepath> "path to script to check")),
+        )
         .get_matches();
 
-    if let Some(filepath) = args.get_one::<String>("filepath") {
+    if let Some(("doc", doc_args)) = args.subcommand() {
+        let dir = Path::new(doc_args.get_one::<String>("dir").expect("dir is required"));
+        let out = match doc_args.get_one::<String>("out") {
+            Some(out) => PathBuf::from(out),
+            None => dir.join("doc"),
+        };
+        let written = Site::build(dir, &out, &search_paths(doc_args))?.write(&out)?;
+        println!("Wrote {} files to {}", written.len(), out.display());
+    } else if let Some(("check", check_args)) = args.subcommand() {
+        let filepath = check_args
+            .get_one::<String>("filepath")
+            .expect("filepath is required");
+        let stmts = Parser::parse_file(Path::new(filepath), &search_paths(check_args))?;
+        let report = Checker::check(&stmts);
+        for problem in report.errors.iter().chain(&report.warnings) {
+            eprintln!("{}", problem);
+        }
+        if !report.errors.is_empty() {
+            anyhow::bail!("{} type error(s) in {}", report.errors.len(), filepath);
+        }
+        if report.warnings.is_empty() {
+            println!("No type errors in {}", filepath);
+        } else {
+            let count = report.warnings.len();
+            println!("No type errors in {}, {} nil warning(s)", filepath, count);
+        }
+    } else if let Some(filepath) = args.get_one::<String>("filepath") {
         let mut vm = VM::new();
-        vm.interpret_script(filepath)?;
+        vm.interpret_script(filepath, &search_paths(&args))?;
     } else {
         run_repl()?;
     };
@@ -46,6 +100,13 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn search_paths(args: &ArgMatches) -> Vec<PathBuf> {
+    args.get_many::<String>("path")
+        .unwrap_or_default()
+        .map(PathBuf::from)
+        .collect()
+}
+
 // TODO(FIXME) :: Fix repl
 fn run_repl() -> anyhow::Result<()> {
     let mut vm = VM::new();
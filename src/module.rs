@@ -0,0 +1,361 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+
+use crate::{
+    lex::Lexer,
+    macros::{self, Expanded},
+    value::{Token, TokenType},
+};
+
+/// File-based module declared with `mod name;`, loaded from the first `name.cor` found next to
+/// the file declaring it or in the script's search paths. Items declared at its top level are
+/// globals named `name::item`, and only the `pub` ones can be reached from outside it.
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    /// `outer::name`, empty for the script being run
+    pub path: String,
+    pub file: PathBuf,
+    /// Directory the files of the modules it declares are looked for in first
+    pub dir: PathBuf,
+    /// Directories searched after the declaring file's, only set on the script
+    pub search: Vec<PathBuf>,
+    /// Top-level items and whether each is `pub`, known before the module is compiled so its
+    /// functions can refer to items declared after them
+    pub items: HashMap<String, bool>,
+    pub modules: HashMap<String, Module>,
+    /// Names brought in by `use`, visible to the code after the declaration
+    pub imports: HashMap<String, Import>,
+    /// Each name of an explicit `use` and each `use path::*;`, in order
+    pub uses: Vec<Use>,
+}
+
+/// Name a module refers to another module's item by.
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub global: String,
+    /// Set when the item is itself a module, so paths can continue through the name
+    pub module: Option<Module>,
+    /// Whether it came from a `use path::*;`, which the module's own items and explicit
+    /// imports shadow
+    pub glob: bool,
+    /// Index of the entry in `Module::uses` that brought it in
+    use_index: usize,
+}
+
+/// Part of a `use` declaration, reported as unused when none of its names are referred to.
+#[derive(Debug, Clone)]
+pub struct Use {
+    /// `alias` or `path::*`
+    pub label: String,
+    pub line: u32,
+    pub used: bool,
+}
+
+impl Module {
+    /// Environment variable holding extra directories to search for modules, separated like PATH.
+    pub const PATH_VAR: &'static str = "CORROSION_PATH";
+
+    /// Root module of the script at `file`. The script's own directory is searched after the
+    /// directory of the file declaring a module, so modules anywhere can load the files next to
+    /// the script, followed by `paths` and then the directories in `CORROSION_PATH`.
+    pub fn script(file: &Path, paths: &[PathBuf]) -> Self {
+        let dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+        let env = std::env::var_os(Self::PATH_VAR).unwrap_or_default();
+        let search = std::iter::once(dir.clone())
+            .chain(paths.iter().cloned())
+            .chain(std::env::split_paths(&env).filter(|p| !p.as_os_str().is_empty()))
+            .collect();
+        Self {
+            file: canonical(file),
+            dir,
+            search,
+            ..Self::default()
+        }
+    }
+
+    /// Global the item `name` is stored in.
+    pub fn global(&self, name: &str) -> String {
+        if self.path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}::{}", self.path, name)
+        }
+    }
+
+    /// Splits a global into the path of the module it belongs to and its name there.
+    pub fn split(global: &str) -> (&str, &str) {
+        global.rsplit_once("::").unwrap_or(("", global))
+    }
+
+    /// Global of `name` as referred to by `path::name` from outside the module.
+    pub fn item(&self, name: &str) -> anyhow::Result<String> {
+        match self.items.get(name) {
+            Some(true) => Ok(self.global(name)),
+            Some(false) => bail!("'{}' is private to module '{}'", name, self.path),
+            None => bail!("Module '{}' has no item '{}'", self.path, name),
+        }
+    }
+
+    /// Global `name` refers to inside the module, an item of its own or an imported one.
+    pub fn resolve(&mut self, name: &str) -> Option<String> {
+        if self.items.contains_key(name) {
+            return Some(self.global(name));
+        }
+        let import = self.imports.get(name)?;
+        self.uses[import.use_index].used = true;
+        Some(import.global.clone())
+    }
+
+    /// Module declared in, or imported into, the module as `name`.
+    pub fn module(&mut self, name: &str) -> Option<Module> {
+        if let Some(module) = self.modules.get(name) {
+            return Some(module.clone());
+        }
+        let import = self.imports.get(name)?;
+        let module = import.module.clone()?;
+        self.uses[import.use_index].used = true;
+        Some(module)
+    }
+
+    /// use path::{name, name as alias}; or use path::*; with `path` as its tokens, `names` as
+    /// (name, alias) pairs and None for a glob. Returns each name brought in with its global,
+    /// nothing is imported when any of them fails.
+    pub fn use_items(
+        &mut self,
+        path: &[Token],
+        names: Option<&[(Token, Token)]>,
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        let (imports, uses) = (self.imports.clone(), self.uses.len());
+        let result = self.bind(path, names);
+        if result.is_err() {
+            self.imports = imports;
+            self.uses.truncate(uses);
+        }
+        result
+    }
+
+    fn bind(
+        &mut self,
+        path: &[Token],
+        names: Option<&[(Token, Token)]>,
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        let Some((first, rest)) = path.split_first() else {
+            bail!("Expected a module path after 'use'");
+        };
+        let Some(mut module) = self.module(&first.lexeme) else {
+            bail!(
+                "Unknown module '{}', modules must be declared with 'mod' before they are used",
+                first.lexeme
+            );
+        };
+        for inner in rest {
+            module.item(&inner.lexeme)?;
+            let Some(next) = module.modules.get(&inner.lexeme).cloned() else {
+                bail!("'{}' is not a module", module.global(&inner.lexeme));
+            };
+            module = next;
+        }
+        let mut bindings = Vec::new();
+        let Some(names) = names else {
+            let line = path.last().map(|t| t.line).unwrap_or_default();
+            self.uses.push(Use {
+                label: format!("{}::*", module.path),
+                line,
+                used: false,
+            });
+            let mut public: Vec<&String> = module
+                .items
+                .iter()
+                .filter_map(|(name, &public)| public.then_some(name))
+                .collect();
+            public.sort();
+            for name in public {
+                // the module's own items shadow glob imports
+                if self.items.contains_key(name) {
+                    continue;
+                }
+                let global = module.global(name);
+                if self.import(name, &global, module.modules.get(name), true)? {
+                    bindings.push((name.clone(), global));
+                }
+            }
+            return Ok(bindings);
+        };
+        let mut listed = HashSet::new();
+        for (name, alias) in names {
+            let global = module.item(&name.lexeme)?;
+            if !listed.insert(&alias.lexeme) {
+                bail!(
+                    "Conflicting imports of '{}', it is listed twice in the same use",
+                    alias.lexeme
+                );
+            }
+            if self.items.contains_key(&alias.lexeme) {
+                bail!(
+                    "Import of '{}' conflicts with the item '{}' declared in this module",
+                    global,
+                    alias.lexeme
+                );
+            }
+            self.uses.push(Use {
+                label: alias.lexeme.clone(),
+                line: alias.line,
+                used: false,
+            });
+            let inner = module.modules.get(&name.lexeme);
+            if self.import(&alias.lexeme, &global, inner, false)? {
+                bindings.push((alias.lexeme.clone(), global));
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Binds `name` to `global` for the latest entry of `uses`, false when an existing import
+    /// already provides it.
+    fn import(
+        &mut self,
+        name: &str,
+        global: &str,
+        module: Option<&Module>,
+        glob: bool,
+    ) -> anyhow::Result<bool> {
+        if let Some(existing) = self.imports.get(name) {
+            if existing.global == global || (glob && !existing.glob) {
+                return Ok(false);
+            }
+            if glob || !existing.glob {
+                bail!(
+                    "Conflicting imports of '{}' from '{}' and '{}'",
+                    name,
+                    existing.global,
+                    global
+                );
+            }
+        }
+        let import = Import {
+            global: global.to_string(),
+            module: module.cloned(),
+            glob,
+            use_index: self.uses.len() - 1,
+        };
+        self.imports.insert(name.to_string(), import);
+        Ok(true)
+    }
+
+    /// Warnings for the parts of `use` declarations none of whose names were referred to.
+    pub fn unused_imports(&self) -> Vec<String> {
+        let file = if self.path.is_empty() {
+            String::new()
+        } else {
+            format!(" in module '{}'", self.path)
+        };
+        self.uses
+            .iter()
+            .filter(|u| !u.used)
+            .map(|u| format!("Unused import '{}'{} (line {})", u.label, file, u.line))
+            .collect()
+    }
+
+    /// Reads, lexes and expands the macros of the module `name` declared by the innermost module
+    /// of `stack`, from the declaring file's directory or the first of the script's search paths
+    /// that has it.
+    pub fn load(stack: &[Module], name: &str) -> anyhow::Result<(Module, Expanded)> {
+        let parent = stack.last().expect("Module stack always holds the script");
+        if parent.modules.contains_key(name) {
+            bail!("Module '{}' is already declared", parent.global(name));
+        }
+        let file_name = format!("{}.cor", name);
+        let mut tried: Vec<PathBuf> = Vec::new();
+        for dir in std::iter::once(&parent.dir).chain(&stack[0].search) {
+            let file = dir.join(&file_name);
+            if !tried.contains(&file) {
+                tried.push(file);
+            }
+        }
+        let mut found = None;
+        for file in &tried {
+            match std::fs::read_to_string(file) {
+                Ok(source) => {
+                    found = Some((file.clone(), source));
+                    break;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => bail!(
+                    "Unable to read module '{}' from {}: {}",
+                    name,
+                    file.display(),
+                    e
+                ),
+            }
+        }
+        let Some((file, source)) = found else {
+            let tried: Vec<String> = tried
+                .iter()
+                .map(|file| format!("\n    {}", file.display()))
+                .collect();
+            bail!("Module '{}' not found, tried:{}", name, tried.concat());
+        };
+        if stack.iter().any(|module| module.file == canonical(&file)) {
+            bail!(
+                "Module '{}' is already being loaded from {}, mod declarations can't be circular",
+                name,
+                file.display()
+            );
+        }
+        let lexed = Lexer::scan_tokens(source.trim());
+        if !lexed.errors.is_empty() {
+            bail!("LEX ERROR(S) in {}: {:?}", file.display(), lexed.errors);
+        }
+        let expanded = macros::expand(&lexed.tokens)?;
+        let module = Module {
+            path: parent.global(name),
+            dir: file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            file: canonical(&file),
+            items: top_level_items(&expanded.tokens),
+            ..Module::default()
+        };
+        Ok((module, expanded))
+    }
+}
+
+fn canonical(file: &Path) -> PathBuf {
+    std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+}
+
+/// Names declared outside of any braces, a clause of a function counts as `pub` if any is.
+pub fn top_level_items(tokens: &[Token]) -> HashMap<String, bool> {
+    let mut items: HashMap<String, bool> = HashMap::new();
+    let mut depth = 0usize;
+    let mut public = false;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.ty {
+            TokenType::LeftBrace => depth += 1,
+            TokenType::RightBrace => depth = depth.saturating_sub(1),
+            TokenType::Pub if depth == 0 => {
+                public = true;
+                continue;
+            }
+            TokenType::Let
+            | TokenType::Fn
+            | TokenType::Struct
+            | TokenType::Enum
+            | TokenType::Trait
+            | TokenType::Mod
+                if depth == 0 =>
+            {
+                // fn* name
+                let name = tokens[i + 1..].iter().find(|t| t.ty != TokenType::Star);
+                if let Some(name) = name.filter(|t| t.ty == TokenType::Ident) {
+                    *items.entry(name.lexeme.clone()).or_default() |= public;
+                }
+            }
+            _ => {}
+        }
+        public = false;
+    }
+    items
+}
@@ -0,0 +1,442 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use anyhow::bail;
+
+use crate::{
+    attr::Attributes,
+    value::{
+        Caller, EnumInstance, Function, Iter, NativeFunction, Object, StructInstance, Trait, Value,
+    },
+};
+
+/// Native functions defined as globals in every VM and Interpreter.
+pub const NATIVES: &[NativeFunction] = &[
+    NativeFunction {
+        name: "parse_number",
+        arity: 1,
+        function: parse_number,
+    },
+    NativeFunction {
+        name: "type_of",
+        arity: 1,
+        function: type_of,
+    },
+    NativeFunction {
+        name: "len",
+        arity: 1,
+        function: len,
+    },
+    NativeFunction {
+        name: "is_variant",
+        arity: 2,
+        function: is_variant,
+    },
+    NativeFunction {
+        name: "impls",
+        arity: 2,
+        function: impls,
+    },
+    NativeFunction {
+        name: "iter",
+        arity: 1,
+        function: iter,
+    },
+    NativeFunction {
+        name: "next",
+        arity: 1,
+        function: next,
+    },
+    NativeFunction {
+        name: "map",
+        arity: 2,
+        function: map,
+    },
+    NativeFunction {
+        name: "filter",
+        arity: 2,
+        function: filter,
+    },
+    NativeFunction {
+        name: "take",
+        arity: 2,
+        function: take,
+    },
+    NativeFunction {
+        name: "zip",
+        arity: 2,
+        function: zip,
+    },
+    NativeFunction {
+        name: "enumerate",
+        arity: 1,
+        function: enumerate,
+    },
+    NativeFunction {
+        name: "collect",
+        arity: 1,
+        function: collect,
+    },
+    NativeFunction {
+        name: "doc",
+        arity: 1,
+        function: doc,
+    },
+    NativeFunction {
+        name: "spec",
+        arity: 1,
+        function: spec,
+    },
+];
+
+/// Operators struct instances can overload by implementing the trait of the same name, each
+/// trait has a single method. The traits are defined as globals along with the natives.
+///
+/// `a + b` calls `a.add(b)`, `a == b` calls `a.eq(b)` and `a[key]` calls `a.index(key)`. Ord's
+/// `lt` gives `a < b` as `a.lt(b)` and `a > b` as `b.lt(a)`, `<=` and `>=` are their negation.
+/// `eq` and `lt` must return a Boolean. An instance implementing Display shows what its
+/// `to_string` returns when printed, inside other values or added to a string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Eq,
+    Ord,
+    Index,
+    Display,
+}
+
+static OPERATOR_TRAITS: LazyLock<Vec<Arc<Trait>>> = LazyLock::new(|| {
+    Operator::ALL
+        .iter()
+        .map(|op| {
+            Arc::new(Trait {
+                name: op.trait_name().into(),
+                required: vec![op.method().into()],
+                defaults: HashMap::new(),
+                attributes: Attributes::default(),
+            })
+        })
+        .collect()
+});
+
+impl Operator {
+    pub const ALL: [Operator; 5] = [
+        Operator::Add,
+        Operator::Eq,
+        Operator::Ord,
+        Operator::Index,
+        Operator::Display,
+    ];
+
+    pub const fn trait_name(self) -> &'static str {
+        match self {
+            Operator::Add => "Add",
+            Operator::Eq => "Eq",
+            Operator::Ord => "Ord",
+            Operator::Index => "Index",
+            Operator::Display => "Display",
+        }
+    }
+
+    pub const fn method(self) -> &'static str {
+        match self {
+            Operator::Add => "add",
+            Operator::Eq => "eq",
+            Operator::Ord => "lt",
+            Operator::Index => "index",
+            Operator::Display => "to_string",
+        }
+    }
+
+    /// Whether `name` is the name of an operator trait, which scripts can't declare again.
+    pub fn is_trait_name(name: &str) -> bool {
+        Operator::ALL.iter().any(|op| op.trait_name() == name)
+    }
+
+    pub fn tr(self) -> &'static Arc<Trait> {
+        &OPERATOR_TRAITS[self as usize]
+    }
+
+    /// Method overloading this operator for `value`, if it is a struct instance implementing it.
+    pub fn method_for(self, value: &Value) -> Option<Arc<Function>> {
+        value.trait_method(self.tr(), self.method())
+    }
+
+    /// Errors if `result` of this operator's method isn't a value the operator can give.
+    pub fn check_result(self, result: Value) -> anyhow::Result<Value> {
+        match (self, &result) {
+            (Operator::Eq | Operator::Ord, Value::Boolean(_)) => Ok(result),
+            (Operator::Eq | Operator::Ord, _) => bail!(
+                "{} must return a Boolean, got: {}",
+                self.method(),
+                result.type_string()
+            ),
+            _ => Ok(result),
+        }
+    }
+
+    /// Name and value of every operator trait, to define as globals.
+    pub fn globals() -> impl Iterator<Item = (&'static str, Value)> {
+        Operator::ALL
+            .iter()
+            .map(|op| (op.trait_name(), Value::Obj(Object::Trait(op.tr().clone()))))
+    }
+}
+
+/// `value` with every struct instance implementing Display replaced by what its `to_string`
+/// returns, including instances in lists, maps, options, results and fields. Formatting the result
+/// gives what `print` shows.
+pub fn displayed(caller: &mut dyn Caller, value: &Value) -> anyhow::Result<Value> {
+    let mut each = |values: &[Value]| -> anyhow::Result<Vec<Value>> {
+        values.iter().map(|v| displayed(caller, v)).collect()
+    };
+    let Value::Obj(object) = value else {
+        return Ok(value.clone());
+    };
+    Ok(match object {
+        Object::Instance(instance) => match Operator::Display.method_for(value) {
+            Some(method) => {
+                let function = Value::Obj(Object::Function(method));
+                let shown = caller.call(&function, std::slice::from_ref(value))?;
+                return displayed(caller, &shown);
+            }
+            None => {
+                let values = instance.values().clone();
+                Value::Obj(Object::Instance(Arc::new(StructInstance {
+                    ty: instance.ty.clone(),
+                    values: RwLock::new(each(&values)?),
+                })))
+            }
+        },
+        Object::EnumInstance(instance) => {
+            Value::Obj(Object::EnumInstance(Arc::new(EnumInstance {
+                ty: instance.ty.clone(),
+                variant: instance.variant,
+                values: each(&instance.values)?,
+            })))
+        }
+        Object::List(list) => {
+            let values = list.values().clone();
+            Value::list(each(&values)?)
+        }
+        Object::Map(map) => {
+            let entries = map
+                .entries()
+                .into_iter()
+                .map(|(key, value)| Ok((key, displayed(caller, &value)?)))
+                .collect::<anyhow::Result<_>>()?;
+            Value::map(entries)?
+        }
+        Object::Option(Some(inner)) => Value::some(displayed(caller, inner)?),
+        Object::Result(Ok(inner)) => Value::ok(displayed(caller, inner)?),
+        Object::Result(Err(inner)) => Value::err(displayed(caller, inner)?),
+        _ => value.clone(),
+    })
+}
+
+/// Operands of `a + b` once a struct instance added to a string is replaced by what it displays
+/// as, they are returned as is unless exactly one is a string and the other implements Display.
+pub fn concat_operands(
+    caller: &mut dyn Caller,
+    a: Value,
+    b: Value,
+) -> anyhow::Result<(Value, Value)> {
+    let is_string = |v: &Value| matches!(v, Value::Obj(Object::String(_)));
+    let shows = |v: &Value| Operator::Display.method_for(v).is_some();
+    let mut shown = |v: &Value| -> anyhow::Result<Value> {
+        Ok(Value::Obj(Object::String(
+            displayed(caller, v)?.to_string(),
+        )))
+    };
+    if is_string(&a) && shows(&b) {
+        Ok((a, shown(&b)?))
+    } else if shows(&a) && is_string(&b) {
+        Ok((shown(&a)?, b))
+    } else {
+        Ok((a, b))
+    }
+}
+
+/// parse_number(string) => Ok(number) or Err(message) if the string isn't a number.
+fn parse_number(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    let string = args[0].as_string()?;
+    match string.trim().parse::<f64>() {
+        Ok(n) => Ok(Value::ok(Value::Number(n))),
+        Err(_) => Ok(Value::err(Value::Obj(Object::String(format!(
+            "Unable to parse '{}' as a Number",
+            string
+        ))))),
+    }
+}
+
+/// len(value) => number of elements in a List, entries in a Map, characters in a String or
+/// integers in a Range.
+fn len(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    let len = match &args[0] {
+        Value::Obj(Object::List(list)) => list.values().len() as u64,
+        Value::Obj(Object::Map(map)) => map.len() as u64,
+        Value::Obj(Object::String(string)) => string.chars().count() as u64,
+        Value::Obj(Object::Range(range)) => range.size(),
+        other => bail!(
+            "len expected a List, Map, String or Range but got: {}",
+            other.type_string()
+        ),
+    };
+    Ok(Value::Number(len as f64))
+}
+
+/// type_of(value) => name of the value's type, enum instances give the name of their enum.
+fn type_of(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    Ok(Value::Obj(Object::String(args[0].type_string())))
+}
+
+/// is_variant(value, Enum::Variant) => true if value was constructed from that variant.
+fn is_variant(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    let (ty, variant) = match &args[1] {
+        Value::Obj(Object::VariantConstructor(ty, variant)) => (ty, *variant),
+        Value::Obj(Object::EnumInstance(instance)) => (&instance.ty, instance.variant),
+        other => bail!(
+            "is_variant expected an enum variant but got: {}",
+            other.type_string()
+        ),
+    };
+    let result = match &args[0] {
+        Value::Obj(Object::EnumInstance(instance)) => instance.is_variant(ty, variant),
+        _ => false,
+    };
+    Ok(Value::Boolean(result))
+}
+
+/// impls(value, Trait) => true if the struct of value, or value itself when it is a struct,
+/// implements the trait directly or through a parent.
+fn impls(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    let Value::Obj(Object::Trait(tr)) = &args[1] else {
+        bail!("impls expected a trait but got: {}", args[1].type_string());
+    };
+    let result = match &args[0] {
+        Value::Obj(Object::Instance(instance)) => instance.ty.implements(tr),
+        Value::Obj(Object::Struct(ty)) => ty.implements(tr),
+        _ => false,
+    };
+    Ok(Value::Boolean(result))
+}
+
+/// doc(item) => Some(text) of the @doc attribute on a function, method, struct, enum or trait,
+/// or None when it has none.
+fn doc(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    let attributes = attributes("doc", &args[0])?;
+    Ok(match &attributes.doc {
+        Some(doc) => Value::some(Value::Obj(Object::String(doc.clone()))),
+        None => Value::none(),
+    })
+}
+
+/// spec(function) => Some(signature) of the @spec attribute on a function or method, or None
+/// when it has none.
+fn spec(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    let attributes = attributes("spec", &args[0])?;
+    Ok(match &attributes.spec {
+        Some(spec) => Value::some(Value::Obj(Object::String(spec.to_string()))),
+        None => Value::none(),
+    })
+}
+
+fn attributes<'a>(native: &str, value: &'a Value) -> anyhow::Result<&'a Attributes> {
+    Ok(match value {
+        Value::Obj(Object::Function(function)) => &function.attributes,
+        Value::Obj(Object::BoundMethod(method)) => &method.method.attributes,
+        Value::Obj(Object::Struct(ty)) => &ty.attributes,
+        Value::Obj(Object::Enum(ty)) => &ty.attributes,
+        Value::Obj(Object::Trait(tr)) => &tr.attributes,
+        other => bail!(
+            "{} expected a Function, Struct, Enum or Trait but got: {}",
+            native,
+            other.type_string()
+        ),
+    })
+}
+
+/// iter(iterable) => lazy Iterator over a List, Range, Iterator, Generator or struct instance
+/// with a next() method.
+fn iter(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    Ok(Value::Obj(Object::Iterator(Iter::from_value(&args[0])?)))
+}
+
+/// next(iterator) => Some(value), or None once the iterator or generator is done.
+fn next(caller: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    let value = match &args[0] {
+        Value::Obj(Object::Iterator(iter)) => iter.next(caller)?,
+        Value::Obj(Object::Generator(generator)) => caller.resume(generator)?,
+        other => bail!(
+            "next expected an Iterator or Generator but got: {}",
+            other.type_string()
+        ),
+    };
+    Ok(match value {
+        Some(value) => Value::some(value),
+        None => Value::none(),
+    })
+}
+
+/// map(iterable, f) => Iterator of f(value) for each value.
+fn map(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    Ok(iterator(Iter::Map {
+        source: Iter::from_value(&args[0])?,
+        f: args[1].clone(),
+    }))
+}
+
+/// filter(iterable, predicate) => Iterator of the values predicate(value) is truthy for.
+fn filter(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    Ok(iterator(Iter::Filter {
+        source: Iter::from_value(&args[0])?,
+        predicate: args[1].clone(),
+    }))
+}
+
+/// take(iterable, n) => Iterator of at most the first n values.
+fn take(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    let n = match args[1].as_integer() {
+        Ok(n) if n >= 0 => n as usize,
+        _ => bail!(
+            "take expected a non-negative integer count but got: {}",
+            args[1]
+        ),
+    };
+    Ok(iterator(Iter::Take {
+        source: Iter::from_value(&args[0])?,
+        remaining: RwLock::new(n),
+    }))
+}
+
+/// zip(left, right) => Iterator of [left value, right value] until either runs out.
+fn zip(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    Ok(iterator(Iter::Zip(
+        Iter::from_value(&args[0])?,
+        Iter::from_value(&args[1])?,
+    )))
+}
+
+/// enumerate(iterable) => Iterator of [index, value].
+fn enumerate(_: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    Ok(iterator(Iter::Enumerate {
+        source: Iter::from_value(&args[0])?,
+        index: RwLock::new(0),
+    }))
+}
+
+/// collect(iterable) => List of every remaining value.
+fn collect(caller: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+    let iter = Iter::from_value(&args[0])?;
+    let mut values = Vec::new();
+    while let Some(value) = iter.next(caller)? {
+        values.push(value);
+    }
+    Ok(Value::list(values))
+}
+
+fn iterator(iter: Iter) -> Value {
+    Value::Obj(Object::Iterator(Arc::new(iter)))
+}
@@ -1,21 +1,67 @@
 use crate::{
-    ast::{AstWalkError, AstWalker, Expr, Stmt},
-    lex::Cursor,
-    value::{Token, TokenType, Value},
+    ast::{
+        AstWalkError, AstWalker, ComprehensionClause, Expr, FnClause, Method, RequiredMethod, Stmt,
+    },
+    attr::{Attributes, Type},
+    lex::{tokens_to_source, Cursor, Lexer},
+    macros::{self, Expansion},
+    module::{top_level_items, Module},
+    native::Operator,
+    value::{check_params, Param, Token, TokenType, Value, VariantFields},
 };
 use anyhow::*;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct Parser {
     cursor: Cursor,
     tokens: Vec<Token>,
+    /// Macro expansions in `tokens`
+    expansions: Vec<Expansion>,
+    /// Number of function bodies being parsed, `return` is only valid inside one
+    function_depth: usize,
+    /// Whether the innermost function body being parsed is a `fn*` generator's
+    in_generator: bool,
+    /// Name of the struct whose methods are being parsed and whether it has a parent
+    current_struct: Option<(Token, bool)>,
+    /// Script being parsed followed by the modules whose files are being parsed into it
+    modules: Vec<Module>,
+    /// Attributes parsed for the item declaration that follows them
+    attributes: Attributes,
+    /// Errors of the declarations that failed to parse, which are skipped
+    errors: Vec<String>,
 }
 
 impl Parser {
     pub fn parse(tokens: &[Token]) -> anyhow::Result<Vec<Stmt>> {
+        Self::parse_module(tokens, Module::default())
+    }
+
+    /// Parses the script at `path`, modules are also searched for in `search_paths`.
+    pub fn parse_file(path: &Path, search_paths: &[PathBuf]) -> anyhow::Result<Vec<Stmt>> {
+        let source = std::fs::read_to_string(path)?;
+        let lexed = Lexer::scan_tokens(source.trim());
+        if !lexed.errors.is_empty() {
+            bail!("LEX ERROR(S): {:?}", lexed.errors);
+        }
+        Self::parse_module(&lexed.tokens, Module::script(path, search_paths))
+    }
+
+    fn parse_module(tokens: &[Token], script: Module) -> anyhow::Result<Vec<Stmt>> {
+        let expanded = macros::expand(tokens)?;
         let mut p = Self {
             cursor: Cursor::new(),
-            tokens: tokens.to_vec(),
+            function_depth: 0,
+            in_generator: false,
+            current_struct: None,
+            modules: vec![Module {
+                items: top_level_items(&expanded.tokens),
+                ..script
+            }],
+            tokens: expanded.tokens,
+            expansions: expanded.expansions,
+            attributes: Attributes::default(),
+            errors: Vec::new(),
         };
         let mut statements = Vec::new();
         while !p.is_eof() {
@@ -23,11 +69,22 @@ impl Parser {
                 statements.push(stmt);
             }
         }
+        if !p.errors.is_empty() {
+            bail!(
+                "{} parse error(s):\n{}",
+                p.errors.len(),
+                p.errors.join("\n")
+            );
+        }
         Ok(statements)
     }
 
     /// advance cursor to the next expression
     fn synchronize(&mut self) {
+        // an error at the end of the input leaves nothing to skip
+        if self.is_eof() {
+            return;
+        }
         self.advance(1);
         while !self.is_eof() {
             if let TokenType::Semicolon = self.prev().ty {
@@ -35,37 +92,321 @@ impl Parser {
             }
             match self.peek().ty {
                 TokenType::Struct
+                | TokenType::Enum
                 | TokenType::Fn
                 | TokenType::Let
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
                 | TokenType::Print
+                | TokenType::Try
+                | TokenType::Throw
+                | TokenType::Mod
+                | TokenType::Pub
+                | TokenType::Use
+                | TokenType::At
                 | TokenType::Return => break,
                 _ => self.advance(1),
             }
         }
     }
 
-    // Option as parsing an invalid declaration just results in that declaration getting ignored.
-    // we should probably do some logging or error reporting at a higher level so invalid
-    // declarations can be known about and arent completely silently ignored.
+    /// None when the declaration fails to parse, its error is recorded and parsing resumes after
+    /// it so the errors of the rest of the file are found too.
     fn declaration(&mut self) -> Option<Stmt> {
-        let result = if let TokenType::Let = self.peek().ty {
-            self.advance(1);
-            self.let_statement()
-        } else {
-            self.statement()
+        let result = match self.peek().ty {
+            TokenType::Let => {
+                self.advance(1);
+                self.let_statement()
+            }
+            TokenType::Enum => {
+                self.advance(1);
+                self.enum_statement()
+            }
+            TokenType::Fn => {
+                self.advance(1);
+                self.fn_statement()
+            }
+            TokenType::Struct => {
+                self.advance(1);
+                self.struct_statement()
+            }
+            TokenType::Trait => {
+                self.advance(1);
+                self.trait_statement()
+            }
+            TokenType::Impl => {
+                self.advance(1);
+                self.impl_statement()
+            }
+            TokenType::Mod => {
+                self.advance(1);
+                self.mod_statement()
+            }
+            TokenType::Pub => {
+                self.advance(1);
+                self.pub_statement()
+            }
+            TokenType::Use => {
+                self.advance(1);
+                self.use_statement()
+            }
+            TokenType::At => self.attributed_statement(),
+            _ => self.statement(),
         };
         match result {
             anyhow::Result::Ok(stmt) => Some(stmt),
-            Err(_) => {
+            Err(err) => {
+                let origin = macros::origin(&self.expansions, self.cursor.i)
+                    .or_else(|| macros::origin(&self.expansions, self.cursor.i.checked_sub(1)?));
+                self.errors.push(match origin {
+                    Some(origin) => format!("{} ({})", err, origin),
+                    None => err.to_string(),
+                });
+                // attributes of an item that failed to parse don't carry over to the next
+                self.attributes = Attributes::default();
                 self.synchronize();
                 None
             }
         }
     }
 
+    /// mod name; parses the module's file into the statements of its body.
+    fn mod_statement(&mut self) -> anyhow::Result<Stmt> {
+        let name = self.consume(TokenType::Ident, "Expected module name after 'mod'")?;
+        let loaded = if self.function_depth > 0 {
+            Err(anyhow!("Modules can only be declared at the top level"))
+        } else {
+            Module::load(&self.modules, &name.lexeme)
+        };
+        // fails before the ';' so synchronizing resumes at the next statement
+        let (module, expanded) = loaded.map_err(|e| AstWalkError::ParseError {
+            token: name.clone(),
+            message: e.to_string(),
+        })?;
+        self.consume(TokenType::Semicolon, "Expected ';' after module name")?;
+        let (path, file, items) = (
+            module.path.clone(),
+            module.file.clone(),
+            module.items.clone(),
+        );
+        let enclosing_tokens = std::mem::replace(&mut self.tokens, expanded.tokens);
+        let enclosing_expansions = std::mem::replace(&mut self.expansions, expanded.expansions);
+        let enclosing_cursor = std::mem::take(&mut self.cursor);
+        self.modules.push(module);
+        let mut body = Vec::new();
+        while !self.is_eof() {
+            if let Some(stmt) = self.declaration() {
+                body.push(stmt);
+            }
+        }
+        let module = self.modules.pop().expect("Module pushed above");
+        self.tokens = enclosing_tokens;
+        self.expansions = enclosing_expansions;
+        self.cursor = enclosing_cursor;
+        self.modules
+            .last_mut()
+            .expect("Module stack always holds the script")
+            .modules
+            .insert(name.lexeme.clone(), module);
+        Ok(Stmt::Mod {
+            name,
+            path,
+            file,
+            items,
+            body,
+        })
+    }
+
+    /// pub item; whether items are `pub` is collected when their module is loaded.
+    fn pub_statement(&mut self) -> anyhow::Result<Stmt> {
+        let keyword = self.prev().clone();
+        let message = if self.function_depth > 0 {
+            "'pub' is only allowed on top-level items"
+        } else if !matches!(
+            self.peek().ty,
+            TokenType::Let
+                | TokenType::Fn
+                | TokenType::Struct
+                | TokenType::Enum
+                | TokenType::Trait
+                | TokenType::Mod
+        ) {
+            "Expected an item after 'pub'"
+        } else {
+            return self
+                .declaration()
+                .ok_or_else(|| anyhow!("Invalid item after 'pub'"));
+        };
+        bail!(
+            "{}",
+            AstWalkError::ParseError {
+                token: keyword,
+                message: message.into()
+            }
+        )
+    }
+
+    /// @doc "text" @spec name(types) -> type, followed by the item they are put on.
+    fn attributed_statement(&mut self) -> anyhow::Result<Stmt> {
+        let at = self.peek().clone();
+        self.attributes = self.attributes()?;
+        let item = match self.peek().ty {
+            TokenType::Pub => self.tokens.get(self.cursor.i + 1).map(|t| t.ty),
+            ty => Some(ty),
+        };
+        if !matches!(
+            item,
+            Some(TokenType::Fn | TokenType::Struct | TokenType::Enum | TokenType::Trait)
+        ) {
+            self.attributes = Attributes::default();
+            bail!(
+                "{}",
+                AstWalkError::ParseError {
+                    token: at,
+                    message: "Attributes can only precede a fn, struct, enum or trait".into()
+                }
+            )
+        }
+        self.declaration()
+            .ok_or_else(|| anyhow!("Invalid item after attributes"))
+    }
+
+    /// Attributes starting at the current token, if it is an '@'.
+    fn attributes(&mut self) -> anyhow::Result<Attributes> {
+        if self.peek().ty != TokenType::At {
+            return Ok(Attributes::default());
+        }
+        let (attributes, end) = Attributes::parse(&self.tokens, self.cursor.i).map_err(|e| {
+            AstWalkError::ParseError {
+                token: self.peek().clone(),
+                message: e.to_string(),
+            }
+        })?;
+        self.advance(end - self.cursor.i);
+        Ok(attributes)
+    }
+
+    /// Attributes parsed for the declaration being parsed, taken before its body so items
+    /// declared inside it don't get them.
+    fn take_attributes(&mut self) -> Attributes {
+        std::mem::take(&mut self.attributes)
+    }
+
+    /// Checks `attributes` fit the `kind` declaration `name`, after its body so an error doesn't
+    /// make synchronize resume in the middle of it.
+    fn check_attributes(attributes: &Attributes, kind: &str, name: &Token) -> anyhow::Result<()> {
+        attributes
+            .check(kind, &name.lexeme)
+            .map_err(|e| AstWalkError::ParseError {
+                token: name.clone(),
+                message: e.to_string(),
+            })?;
+        Ok(())
+    }
+
+    /// use module::item; use module::{item, item as alias}; use module::*;
+    fn use_statement(&mut self) -> anyhow::Result<Stmt> {
+        let keyword = self.prev().clone();
+        if self.function_depth > 0 {
+            bail!(
+                "{}",
+                AstWalkError::ParseError {
+                    token: keyword,
+                    message: "'use' is only allowed at the top level".into()
+                }
+            )
+        }
+        let mut path = vec![self.consume(TokenType::Ident, "Expected module name after 'use'")?];
+        self.consume(TokenType::DoubleColon, "Expected '::' after module name")?;
+        let names = loop {
+            match self.peek().ty {
+                TokenType::Star => {
+                    self.advance(1);
+                    break None;
+                }
+                TokenType::LeftBrace => {
+                    self.advance(1);
+                    let mut names = Vec::new();
+                    while self.peek().ty != TokenType::RightBrace {
+                        names.push(self.use_name()?);
+                        if self.peek().ty != TokenType::Comma {
+                            break;
+                        }
+                        self.advance(1);
+                    }
+                    self.consume(TokenType::RightBrace, "Expected '}' after imported names")?;
+                    break Some(names);
+                }
+                _ => {
+                    let name = self.use_name()?;
+                    if self.peek().ty != TokenType::DoubleColon {
+                        break Some(vec![name]);
+                    }
+                    self.advance(1);
+                    path.push(name.0);
+                }
+            }
+        };
+        let module = self
+            .modules
+            .last_mut()
+            .expect("Module stack always holds the script");
+        // fails before the ';' so synchronizing resumes at the next statement
+        let imports =
+            module
+                .use_items(&path, names.as_deref())
+                .map_err(|e| AstWalkError::ParseError {
+                    token: keyword.clone(),
+                    message: e.to_string(),
+                })?;
+        self.consume(TokenType::Semicolon, "Expected ';' after use declaration")?;
+        Ok(Stmt::Use { keyword, imports })
+    }
+
+    /// name or name as alias, in a use statement
+    fn use_name(&mut self) -> anyhow::Result<(Token, Token)> {
+        let name = self.consume(TokenType::Ident, "Expected name to import")?;
+        if self.peek().ty != TokenType::As {
+            return Ok((name.clone(), name));
+        }
+        self.advance(1);
+        let alias = self.consume(TokenType::Ident, "Expected alias after 'as'")?;
+        Ok((name, alias))
+    }
+
+    /// module::item, or module::inner::item, when `name` is a module declared in, or imported
+    /// into, the module being parsed. The path up to the item is consumed and the item's global
+    /// returned, a '::' after it is left for enum variants.
+    fn module_path(&mut self, name: &Token) -> anyhow::Result<Option<Token>> {
+        let declared = self.modules.last_mut().and_then(|m| m.module(&name.lexeme));
+        let Some(mut module) = declared else {
+            return Ok(None);
+        };
+        loop {
+            self.advance(1);
+            let item = self.consume(TokenType::Ident, "Expected item name after '::'")?;
+            let global = module
+                .item(&item.lexeme)
+                .map_err(|e| AstWalkError::ParseError {
+                    token: item.clone(),
+                    message: e.to_string(),
+                })?;
+            match module.modules.get(&item.lexeme) {
+                Some(inner) if self.peek().ty == TokenType::DoubleColon => {
+                    let inner = inner.clone();
+                    module = inner;
+                }
+                _ => {
+                    return Ok(Some(Token {
+                        lexeme: global,
+                        ..item
+                    }))
+                }
+            }
+        }
+    }
+
     fn let_statement(&mut self) -> anyhow::Result<Stmt> {
         if let TokenType::Ident = self.peek().ty {
             let name = self.peek().clone();
@@ -100,20 +441,597 @@ impl Parser {
         }
     }
 
+    fn enum_statement(&mut self) -> anyhow::Result<Stmt> {
+        let name = self.consume(TokenType::Ident, "Expected name for enum declaration")?;
+        let attributes = self.take_attributes();
+        self.consume(TokenType::LeftBrace, "Expected '{' after enum name")?;
+        let mut variants: Vec<(Token, VariantFields)> = Vec::new();
+        while self.peek().ty != TokenType::RightBrace {
+            let variant = self.consume(TokenType::Ident, "Expected variant name")?;
+            if variants.iter().any(|(v, _)| v.lexeme == variant.lexeme) {
+                bail!(
+                    "{}",
+                    AstWalkError::ParseError {
+                        message: format!(
+                            "Duplicate variant '{}' in enum {}",
+                            variant.lexeme, name.lexeme
+                        ),
+                        token: variant,
+                    }
+                )
+            }
+            let fields = match self.peek().ty {
+                TokenType::LeftParen => {
+                    self.advance(1);
+                    VariantFields::Tuple(self.variant_fields(TokenType::RightParen)?)
+                }
+                TokenType::LeftBrace => {
+                    self.advance(1);
+                    VariantFields::Struct(self.variant_fields(TokenType::RightBrace)?)
+                }
+                _ => VariantFields::Unit,
+            };
+            variants.push((variant, fields));
+            if self.peek().ty != TokenType::Comma {
+                break;
+            }
+            self.advance(1);
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after enum variants")?;
+        Self::check_attributes(&attributes, "enum", &name)?;
+        Ok(Stmt::Enum {
+            name,
+            variants,
+            attributes,
+        })
+    }
+
+    fn fn_statement(&mut self) -> anyhow::Result<Stmt> {
+        let generator = self.generator_star();
+        let name = self.consume(TokenType::Ident, "Expected function name after 'fn'")?;
+        let attributes = self.take_attributes();
+        let clauses = self.fn_clauses(&name, generator)?;
+        Self::check_attributes(&attributes, "fn", &name)?;
+        Ok(Stmt::Function {
+            name,
+            clauses,
+            attributes,
+        })
+    }
+
+    /// Consumes the `*` of a `fn*` generator declaration, if there is one.
+    fn generator_star(&mut self) -> bool {
+        let generator = self.peek().ty == TokenType::Star;
+        if generator {
+            self.advance(1);
+        }
+        generator
+    }
+
+    /// Clauses of the function `name`, whose name token was just consumed. Further clauses have
+    /// to be declared with the same `fn` or `fn*`.
+    fn fn_clauses(&mut self, name: &Token, generator: bool) -> anyhow::Result<Vec<FnClause>> {
+        let star = usize::from(generator);
+        let mut clauses = vec![self.fn_clause(generator)?];
+        while self.peek().ty == TokenType::Fn
+            && (!generator
+                || self.tokens.get(self.cursor.i + 1).map(|t| t.ty) == Some(TokenType::Star))
+            && self
+                .tokens
+                .get(self.cursor.i + 1 + star)
+                .is_some_and(|t| t.ty == TokenType::Ident && t.lexeme == name.lexeme)
+        {
+            self.advance(2 + star);
+            clauses.push(self.fn_clause(generator)?);
+        }
+        Ok(clauses)
+    }
+
+    /// struct Name : Parent { fields, fn method(self) { ... } }
+    fn struct_statement(&mut self) -> anyhow::Result<Stmt> {
+        let name = self.consume(TokenType::Ident, "Expected name for struct declaration")?;
+        let attributes = self.take_attributes();
+        let parent = if self.peek().ty == TokenType::Colon {
+            self.advance(1);
+            Some(self.consume(TokenType::Ident, "Expected parent struct name after ':'")?)
+        } else {
+            None
+        };
+        self.consume(TokenType::LeftBrace, "Expected '{' after struct name")?;
+        let enclosing = self
+            .current_struct
+            .replace((name.clone(), parent.is_some()));
+        let stmt = self.struct_body(name.clone(), parent, attributes.clone());
+        self.current_struct = enclosing;
+        let stmt = stmt?;
+        Self::check_attributes(&attributes, "struct", &name)?;
+        Ok(stmt)
+    }
+
+    /// trait Name { fn required(self); fn provided(self) { ... } }
+    fn trait_statement(&mut self) -> anyhow::Result<Stmt> {
+        let name = self.consume(TokenType::Ident, "Expected name for trait declaration")?;
+        let attributes = self.take_attributes();
+        self.consume(TokenType::LeftBrace, "Expected '{' after trait name")?;
+        let enclosing = self.current_struct.replace((name.clone(), false));
+        let stmt = self.trait_body(name.clone(), attributes.clone());
+        self.current_struct = enclosing;
+        let stmt = stmt?;
+        Self::check_attributes(&attributes, "trait", &name)?;
+        if Operator::is_trait_name(&name.lexeme) {
+            bail!(
+                "{}",
+                AstWalkError::ParseError {
+                    message: format!("Cannot redeclare the operator trait '{}'", name.lexeme),
+                    token: name,
+                }
+            );
+        }
+        Ok(stmt)
+    }
+
+    fn trait_body(&mut self, name: Token, attributes: Attributes) -> anyhow::Result<Stmt> {
+        let mut required: Vec<RequiredMethod> = Vec::new();
+        let mut defaults: Vec<Method> = Vec::new();
+        while self.peek().ty != TokenType::RightBrace && !self.is_eof() {
+            let method_attributes = self.attributes()?;
+            self.consume(TokenType::Fn, "Expected method in trait")?;
+            let generator = self.generator_star();
+            let method = self.consume(TokenType::Ident, "Expected method name after 'fn'")?;
+            if required
+                .iter()
+                .map(|m| &m.name)
+                .chain(defaults.iter().map(|m| &m.name))
+                .any(|m| m.lexeme == method.lexeme)
+            {
+                bail!(
+                    "{}",
+                    AstWalkError::ParseError {
+                        token: method.clone(),
+                        message: format!(
+                            "Duplicate method '{}' in trait {}",
+                            method.lexeme, name.lexeme
+                        )
+                    }
+                )
+            }
+            if self.is_method_signature() {
+                Self::check_attributes(&method_attributes, "fn", &method)?;
+                // fn name(params);
+                let head_start = self.cursor.i - 1;
+                while self.peek().ty != TokenType::Semicolon {
+                    self.advance(1);
+                }
+                let signature = tokens_to_source(&self.tokens[head_start..self.cursor.i]);
+                self.advance(1);
+                required.push(RequiredMethod {
+                    name: method,
+                    signature,
+                    attributes: method_attributes,
+                });
+                continue;
+            }
+            defaults.push(self.method(method, generator, method_attributes)?);
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after trait body")?;
+        Ok(Stmt::Trait {
+            name,
+            required,
+            defaults,
+            attributes,
+        })
+    }
+
+    /// Clauses of the method whose name was just consumed.
+    fn method(
+        &mut self,
+        name: Token,
+        generator: bool,
+        attributes: Attributes,
+    ) -> anyhow::Result<Method> {
+        let clauses = self.fn_clauses(&name, generator)?;
+        Self::check_attributes(&attributes, "fn", &name)?;
+        Ok(Method {
+            name,
+            clauses,
+            attributes,
+        })
+    }
+
+    /// Whether the method head starting at the current '(' ends in a ';' rather than a body.
+    fn is_method_signature(&self) -> bool {
+        let mut depth = 0;
+        for token in &self.tokens[self.cursor.i..] {
+            match token.ty {
+                TokenType::LeftParen => depth += 1,
+                TokenType::RightParen => depth -= 1,
+                TokenType::Semicolon if depth == 0 => return true,
+                TokenType::LeftBrace | TokenType::Where | TokenType::Eof if depth == 0 => {
+                    return false
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// impl Trait for Struct { fn method(self) { ... } }
+    fn impl_statement(&mut self) -> anyhow::Result<Stmt> {
+        let trait_name = self.consume(TokenType::Ident, "Expected trait name after 'impl'")?;
+        self.consume(TokenType::For, "Expected 'for' after trait name")?;
+        let ty = self.consume(TokenType::Ident, "Expected struct name after 'for'")?;
+        self.consume(TokenType::LeftBrace, "Expected '{' after impl struct name")?;
+        let enclosing = self.current_struct.replace((ty.clone(), true));
+        let methods = self.impl_body(&ty);
+        self.current_struct = enclosing;
+        Ok(Stmt::Impl {
+            trait_name,
+            ty,
+            methods: methods?,
+        })
+    }
+
+    fn impl_body(&mut self, ty: &Token) -> anyhow::Result<Vec<Method>> {
+        let mut methods: Vec<Method> = Vec::new();
+        while self.peek().ty != TokenType::RightBrace && !self.is_eof() {
+            let attributes = self.attributes()?;
+            self.consume(TokenType::Fn, "Expected method in impl")?;
+            let generator = self.generator_star();
+            let method = self.consume(TokenType::Ident, "Expected method name after 'fn'")?;
+            if methods.iter().any(|m| m.name.lexeme == method.lexeme) {
+                bail!(
+                    "{}",
+                    AstWalkError::ParseError {
+                        token: method.clone(),
+                        message: format!(
+                            "Duplicate method '{}' in impl for {}",
+                            method.lexeme, ty.lexeme
+                        )
+                    }
+                )
+            }
+            methods.push(self.method(method, generator, attributes)?);
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after impl body")?;
+        Ok(methods)
+    }
+
+    /// Fields and methods of a struct up to its closing '}'.
+    fn struct_body(
+        &mut self,
+        name: Token,
+        parent: Option<Token>,
+        attributes: Attributes,
+    ) -> anyhow::Result<Stmt> {
+        let mut fields: Vec<Token> = Vec::new();
+        let mut field_types: Vec<Option<Type>> = Vec::new();
+        let mut methods: Vec<Method> = Vec::new();
+        while self.peek().ty != TokenType::RightBrace && !self.is_eof() {
+            if matches!(self.peek().ty, TokenType::Fn | TokenType::At) {
+                let method_attributes = self.attributes()?;
+                self.consume(TokenType::Fn, "Expected method after attributes")?;
+                let generator = self.generator_star();
+                let method = self.consume(TokenType::Ident, "Expected method name after 'fn'")?;
+                if methods.iter().any(|m| m.name.lexeme == method.lexeme) {
+                    bail!(
+                        "{}",
+                        AstWalkError::ParseError {
+                            token: method.clone(),
+                            message: format!(
+                                "Duplicate method '{}' in struct {}",
+                                method.lexeme, name.lexeme
+                            )
+                        }
+                    )
+                }
+                methods.push(self.method(method, generator, method_attributes)?);
+                continue;
+            }
+            let field = self.consume(TokenType::Ident, "Expected field or method in struct")?;
+            if fields.iter().any(|f| f.lexeme == field.lexeme) {
+                bail!(
+                    "{}",
+                    AstWalkError::ParseError {
+                        token: field.clone(),
+                        message: format!(
+                            "Duplicate field '{}' in struct {}",
+                            field.lexeme, name.lexeme
+                        )
+                    }
+                )
+            }
+            field_types.push(self.field_type()?);
+            fields.push(field);
+            if self.peek().ty == TokenType::Comma {
+                self.advance(1);
+            } else if !matches!(
+                self.peek().ty,
+                TokenType::RightBrace | TokenType::Fn | TokenType::At
+            ) {
+                bail!(
+                    "{}",
+                    AstWalkError::ParseError {
+                        token: self.peek().clone(),
+                        message: "Expected ',' after struct field".into()
+                    }
+                )
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after struct body")?;
+        Ok(Stmt::Struct {
+            name,
+            parent,
+            fields,
+            field_types,
+            methods,
+            attributes,
+        })
+    }
+
+    /// `: type` after a struct field, only used by `corrosion check`.
+    fn field_type(&mut self) -> anyhow::Result<Option<Type>> {
+        if self.peek().ty != TokenType::Colon {
+            return Ok(None);
+        }
+        self.advance(1);
+        let (ty, end) =
+            Type::parse(&self.tokens, self.cursor.i).map_err(|e| AstWalkError::ParseError {
+                token: self.peek().clone(),
+                message: e.to_string(),
+            })?;
+        self.advance(end - self.cursor.i);
+        Ok(Some(ty))
+    }
+
+    fn fn_clause(&mut self, generator: bool) -> anyhow::Result<FnClause> {
+        let head_start = self.cursor.i - 1;
+        self.consume(TokenType::LeftParen, "Expected '(' after function name")?;
+        let mut params = Vec::new();
+        let mut defaults = Vec::new();
+        while self.peek().ty != TokenType::RightParen {
+            let (param, default) = self.param()?;
+            params.push(param);
+            defaults.push(default);
+            if self.peek().ty != TokenType::Comma {
+                break;
+            }
+            self.advance(1);
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after parameters")?;
+        if let Err(message) = check_params(&params) {
+            bail!(
+                "{}",
+                AstWalkError::ParseError {
+                    token: self.prev().clone(),
+                    message
+                }
+            )
+        }
+        let guard = if self.peek().ty == TokenType::Where {
+            self.advance(1);
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        let signature = tokens_to_source(&self.tokens[head_start..self.cursor.i]);
+        self.consume(TokenType::LeftBrace, "Expected '{' before function body")?;
+        self.function_depth += 1;
+        let enclosing = std::mem::replace(&mut self.in_generator, generator);
+        let body = self.block_expression();
+        self.in_generator = enclosing;
+        self.function_depth -= 1;
+        Ok(FnClause {
+            params,
+            defaults,
+            signature,
+            guard,
+            body: body?,
+            generator,
+        })
+    }
+
+    /// A parameter is either a name the argument is bound to, a literal the argument has to
+    /// equal for the clause to be called, `Some(name)`, a name with a default or a rest
+    /// parameter.
+    fn param(&mut self) -> anyhow::Result<(Param, Option<Expr>)> {
+        self.advance(1);
+        let token = self.prev().clone();
+        let param = match token.ty {
+            TokenType::Ident if self.peek().ty == TokenType::Equal => {
+                self.advance(1);
+                let default = self.expression()?;
+                return Ok((Param::Default(token.lexeme), Some(default)));
+            }
+            TokenType::Ident | TokenType::ThisSelf => Param::Name(token.lexeme),
+            TokenType::DotDotDot => {
+                let name =
+                    self.consume(TokenType::Ident, "Expected rest parameter name after '...'")?;
+                Param::Rest(name.lexeme)
+            }
+            TokenType::Number | TokenType::String | TokenType::Atom => {
+                Param::Literal(token.literal)
+            }
+            TokenType::Minus if self.peek().ty == TokenType::Number => {
+                self.advance(1);
+                Param::Literal(Value::Number(-self.prev().literal.as_number()?))
+            }
+            TokenType::True => Param::Literal(Value::Boolean(true)),
+            TokenType::False => Param::Literal(Value::Boolean(false)),
+            TokenType::Nil => Param::Literal(Value::Nil),
+            TokenType::None => Param::Literal(Value::none()),
+            TokenType::Some => {
+                self.consume(TokenType::LeftParen, "Expected '(' after Some in a pattern")?;
+                let name = self.consume(TokenType::Ident, "Expected a name inside Some(...)")?;
+                self.consume(TokenType::RightParen, "Expected ')' after Some(name")?;
+                Param::Some(name.lexeme)
+            }
+            _ => bail!(
+                "{}",
+                AstWalkError::ParseError {
+                    token,
+                    message: "Expected parameter name or pattern".into()
+                }
+            ),
+        };
+        Ok((param, None))
+    }
+
+    /// Comma separated field names of a tuple or struct variant up to the closing delimiter.
+    fn variant_fields(&mut self, close: TokenType) -> anyhow::Result<Vec<String>> {
+        let mut fields = Vec::new();
+        while self.peek().ty != close {
+            let field = self.consume(TokenType::Ident, "Expected variant field name")?;
+            if fields.contains(&field.lexeme) {
+                bail!(
+                    "{}",
+                    AstWalkError::ParseError {
+                        message: format!("Duplicate variant field '{}'", field.lexeme),
+                        token: field,
+                    }
+                )
+            }
+            fields.push(field.lexeme);
+            if self.peek().ty != TokenType::Comma {
+                break;
+            }
+            self.advance(1);
+        }
+        self.consume(close, "Expected end of variant fields")?;
+        Ok(fields)
+    }
+
     fn statement(&mut self) -> anyhow::Result<Stmt> {
         match self.peek().ty {
             TokenType::Print => {
                 self.advance(1);
                 self.statement_print()
             }
-            TokenType::LeftBrace => {
+            TokenType::For => {
+                self.advance(1);
+                self.statement_for()
+            }
+            TokenType::Try => {
+                self.advance(1);
+                self.statement_try()
+            }
+            TokenType::Throw => {
+                self.advance(1);
+                let keyword = self.prev().clone();
+                let value = self.expression()?;
+                self.consume(TokenType::Semicolon, "Expected ';' after throw value")?;
+                Ok(Stmt::Throw { keyword, value })
+            }
+            TokenType::Return => {
                 self.advance(1);
-                Ok(Stmt::Block(self.block()?))
+                let keyword = self.prev().clone();
+                if self.function_depth == 0 {
+                    bail!(
+                        "{}",
+                        AstWalkError::ParseError {
+                            token: keyword,
+                            message: "Cannot return from top-level code".into()
+                        }
+                    )
+                }
+                let value = if self.peek().ty == TokenType::Semicolon {
+                    None
+                } else {
+                    Some(self.expression()?)
+                };
+                self.consume(TokenType::Semicolon, "Expected ';' after return value")?;
+                Ok(Stmt::Return { keyword, value })
+            }
+            TokenType::Yield => {
+                self.advance(1);
+                let keyword = self.prev().clone();
+                if !self.in_generator {
+                    bail!(
+                        "{}",
+                        AstWalkError::ParseError {
+                            token: keyword,
+                            message: "Cannot use 'yield' outside of a generator function".into()
+                        }
+                    )
+                }
+                let value = if self.peek().ty == TokenType::Semicolon {
+                    None
+                } else {
+                    Some(self.expression()?)
+                };
+                self.consume(TokenType::Semicolon, "Expected ';' after yield value")?;
+                Ok(Stmt::Yield { keyword, value })
             }
             _ => self.statement_expression(),
         }
     }
 
+    fn statement_for(&mut self) -> anyhow::Result<Stmt> {
+        let name = self.consume(TokenType::Ident, "Expected loop variable name after 'for'")?;
+        self.consume(TokenType::In, "Expected 'in' after for loop variable")?;
+        let iterable = self.expression()?;
+        self.consume(TokenType::LeftBrace, "Expected '{' before for loop body")?;
+        let body = self.block()?;
+        Ok(Stmt::For {
+            name,
+            iterable,
+            body,
+        })
+    }
+
+    fn statement_try(&mut self) -> anyhow::Result<Stmt> {
+        let keyword = self.prev().clone();
+        self.consume(TokenType::LeftBrace, "Expected '{' after try")?;
+        let body = self.block()?;
+        let catch = if let TokenType::Catch = self.peek().ty {
+            self.advance(1);
+            self.consume(TokenType::LeftParen, "Expected '(' after catch")?;
+            let name = self.consume(TokenType::Ident, "Expected error name in catch")?;
+            self.consume(TokenType::RightParen, "Expected ')' after catch error name")?;
+            self.consume(TokenType::LeftBrace, "Expected '{' after catch")?;
+            Some((name, self.block()?))
+        } else {
+            None
+        };
+        let finally = if let TokenType::Finally = self.peek().ty {
+            self.advance(1);
+            self.consume(TokenType::LeftBrace, "Expected '{' after finally")?;
+            Some(self.block()?)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            bail!(
+                "{}",
+                AstWalkError::ParseError {
+                    token: self.peek().clone(),
+                    message: "Expected 'catch' or 'finally' after try block".into()
+                }
+            )
+        }
+        Ok(Stmt::Try {
+            keyword,
+            body,
+            catch,
+            finally,
+        })
+    }
+
+    /// Advances past the current token if it is of type `ty`, returning it.
+    fn consume(&mut self, ty: TokenType, message: &str) -> anyhow::Result<Token> {
+        if self.peek().ty == ty {
+            self.advance(1);
+            Ok(self.prev().clone())
+        } else {
+            bail!(
+                "{}",
+                AstWalkError::ParseError {
+                    token: self.peek().clone(),
+                    message: message.into()
+                }
+            )
+        }
+    }
+
     fn block(&mut self) -> anyhow::Result<Vec<Stmt>> {
         let mut statements = Vec::new();
         while self.peek().ty != TokenType::RightBrace && !self.is_eof() {
@@ -129,6 +1047,7 @@ impl Parser {
             };
         }
         if let TokenType::RightBrace = self.peek().ty {
+            self.advance(1);
             Ok(statements)
         } else {
             bail!(
@@ -141,11 +1060,108 @@ impl Parser {
         }
     }
 
+    /// Parses the rest of a block expression after its '{'. A final expression without a ';'
+    /// becomes the value of the block.
+    fn block_expression(&mut self) -> anyhow::Result<Expr> {
+        let mut statements = Vec::new();
+        let mut value = None;
+        while self.peek().ty != TokenType::RightBrace && !self.is_eof() {
+            if starts_statement(self.peek().ty) {
+                match self.declaration() {
+                    Some(stmt) => statements.push(stmt),
+                    None => bail!(
+                        "{}",
+                        AstWalkError::ParseError {
+                            token: self.peek().clone(),
+                            message: "invalid declaration".into()
+                        }
+                    ),
+                };
+                continue;
+            }
+            let expr = self.expression()?;
+            if self.peek().ty == TokenType::RightBrace {
+                value = Some(Box::new(expr));
+                break;
+            }
+            statements.push(self.end_statement_expression(expr)?);
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(Expr::Block { statements, value })
+    }
+
+    /// Clauses of a comprehension up to its ']', or its '}' when it builds a map from `key`,
+    /// after its first element.
+    fn comprehension(
+        &mut self,
+        bracket: Token,
+        key: Option<Expr>,
+        element: Expr,
+    ) -> anyhow::Result<Expr> {
+        let (close, unexpected, unclosed) = match key {
+            Some(_) => (
+                TokenType::RightBrace,
+                "Expected 'for', 'if' or '}' in map comprehension",
+                "Expected '}' after map comprehension",
+            ),
+            None => (
+                TokenType::RightBracket,
+                "Expected 'for', 'if' or ']' in list comprehension",
+                "Expected ']' after list comprehension",
+            ),
+        };
+        let mut clauses = Vec::new();
+        while self.peek().ty != close {
+            if self.peek().ty == TokenType::If {
+                self.advance(1);
+                clauses.push(ComprehensionClause::If(self.expression()?));
+                continue;
+            }
+            self.consume(TokenType::For, unexpected)?;
+            let name = self.consume(TokenType::Ident, "Expected loop variable name after 'for'")?;
+            self.consume(TokenType::In, "Expected 'in' after for loop variable")?;
+            let iterable = self.expression()?;
+            clauses.push(ComprehensionClause::For { name, iterable });
+        }
+        self.consume(close, unclosed)?;
+        Ok(Expr::Comprehension {
+            bracket,
+            key: key.map(Box::new),
+            element: Box::new(element),
+            clauses,
+        })
+    }
+
+    /// Parses the rest of an `if` after the keyword, `else if` chains nest in the else branch.
+    fn if_expression(&mut self) -> anyhow::Result<Expr> {
+        let condition = Box::new(self.expression()?);
+        self.consume(TokenType::LeftBrace, "Expected '{' after if condition")?;
+        let then_branch = Box::new(self.block_expression()?);
+        let else_branch = if self.peek().ty == TokenType::Else {
+            self.advance(1);
+            if self.peek().ty == TokenType::If {
+                self.advance(1);
+                Some(Box::new(self.if_expression()?))
+            } else {
+                self.consume(TokenType::LeftBrace, "Expected '{' after else")?;
+                Some(Box::new(self.block_expression()?))
+            }
+        } else {
+            None
+        };
+        Ok(Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
     fn statement_print(&mut self) -> anyhow::Result<Stmt> {
-        let expr = self.expression()?;
+        let keyword = self.prev().clone();
+        let value = self.expression()?;
         if let TokenType::Semicolon = self.peek().ty {
             self.advance(1);
-            Ok(Stmt::Print(expr))
+            Ok(Stmt::Print { keyword, value })
         } else {
             bail!(
                 "{}",
@@ -159,7 +1175,14 @@ impl Parser {
 
     fn statement_expression(&mut self) -> anyhow::Result<Stmt> {
         let expr = self.expression()?;
-        if let TokenType::Semicolon = self.peek().ty {
+        self.end_statement_expression(expr)
+    }
+
+    /// The ';' is optional after an expression ending in a block, like `if` or `{ ... }`.
+    fn end_statement_expression(&mut self, expr: Expr) -> anyhow::Result<Stmt> {
+        if self.prev().ty == TokenType::RightBrace && self.peek().ty != TokenType::Semicolon {
+            Ok(Stmt::Expression(expr))
+        } else if let TokenType::Semicolon = self.peek().ty {
             self.advance(1);
             Ok(Stmt::Expression(expr))
         } else {
@@ -174,25 +1197,87 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> anyhow::Result<Expr> {
-        let expr = self.equality()?;
+        let expr = self.coalesce()?;
 
         if let TokenType::Equal = self.peek().ty {
             self.advance(1);
             let equals = self.prev().clone();
             let value = self.assignment()?;
-            if let Expr::Name(name) = expr {
-                Ok(Expr::Assignment {
+            match expr {
+                Expr::Name(name) => Ok(Expr::Assignment {
                     name,
                     value: Box::new(value),
-                })
-            } else {
-                bail!(
+                }),
+                Expr::Get { object, name } => Ok(Expr::Set {
+                    object,
+                    name,
+                    operator: None,
+                    value: Box::new(value),
+                }),
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => Ok(Expr::SetIndex {
+                    object,
+                    bracket,
+                    index,
+                    operator: None,
+                    value: Box::new(value),
+                }),
+                _ => bail!(
                     "{}",
                     AstWalkError::ParseError {
                         token: equals,
                         message: "Invalid assignment target".into()
                     }
-                )
+                ),
+            }
+        } else if let Some(ty) = compound_assignment_op(self.peek().ty) {
+            // desugar `a += b` into `a = a + b`
+            self.advance(1);
+            let compound = self.prev().clone();
+            let value = self.assignment()?;
+            let operator = Token {
+                ty,
+                literal: Value::Nil,
+                line: compound.line,
+                lexeme: compound.lexeme.trim_end_matches('=').into(),
+            };
+            match expr {
+                Expr::Name(name) => Ok(Expr::Assignment {
+                    name: name.clone(),
+                    value: Box::new(Expr::Binary {
+                        left: Box::new(Expr::Name(name)),
+                        operator,
+                        right: Box::new(value),
+                    }),
+                }),
+                // the object is only evaluated once, so it isn't desugared
+                Expr::Get { object, name } => Ok(Expr::Set {
+                    object,
+                    name,
+                    operator: Some(operator),
+                    value: Box::new(value),
+                }),
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => Ok(Expr::SetIndex {
+                    object,
+                    bracket,
+                    index,
+                    operator: Some(operator),
+                    value: Box::new(value),
+                }),
+                _ => bail!(
+                    "{}",
+                    AstWalkError::ParseError {
+                        token: compound,
+                        message: "Invalid assignment target".into()
+                    }
+                ),
             }
         } else {
             Ok(expr)
@@ -202,6 +1287,15 @@ impl Parser {
     fn expression(&mut self) -> anyhow::Result<Expr> {
         self.assignment()
     }
+    fn coalesce(&mut self) -> anyhow::Result<Expr> {
+        self.left_assoc_binary(&[TokenType::QuestionQuestion], Self::or)
+    }
+    fn or(&mut self) -> anyhow::Result<Expr> {
+        self.left_assoc_binary(&[TokenType::Or], Self::and)
+    }
+    fn and(&mut self) -> anyhow::Result<Expr> {
+        self.left_assoc_binary(&[TokenType::And], Self::equality)
+    }
     fn term(&mut self) -> anyhow::Result<Expr> {
         // self.expand_binary_expr(ExprRule::Factor, &[TokenType::Minus, TokenType::Plus])
         let mut expr = self.factor()?;
@@ -223,7 +1317,7 @@ impl Parser {
         Ok(expr)
     }
     fn comparison(&mut self) -> anyhow::Result<Expr> {
-        let mut expr = self.term()?;
+        let mut expr = self.range()?;
         loop {
             match self.peek().ty {
                 TokenType::Gt
@@ -231,10 +1325,11 @@ impl Parser {
                 | TokenType::Lt
                 | TokenType::Le
                 | TokenType::EqualEqual
-                | TokenType::BangEqual => {
+                | TokenType::BangEqual
+                | TokenType::In => {
                     self.advance(1);
                     let operator = self.prev().clone();
-                    let right = self.term()?;
+                    let right = self.range()?;
                     expr = Expr::Binary {
                         left: Box::new(expr),
                         operator,
@@ -247,11 +1342,63 @@ impl Parser {
 
         Ok(expr)
     }
+    /// Ranges don't chain, `a..b..c` is a parse error.
+    fn range(&mut self) -> anyhow::Result<Expr> {
+        let expr = self.bit_or()?;
+        match self.peek().ty {
+            TokenType::DotDot | TokenType::DotDotEqual => {
+                self.advance(1);
+                let operator = self.prev().clone();
+                let right = self.bit_or()?;
+                Ok(Expr::Binary {
+                    left: Box::new(expr),
+                    operator,
+                    right: Box::new(right),
+                })
+            }
+            _ => Ok(expr),
+        }
+    }
+    fn bit_or(&mut self) -> anyhow::Result<Expr> {
+        self.left_assoc_binary(&[TokenType::Pipe], Self::bit_xor)
+    }
+    fn bit_xor(&mut self) -> anyhow::Result<Expr> {
+        self.left_assoc_binary(&[TokenType::Caret], Self::bit_and)
+    }
+    fn bit_and(&mut self) -> anyhow::Result<Expr> {
+        self.left_assoc_binary(&[TokenType::Ampersand], Self::shift)
+    }
+    fn shift(&mut self) -> anyhow::Result<Expr> {
+        self.left_assoc_binary(&[TokenType::ShiftLeft, TokenType::ShiftRight], Self::term)
+    }
+    /// Parses a chain of left associative binary operators, where both operands of each operator
+    /// are parsed by the next highest precedence rule `operand`.
+    fn left_assoc_binary(
+        &mut self,
+        operators: &[TokenType],
+        operand: fn(&mut Self) -> anyhow::Result<Expr>,
+    ) -> anyhow::Result<Expr> {
+        let mut expr = operand(self)?;
+        while operators.contains(&self.peek().ty) {
+            self.advance(1);
+            let operator = self.prev().clone();
+            let right = operand(self)?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            }
+        }
+        Ok(expr)
+    }
     fn factor(&mut self) -> anyhow::Result<Expr> {
         let mut expr = self.unary()?;
         loop {
             match self.peek().ty {
-                TokenType::ForwardSlash | TokenType::Star => {
+                TokenType::ForwardSlash
+                | TokenType::Star
+                | TokenType::Percent
+                | TokenType::TildeSlash => {
                     self.advance(1);
                     let operator = self.prev().clone();
                     let right = self.unary()?;
@@ -269,7 +1416,7 @@ impl Parser {
     }
     fn unary(&mut self) -> anyhow::Result<Expr> {
         match self.peek().ty {
-            TokenType::Bang | TokenType::Minus => {
+            TokenType::Bang | TokenType::Minus | TokenType::Tilde => {
                 self.advance(1);
                 let operator = self.prev().clone();
                 let right = self.unary()?;
@@ -278,9 +1425,147 @@ impl Parser {
                     right: Box::new(right),
                 })
             }
-            _ => self.primary(),
+            _ => self.exponent(),
         }
     }
+    /// ** binds tighter than unary operators on its left (-2 ** 2 == -4), and is right
+    /// associative, so its right operand goes back through unary.
+    fn exponent(&mut self) -> anyhow::Result<Expr> {
+        let expr = self.postfix()?;
+        if let TokenType::StarStar = self.peek().ty {
+            self.advance(1);
+            let operator = self.prev().clone();
+            let right = self.unary()?;
+            Ok(Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            })
+        } else {
+            Ok(expr)
+        }
+    }
+    fn postfix(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.primary()?;
+        loop {
+            expr = match self.peek().ty {
+                TokenType::LeftBracket => {
+                    self.advance(1);
+                    let bracket = self.prev().clone();
+                    let index = self.expression()?;
+                    self.consume(TokenType::RightBracket, "Expected ']' after index")?;
+                    Expr::Index {
+                        object: Box::new(expr),
+                        bracket,
+                        index: Box::new(index),
+                    }
+                }
+                TokenType::LeftParen => {
+                    self.advance(1);
+                    let (arguments, keywords) = self.arguments()?;
+                    Expr::Call {
+                        callee: Box::new(expr),
+                        paren: self.prev().clone(),
+                        arguments,
+                        keywords,
+                    }
+                }
+                TokenType::Dot => {
+                    self.advance(1);
+                    // tuple variant fields are accessed by position, value.0
+                    let name = if self.peek().ty == TokenType::Number {
+                        self.advance(1);
+                        self.prev().clone()
+                    } else {
+                        self.consume(TokenType::Ident, "Expected property name after '.'")?
+                    };
+                    Expr::Get {
+                        object: Box::new(expr),
+                        name,
+                    }
+                }
+                TokenType::QuestionDot => {
+                    self.advance(1);
+                    let name = if self.peek().ty == TokenType::Number {
+                        self.advance(1);
+                        self.prev().clone()
+                    } else {
+                        self.consume(TokenType::Ident, "Expected property name after '?.'")?
+                    };
+                    // a method call is skipped along with the property
+                    let call = if self.peek().ty == TokenType::LeftParen {
+                        self.advance(1);
+                        let (arguments, keywords) = self.arguments()?;
+                        Some((self.prev().clone(), arguments, keywords))
+                    } else {
+                        None
+                    };
+                    Expr::SafeGet {
+                        object: Box::new(expr),
+                        name,
+                        call,
+                    }
+                }
+                TokenType::DoubleColon => {
+                    self.advance(1);
+                    let name = self.consume(TokenType::Ident, "Expected name after '::'")?;
+                    Expr::Path {
+                        object: Box::new(expr),
+                        name,
+                    }
+                }
+                TokenType::Question => {
+                    self.advance(1);
+                    Expr::Try {
+                        value: Box::new(expr),
+                        question: self.prev().clone(),
+                    }
+                }
+                _ => break,
+            };
+        }
+        Ok(expr)
+    }
+    /// Parses a comma separated argument list up to and including the closing ')'.
+    /// Keyword arguments, `name: value`, have to come after the positional ones and are
+    /// returned with their names.
+    fn arguments(&mut self) -> anyhow::Result<(Vec<Expr>, Vec<Token>)> {
+        let mut arguments = Vec::new();
+        let mut keywords: Vec<Token> = Vec::new();
+        while self.peek().ty != TokenType::RightParen {
+            let is_keyword = self.peek().ty == TokenType::Ident
+                && self.tokens.get(self.cursor.i + 1).map(|t| t.ty) == Some(TokenType::Colon);
+            if is_keyword {
+                let keyword = self.peek().clone();
+                if keywords.iter().any(|k| k.lexeme == keyword.lexeme) {
+                    bail!(
+                        "{}",
+                        AstWalkError::ParseError {
+                            message: format!("Duplicate keyword argument '{}'", keyword.lexeme),
+                            token: keyword,
+                        }
+                    )
+                }
+                keywords.push(keyword);
+                self.advance(2);
+            } else if !keywords.is_empty() {
+                bail!(
+                    "{}",
+                    AstWalkError::ParseError {
+                        token: self.peek().clone(),
+                        message: "Positional argument can't follow keyword arguments".into()
+                    }
+                )
+            }
+            arguments.push(self.expression()?);
+            if self.peek().ty != TokenType::Comma {
+                break;
+            }
+            self.advance(1);
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after arguments")?;
+        Ok((arguments, keywords))
+    }
     fn primary(&mut self) -> anyhow::Result<Expr> {
         match self.peek().ty {
             TokenType::False => {
@@ -295,7 +1580,29 @@ impl Parser {
                 self.advance(1);
                 Ok(Expr::Literal(Value::Nil))
             }
-            TokenType::Number | TokenType::String => {
+            TokenType::None => {
+                self.advance(1);
+                Ok(Expr::Literal(Value::none()))
+            }
+            TokenType::Some | TokenType::Ok | TokenType::Err => {
+                self.advance(1);
+                let wrapper = self.prev().clone();
+                self.consume(
+                    TokenType::LeftParen,
+                    &format!("Expected '(' after {}", wrapper.lexeme),
+                )?;
+                let value = Box::new(self.expression()?);
+                self.consume(
+                    TokenType::RightParen,
+                    &format!("Expected ')' after {} value", wrapper.lexeme),
+                )?;
+                Ok(match wrapper.ty {
+                    TokenType::Some => Expr::Some(value),
+                    TokenType::Ok => Expr::Ok(value),
+                    _ => Expr::Err(value),
+                })
+            }
+            TokenType::Number | TokenType::String | TokenType::Atom => {
                 self.advance(1);
                 Ok(Expr::Literal(self.prev().literal.clone()))
             }
@@ -303,6 +1610,7 @@ impl Parser {
                 self.advance(1);
                 let expr = self.expression()?;
                 if self.peek().ty == TokenType::RightParen {
+                    self.advance(1);
                     Ok(Expr::Grouping(Box::new(expr)))
                 } else {
                     Err(anyhow!(
@@ -313,8 +1621,104 @@ impl Parser {
             TokenType::Ident => {
                 let name = self.peek().clone();
                 self.advance(1);
+                if self.peek().ty == TokenType::DoubleColon {
+                    if let Some(global) = self.module_path(&name)? {
+                        return Ok(Expr::Name(global));
+                    }
+                }
                 Ok(Expr::Name(name))
             }
+            TokenType::ThisSelf => {
+                self.advance(1);
+                if self.current_struct.is_none() {
+                    bail!(
+                        "{}",
+                        AstWalkError::ParseError {
+                            token: self.prev().clone(),
+                            message: "Cannot use 'self' outside of a method".into()
+                        }
+                    )
+                }
+                Ok(Expr::Name(self.prev().clone()))
+            }
+            TokenType::Super => {
+                self.advance(1);
+                let keyword = self.prev().clone();
+                let owner = match &self.current_struct {
+                    Some((owner, true)) => owner.clone(),
+                    Some((owner, false)) => bail!(
+                        "{}",
+                        AstWalkError::ParseError {
+                            token: keyword,
+                            message: format!(
+                                "Cannot use 'super' in {}, it has no parent",
+                                owner.lexeme
+                            )
+                        }
+                    ),
+                    None => bail!(
+                        "{}",
+                        AstWalkError::ParseError {
+                            token: keyword,
+                            message: "Cannot use 'super' outside of a method".into()
+                        }
+                    ),
+                };
+                self.consume(TokenType::Dot, "Expected '.' after 'super'")?;
+                let method =
+                    self.consume(TokenType::Ident, "Expected method name after 'super.'")?;
+                Ok(Expr::Super {
+                    keyword,
+                    owner,
+                    method,
+                })
+            }
+            TokenType::LeftBrace => {
+                self.advance(1);
+                self.block_expression()
+            }
+            TokenType::If => {
+                self.advance(1);
+                self.if_expression()
+            }
+            TokenType::LeftBracket => {
+                self.advance(1);
+                let bracket = self.prev().clone();
+                let mut elements = Vec::new();
+                while self.peek().ty != TokenType::RightBracket {
+                    let element = self.expression()?;
+                    if elements.is_empty() && self.peek().ty == TokenType::For {
+                        return self.comprehension(bracket, None, element);
+                    }
+                    elements.push(element);
+                    if self.peek().ty != TokenType::Comma {
+                        break;
+                    }
+                    self.advance(1);
+                }
+                self.consume(TokenType::RightBracket, "Expected ']' after list elements")?;
+                Ok(Expr::List { bracket, elements })
+            }
+            TokenType::Percent if self.tokens[self.cursor.i + 1].ty == TokenType::LeftBrace => {
+                self.advance(2);
+                let brace = self.prev().clone();
+                let mut entries = Vec::new();
+                while self.peek().ty != TokenType::RightBrace {
+                    let key = self.expression()?;
+                    self.consume(TokenType::FatArrow, "Expected '=>' after map key")?;
+                    let value = self.expression()?;
+                    if entries.is_empty() && self.peek().ty == TokenType::For {
+                        return self.comprehension(brace, Some(key), value);
+                    }
+                    entries.push((key, value));
+                    if self.peek().ty != TokenType::Comma {
+                        break;
+                    }
+                    self.advance(1);
+                }
+                self.consume(TokenType::RightBrace, "Expected '}' after map entries")?;
+                Ok(Expr::Map { brace, entries })
+            }
             _ => Err(anyhow!(
                 "Expected primary or group expression, found: {:?}",
                 self.peek()
@@ -367,3 +1771,38 @@ impl Parser {
         &self.tokens[self.cursor.i - 1]
     }
 }
+
+/// Tokens starting a declaration or statement rather than an expression.
+pub(crate) fn starts_statement(ty: TokenType) -> bool {
+    matches!(
+        ty,
+        TokenType::Let
+            | TokenType::Enum
+            | TokenType::Fn
+            | TokenType::Struct
+            | TokenType::Trait
+            | TokenType::Impl
+            | TokenType::Mod
+            | TokenType::Pub
+            | TokenType::Use
+            | TokenType::At
+            | TokenType::Print
+            | TokenType::For
+            | TokenType::Try
+            | TokenType::Throw
+            | TokenType::Yield
+            | TokenType::Return
+    )
+}
+
+/// Maps a compound assignment operator (+=, -=, ...) to the binary operator it applies.
+fn compound_assignment_op(ty: TokenType) -> Option<TokenType> {
+    match ty {
+        TokenType::PlusEqual => Some(TokenType::Plus),
+        TokenType::MinusEqual => Some(TokenType::Minus),
+        TokenType::StarEqual => Some(TokenType::Star),
+        TokenType::SlashEqual => Some(TokenType::ForwardSlash),
+        TokenType::PercentEqual => Some(TokenType::Percent),
+        _ => None,
+    }
+}
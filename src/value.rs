@@ -2,6 +2,8 @@
 pub enum TokenType {
     Print,
     FatArrow,
+    Arrow,
+    At,
     LeftBrace,
     RightBrace,
     LeftParen,
@@ -13,6 +15,20 @@ pub enum TokenType {
     Semicolon,
     ForwardSlash,
     Star,
+    StarStar,
+    Percent,
+    TildeSlash,
+    Tilde,
+    Ampersand,
+    Pipe,
+    Caret,
+    ShiftLeft,
+    ShiftRight,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
     Bang,
     Equal,
     BangEqual,
@@ -28,6 +44,11 @@ pub enum TokenType {
     Struct,
     Trait,
     Impl,
+    Mod,
+    Pub,
+    Use,
+    As,
+    Macro,
     Else,
     False,
     True,
@@ -37,6 +58,7 @@ pub enum TokenType {
     Nil,
     Or,
     Return,
+    Yield,
     Super,
     ThisSelf,
     Let,
@@ -52,12 +74,44 @@ pub enum TokenType {
     Unknown,
     Colon,
     DoubleColon,
+    DotDot,
+    DotDotEqual,
+    DotDotDot,
+    In,
+    LeftBracket,
+    RightBracket,
+    Some,
+    None,
+    QuestionQuestion,
+    QuestionDot,
+    Ok,
+    Err,
+    Question,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    Atom,
+    Enum,
+    Where,
 }
 
 use anyhow::*;
-use std::fmt;
+use std::{
+    collections::HashMap,
+    fmt,
+    io::Write,
+    sync::{Arc, LazyLock, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
 
-use crate::ast::AstWalkError;
+use crate::{
+    ast::{AstWalkError, Expr},
+    attr::Attributes,
+    compiler::Chunk,
+    interp::GeneratorThread,
+    vm::SuspendedFrame,
+};
+use thiserror::Error;
 
 #[derive(Debug, Clone)]
 pub struct Token {
@@ -90,9 +144,1165 @@ impl std::fmt::Display for Token {
     }
 }
 
+/// Interned symbol written as `:name`. Atoms with the same name share an id, so comparing them
+/// doesn't compare strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Atom(u32);
+
+#[derive(Default)]
+struct AtomTable {
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+/// Shared by every thread, generator bodies run by the Interpreter use the caller's atoms.
+static ATOMS: LazyLock<Mutex<AtomTable>> = LazyLock::new(Mutex::default);
+
+fn atoms() -> MutexGuard<'static, AtomTable> {
+    ATOMS.lock().expect("atom table lock poisoned")
+}
+
+impl Atom {
+    pub fn intern(name: &str) -> Self {
+        let mut atoms = atoms();
+        if let Some(id) = atoms.ids.get(name) {
+            return Atom(*id);
+        }
+        let id = atoms.names.len() as u32;
+        atoms.names.push(name.to_string());
+        atoms.ids.insert(name.to_string(), id);
+        Atom(id)
+    }
+
+    pub fn name(&self) -> String {
+        atoms().names[self.0 as usize].clone()
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ":{}", self.name())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Object {
     String(String),
+    List(Arc<List>),
+    Map(Arc<Map>),
+    Range(Range),
+    Option(Option<Box<Value>>),
+    Result(std::result::Result<Box<Value>, Box<Value>>),
+    Native(NativeFunction),
+    Error(ScriptError),
+    Enum(Arc<EnumType>),
+    /// `Enum::Variant` of a variant with fields, calling it constructs an instance
+    VariantConstructor(Arc<EnumType>, usize),
+    EnumInstance(Arc<EnumInstance>),
+    Function(Arc<Function>),
+    Struct(Arc<StructType>),
+    Instance(Arc<StructInstance>),
+    /// `instance.method`, calling it passes the instance as the first argument
+    BoundMethod(Arc<BoundMethod>),
+    Trait(Arc<Trait>),
+    Iterator(Arc<Iter>),
+    Generator(Arc<Generator>),
+}
+
+/// Elements of a list, shared by every copy of it like the fields of a struct instance.
+#[derive(Debug, Default)]
+pub struct List(RwLock<Vec<Value>>);
+
+impl List {
+    pub fn values(&self) -> RwLockReadGuard<'_, Vec<Value>> {
+        self.0.read().expect("list lock poisoned")
+    }
+
+    pub fn values_mut(&self) -> RwLockWriteGuard<'_, Vec<Value>> {
+        self.0.write().expect("list lock poisoned")
+    }
+}
+
+/// Entries of a map in insertion order, shared by every copy of it like the elements of a list.
+#[derive(Debug, Default)]
+pub struct Map(RwLock<MapEntries>);
+
+#[derive(Debug, Default, Clone)]
+struct MapEntries {
+    entries: Vec<(Value, Value)>,
+    /// Position in `entries` of each key
+    index: HashMap<MapKey, usize>,
+}
+
+/// Key of a map entry, only values compared by content can be keys.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum MapKey {
+    Nil,
+    Boolean(bool),
+    Number(u64),
+    String(String),
+    Atom(Atom),
+}
+
+impl MapKey {
+    fn new(value: &Value) -> anyhow::Result<Self> {
+        Ok(match value {
+            Value::Nil => Self::Nil,
+            Value::Boolean(b) => Self::Boolean(*b),
+            // -0 == 0 so they have to be the same key
+            Value::Number(n) if *n == 0.0 => Self::Number(0),
+            Value::Number(n) => Self::Number(n.to_bits()),
+            Value::Obj(Object::String(string)) => Self::String(string.clone()),
+            Value::Atom(atom) => Self::Atom(*atom),
+            _ => bail!(
+                "Map keys must be a Number, String, Boolean, Atom or nil, got: {}",
+                value.type_string()
+            ),
+        })
+    }
+}
+
+impl Map {
+    /// Copy of the entries in the order their keys were first inserted.
+    pub fn entries(&self) -> Vec<(Value, Value)> {
+        self.0.read().expect("map lock poisoned").entries.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.read().expect("map lock poisoned").entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, key: &Value) -> anyhow::Result<Option<Value>> {
+        let key = MapKey::new(key)?;
+        let map = self.0.read().expect("map lock poisoned");
+        Ok(map.index.get(&key).map(|&i| map.entries[i].1.clone()))
+    }
+
+    /// Sets the value of `key`, which keeps its position if it was already in the map.
+    pub fn insert(&self, key: Value, value: Value) -> anyhow::Result<()> {
+        let hashed = MapKey::new(&key)?;
+        let mut map = self.0.write().expect("map lock poisoned");
+        match map.index.get(&hashed) {
+            Some(&i) => map.entries[i].1 = value,
+            None => {
+                let i = map.entries.len();
+                map.entries.push((key, value));
+                map.index.insert(hashed, i);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Type created by an `enum Name { ... }` declaration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumType {
+    pub name: String,
+    pub variants: Vec<Variant>,
+    pub attributes: Attributes,
+}
+
+impl EnumType {
+    /// Builds an instance of the variant at `variant`, only struct variants take keyword
+    /// arguments.
+    pub fn construct(
+        ty: &Arc<Self>,
+        variant: usize,
+        args: &[Value],
+        keywords: &[String],
+    ) -> anyhow::Result<Value> {
+        let Variant { name, fields } = &ty.variants[variant];
+        let values = match fields {
+            VariantFields::Struct(names) => {
+                let params: Vec<Param> = names.iter().cloned().map(Param::Name).collect();
+                bind_params(&params, args, keywords)
+                    .map_err(|e| anyhow!("{}::{} {}", ty.name, name, e))?
+                    .into_iter()
+                    .map(Option::unwrap_or_default)
+                    .collect()
+            }
+            _ if !keywords.is_empty() => {
+                bail!("{}::{} does not accept keyword arguments", ty.name, name)
+            }
+            _ if args.len() != fields.arity() => bail!(
+                "{}::{} expected {} argument(s) but got {}",
+                ty.name,
+                name,
+                fields.arity(),
+                args.len()
+            ),
+            _ => args.to_vec(),
+        };
+        Ok(Value::Obj(Object::EnumInstance(Arc::new(EnumInstance {
+            ty: ty.clone(),
+            variant,
+            values,
+        }))))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub fields: VariantFields,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantFields {
+    /// `Empty`
+    Unit,
+    /// `Circle(r)`, fields are accessed by position, `circle.0`
+    Tuple(Vec<String>),
+    /// `Rect { w, h }`, constructed positionally in declaration order or by name,
+    /// `Shape::Rect(1, 2)` or `Shape::Rect(w: 1, h: 2)`
+    Struct(Vec<String>),
+}
+
+impl VariantFields {
+    pub fn arity(&self) -> usize {
+        match self {
+            VariantFields::Unit => 0,
+            VariantFields::Tuple(fields) | VariantFields::Struct(fields) => fields.len(),
+        }
+    }
+}
+
+/// Value of one of an enum's variants along with its payload.
+#[derive(Debug, Clone)]
+pub struct EnumInstance {
+    pub ty: Arc<EnumType>,
+    pub variant: usize,
+    pub values: Vec<Value>,
+}
+
+impl EnumInstance {
+    pub fn variant(&self) -> &Variant {
+        &self.ty.variants[self.variant]
+    }
+
+    pub fn is_variant(&self, ty: &Arc<EnumType>, variant: usize) -> bool {
+        Arc::ptr_eq(&self.ty, ty) && self.variant == variant
+    }
+}
+
+impl PartialEq for EnumInstance {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_variant(&other.ty, other.variant) && self.values == other.values
+    }
+}
+
+impl fmt::Display for EnumInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let variant = self.variant();
+        write!(f, "{}::{}", self.ty.name, variant.name)?;
+        match &variant.fields {
+            VariantFields::Unit => Result::Ok(()),
+            VariantFields::Tuple(_) => {
+                let values: Vec<String> = self.values.iter().map(|v| v.to_string()).collect();
+                write!(f, "({})", values.join(", "))
+            }
+            VariantFields::Struct(fields) => {
+                let values: Vec<String> = fields
+                    .iter()
+                    .zip(&self.values)
+                    .map(|(field, value)| format!("{}: {}", field, value))
+                    .collect();
+                write!(f, " {{ {} }}", values.join(", "))
+            }
+        }
+    }
+}
+
+/// `Enum::name`, unit variants evaluate to their instance and variants with fields evaluate to
+/// their constructor.
+pub fn get_variant(ty: &Arc<EnumType>, name: &str) -> anyhow::Result<Value> {
+    let Some(index) = ty.variants.iter().position(|v| v.name == name) else {
+        bail!("Enum {} has no variant '{}'", ty.name, name);
+    };
+    let value = match ty.variants[index].fields {
+        VariantFields::Unit => Object::EnumInstance(Arc::new(EnumInstance {
+            ty: ty.clone(),
+            variant: index,
+            values: Vec::new(),
+        })),
+        _ => Object::VariantConstructor(ty.clone(), index),
+    };
+    Ok(Value::Obj(value))
+}
+
+/// Type created by a `struct Name : Parent { fields, methods }` declaration.
+#[derive(Debug)]
+pub struct StructType {
+    pub name: String,
+    pub parent: Option<Arc<StructType>>,
+    /// Inherited fields first, in the order instances are constructed with
+    pub fields: Vec<String>,
+    /// Methods declared by this struct, inherited ones are found through `parent`
+    pub methods: HashMap<String, Arc<Function>>,
+    /// Traits implemented for this struct by `impl` declarations, registered at runtime
+    pub impls: RwLock<Vec<TraitImpl>>,
+    pub attributes: Attributes,
+}
+
+impl StructType {
+    pub fn new(name: String, fields: Vec<String>, methods: HashMap<String, Arc<Function>>) -> Self {
+        Self {
+            name,
+            parent: None,
+            fields,
+            methods,
+            impls: RwLock::default(),
+            attributes: Attributes::default(),
+        }
+    }
+
+    /// Copy of the struct linked to the one it extends, inheriting its fields.
+    pub fn inherit(&self, parent: Arc<StructType>) -> Result<Self, String> {
+        if let Some(field) = self.fields.iter().find(|f| parent.fields.contains(f)) {
+            return Err(format!(
+                "Struct {} redeclares field '{}' of {}",
+                self.name, field, parent.name
+            ));
+        }
+        let fields = parent.fields.iter().chain(&self.fields).cloned().collect();
+        let mut ty = Self::new(self.name.clone(), fields, self.methods.clone());
+        ty.parent = Some(parent);
+        ty.attributes = self.attributes.clone();
+        Result::Ok(ty)
+    }
+
+    /// Method `name` declared by this struct, then by the traits it implements (including their
+    /// default methods), then the same for its closest ancestor.
+    pub fn find_method(&self, name: &str) -> Option<Arc<Function>> {
+        if let Some(method) = self.methods.get(name) {
+            return Some(method.clone());
+        }
+        let impls = self.impls.read().expect("struct impls lock poisoned");
+        impls
+            .iter()
+            .find_map(|imp| imp.find_method(name))
+            .or_else(|| self.parent.as_ref()?.find_method(name))
+    }
+
+    /// Method `name` of this struct's implementation of `tr`, or of the closest ancestor's.
+    pub fn impl_method(&self, tr: &Arc<Trait>, name: &str) -> Option<Arc<Function>> {
+        let impls = self.impls.read().expect("struct impls lock poisoned");
+        match impls.iter().find(|imp| Arc::ptr_eq(&imp.tr, tr)) {
+            Some(imp) => imp.find_method(name),
+            None => self.parent.as_ref()?.impl_method(tr, name),
+        }
+    }
+
+    /// Whether this struct or one of its ancestors implements `tr`.
+    pub fn implements(&self, tr: &Arc<Trait>) -> bool {
+        let impls = self.impls.read().expect("struct impls lock poisoned");
+        impls.iter().any(|imp| Arc::ptr_eq(&imp.tr, tr))
+            || self.parent.as_ref().is_some_and(|p| p.implements(tr))
+    }
+
+    /// Builds an instance from positional or keyword arguments, one for each field.
+    pub fn construct(ty: &Arc<Self>, args: &[Value], keywords: &[String]) -> anyhow::Result<Value> {
+        let params: Vec<Param> = ty.fields.iter().cloned().map(Param::Name).collect();
+        let values =
+            bind_params(&params, args, keywords).map_err(|e| anyhow!("{} {}", ty.name, e))?;
+        Ok(Value::Obj(Object::Instance(Arc::new(StructInstance {
+            ty: ty.clone(),
+            values: RwLock::new(values.into_iter().map(Option::unwrap_or_default).collect()),
+        }))))
+    }
+}
+
+#[derive(Debug)]
+pub struct StructInstance {
+    pub ty: Arc<StructType>,
+    /// Parallel to `ty.fields`, shared by every copy of the instance
+    pub values: RwLock<Vec<Value>>,
+}
+
+impl StructInstance {
+    /// `instance.name`, a field or a method bound to the instance.
+    pub fn get(self: &Arc<Self>, name: &str) -> anyhow::Result<Value> {
+        if let Some(i) = self.ty.fields.iter().position(|f| f == name) {
+            return Ok(self.values()[i].clone());
+        }
+        match self.ty.find_method(name) {
+            Some(method) => Ok(Value::Obj(Object::BoundMethod(Arc::new(BoundMethod {
+                receiver: Value::Obj(Object::Instance(self.clone())),
+                method,
+            })))),
+            None => bail!("{} has no field or method '{}'", self.ty.name, name),
+        }
+    }
+
+    /// `instance.name = value`
+    pub fn set(&self, name: &str, value: Value) -> anyhow::Result<()> {
+        let Some(i) = self.ty.fields.iter().position(|f| f == name) else {
+            bail!("{} has no field '{}'", self.ty.name, name);
+        };
+        self.values.write().expect("struct fields lock poisoned")[i] = value;
+        Ok(())
+    }
+
+    pub fn values(&self) -> RwLockReadGuard<'_, Vec<Value>> {
+        self.values.read().expect("struct fields lock poisoned")
+    }
+}
+
+impl PartialEq for StructInstance {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.ty, &other.ty) && *self.values() == *other.values()
+    }
+}
+
+impl fmt::Display for StructInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values: Vec<String> = self
+            .ty
+            .fields
+            .iter()
+            .zip(self.values().iter())
+            .map(|(field, value)| format!("{}: {}", field, value))
+            .collect();
+        write!(f, "{} {{ {} }}", self.ty.name, values.join(", "))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Arc<Function>,
+}
+
+impl BoundMethod {
+    /// `super.name` inside a method of `owner`, looked up from the struct `owner` extends.
+    pub fn find_super(owner: &Value, receiver: Value, name: &str) -> anyhow::Result<Value> {
+        let Value::Obj(Object::Struct(owner)) = owner else {
+            bail!("'super' needs a struct, got: {}", owner.type_string());
+        };
+        let Some(parent) = &owner.parent else {
+            bail!("Struct {} has no parent for 'super'", owner.name);
+        };
+        match parent.find_method(name) {
+            Some(method) => Ok(Value::Obj(Object::BoundMethod(Arc::new(BoundMethod {
+                receiver,
+                method,
+            })))),
+            None => bail!("{} has no method '{}'", parent.name, name),
+        }
+    }
+
+    /// Arguments to call the method with, the receiver followed by `args`.
+    pub fn args(&self, args: &[Value]) -> Vec<Value> {
+        let mut all = Vec::with_capacity(args.len() + 1);
+        all.push(self.receiver.clone());
+        all.extend_from_slice(args);
+        all
+    }
+}
+
+/// Type created by a `trait Name { fn required(self); fn default(self) { ... } }` declaration.
+#[derive(Debug, Clone)]
+pub struct Trait {
+    pub name: String,
+    /// Methods every implementation has to declare
+    pub required: Vec<String>,
+    /// Methods implementations get unless they declare their own
+    pub defaults: HashMap<String, Arc<Function>>,
+    pub attributes: Attributes,
+}
+
+/// Methods of one `impl Trait for Struct` declaration.
+#[derive(Debug, Clone)]
+pub struct TraitImpl {
+    pub tr: Arc<Trait>,
+    pub methods: HashMap<String, Arc<Function>>,
+}
+
+impl TraitImpl {
+    fn find_method(&self, name: &str) -> Option<Arc<Function>> {
+        self.methods
+            .get(name)
+            .or_else(|| self.tr.defaults.get(name))
+            .cloned()
+    }
+}
+
+/// Registers the methods of `impl tr for ty` with the struct, after checking they are exactly
+/// the trait's methods, minus any it has a default for.
+pub fn implement(
+    tr: &Value,
+    ty: &Value,
+    methods: HashMap<String, Arc<Function>>,
+) -> Result<(), String> {
+    let Value::Obj(Object::Trait(tr)) = tr else {
+        return Err(format!("impl expected a trait, got: {}", tr.type_string()));
+    };
+    let Value::Obj(Object::Struct(ty)) = ty else {
+        return Err(format!(
+            "Can only implement traits for structs, got: {}",
+            ty.type_string()
+        ));
+    };
+    if let Some(method) = methods
+        .keys()
+        .find(|m| !tr.required.contains(m) && !tr.defaults.contains_key(*m))
+    {
+        return Err(format!(
+            "Method '{}' is not a member of trait {}",
+            method, tr.name
+        ));
+    }
+    if let Some(method) = tr.required.iter().find(|m| !methods.contains_key(*m)) {
+        return Err(format!(
+            "impl {} for {} is missing method '{}'",
+            tr.name, ty.name, method
+        ));
+    }
+    let mut impls = ty.impls.write().expect("struct impls lock poisoned");
+    if impls.iter().any(|imp| Arc::ptr_eq(&imp.tr, tr)) {
+        return Err(format!(
+            "Trait {} is already implemented for {}",
+            tr.name, ty.name
+        ));
+    }
+    impls.push(TraitImpl {
+        tr: tr.clone(),
+        methods,
+    });
+    Result::Ok(())
+}
+
+/// Error value bound by `catch (e)`, either thrown by the script or raised by the runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptError {
+    pub message: String,
+    pub kind: String,
+    pub line: u64,
+}
+
+impl ScriptError {
+    /// Kind of errors created by `throw` from a value that isn't already an error.
+    pub const THROWN: &'static str = "Error";
+    /// Kind of errors raised by the runtime, like mismatched operand types.
+    pub const RUNTIME: &'static str = "RuntimeError";
+
+    /// Converts a thrown value into an error, values that are already errors are rethrown as is
+    /// so they keep the line they were first raised on.
+    pub fn from_thrown(value: Value, line: u64) -> Self {
+        match value {
+            Value::Obj(Object::Error(err)) => err,
+            _ => Self {
+                message: value.to_string(),
+                kind: Self::THROWN.into(),
+                line,
+            },
+        }
+    }
+
+    /// Converts an error from the runtime into an error the script can catch. Errors from a
+    /// `throw` keep their original value.
+    pub fn from_runtime(err: anyhow::Error, line: u64) -> Self {
+        match err.downcast::<Thrown>() {
+            Result::Ok(Thrown(thrown)) => thrown,
+            Err(err) => Self {
+                message: err.to_string(),
+                kind: Self::RUNTIME.into(),
+                line,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} (line {})", self.kind, self.message, self.line)
+    }
+}
+
+/// Carries a thrown error out of the VM or Interpreter until a catch handler receives it.
+#[derive(Error, Debug)]
+#[error("Uncaught {0}")]
+pub struct Thrown(pub ScriptError);
+
+/// Function implemented in Rust and callable from scripts. Returning an `Err` aborts the script,
+/// failures the script should be able to handle are returned as `Value::err` instead.
+#[derive(Debug, Clone, Copy)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&mut dyn Caller, &[Value]) -> anyhow::Result<Value>,
+}
+
+/// Runs script functions for native code, implemented by the VM and the Interpreter so natives
+/// and iterators can call back into the script running them.
+pub trait Caller {
+    fn call(&mut self, callee: &Value, args: &[Value]) -> anyhow::Result<Value>;
+    /// Runs `generator` up to its next yield, None once its body has returned.
+    fn resume(&mut self, generator: &Arc<Generator>) -> anyhow::Result<Option<Value>>;
+}
+
+/// Where `print` writes, stdout unless the VM or Interpreter running the script is given another
+/// writer, like a buffer to read the script's output back from.
+#[derive(Clone)]
+pub struct Output(Arc<Mutex<dyn Write + Send>>);
+
+impl Output {
+    pub fn new(writer: Arc<Mutex<dyn Write + Send>>) -> Self {
+        Self(writer)
+    }
+
+    pub fn println(&self, value: &Value) -> anyhow::Result<()> {
+        let mut writer = self.0.lock().expect("output lock poisoned");
+        writeln!(writer, "{}", value)?;
+        Ok(())
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(std::io::stdout())))
+    }
+}
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Output")
+    }
+}
+
+impl NativeFunction {
+    pub fn call(&self, caller: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+        if args.len() != self.arity {
+            bail!(
+                "{} expected {} argument(s) but got {}",
+                self.name,
+                self.arity,
+                args.len()
+            );
+        }
+        (self.function)(caller, args)
+    }
+}
+
+/// Function declared with `fn`. Consecutive declarations with the same name become clauses of
+/// one function, a call runs the first clause whose parameters and guard accept the arguments.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub clauses: Vec<Clause>,
+    /// Declared with `fn*`, a call returns a Generator instead of running the body
+    pub generator: bool,
+    pub attributes: Attributes,
+}
+
+/// Call of a `fn*` function. Each resume runs the body up to its next `yield`, the generator is
+/// done once the body returns.
+#[derive(Debug)]
+pub struct Generator {
+    pub name: String,
+    state: Mutex<GeneratorState>,
+}
+
+#[derive(Debug)]
+pub enum GeneratorState {
+    /// Frame saved by the VM at the start of the body or at a yield
+    Frame(SuspendedFrame),
+    /// Body run by the Interpreter on its own thread, waiting at the start or at a yield
+    Thread(GeneratorThread),
+    Running,
+    Done,
+}
+
+impl Generator {
+    pub fn new(name: &str, state: GeneratorState) -> Self {
+        Self {
+            name: name.to_string(),
+            state: Mutex::new(state),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, GeneratorState> {
+        self.state.lock().expect("generator lock poisoned")
+    }
+
+    /// Takes the suspended state to resume it and marks the generator as running, None once
+    /// it is done.
+    pub fn take(&self) -> anyhow::Result<Option<GeneratorState>> {
+        let mut state = self.state();
+        match std::mem::replace(&mut *state, GeneratorState::Running) {
+            GeneratorState::Running => bail!("Generator '{}' is already running", self.name),
+            GeneratorState::Done => {
+                *state = GeneratorState::Done;
+                Ok(None)
+            }
+            suspended => Ok(Some(suspended)),
+        }
+    }
+
+    pub fn suspend(&self, state: GeneratorState) {
+        *self.state() = state;
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        matches!(
+            *self.state(),
+            GeneratorState::Frame(_) | GeneratorState::Thread(_)
+        )
+    }
+
+    pub fn finish(&self) {
+        *self.state() = GeneratorState::Done;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Clause {
+    pub params: Vec<Param>,
+    /// Source of the clause head, `name(a, b) where guard`, listed when no clause matches
+    pub signature: String,
+    pub body: FunctionBody,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param {
+    /// Binds the argument to a name, `_` binds nothing but accepts anything
+    Name(String),
+    /// Only accepts arguments equal to the literal, `fn fib(0) { ... }`
+    Literal(Value),
+    /// `name = default`, the default is evaluated by the callee when no argument is given
+    Default(String),
+    /// `...name`, collects the remaining positional arguments into a List
+    Rest(String),
+    /// `Some(name)`, only accepts Some and binds the value inside it to the name
+    Some(String),
+}
+
+/// Checks a clause's parameter list for duplicate names, a rest parameter that isn't last, and
+/// required parameters following ones with a default.
+pub fn check_params(params: &[Param]) -> Result<(), String> {
+    let mut names: Vec<&str> = Vec::new();
+    let mut has_default = false;
+    for (i, param) in params.iter().enumerate() {
+        match param {
+            Param::Name(name) | Param::Default(name) | Param::Rest(name) | Param::Some(name)
+                if name != "_" =>
+            {
+                if names.contains(&name.as_str()) {
+                    return Err(format!("Duplicate parameter '{}'", name));
+                }
+                names.push(name);
+            }
+            _ => {}
+        }
+        match param {
+            Param::Default(_) => has_default = true,
+            Param::Rest(name) if i + 1 != params.len() => {
+                return Err(format!(
+                    "Rest parameter '...{}' must be the last parameter",
+                    name
+                ));
+            }
+            Param::Name(_) | Param::Literal(_) | Param::Some(_) if has_default => {
+                return Err(format!(
+                    "Parameter {} without a default can't follow a parameter with one",
+                    i + 1
+                ));
+            }
+            _ => {}
+        }
+    }
+    Result::Ok(())
+}
+
+impl Param {
+    /// Name a keyword argument can bind to, literals and rest parameters can't be named
+    pub fn keyword(&self) -> Option<&str> {
+        match self {
+            Param::Name(name) | Param::Default(name) => Some(name),
+            Param::Literal(_) | Param::Rest(_) | Param::Some(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FunctionBody {
+    /// Compiled clause, starting with the default parameters and guard check if it has them
+    Chunk(Arc<Chunk>),
+    Ast {
+        /// Default value of each parameter, parallel to `Clause::params`
+        defaults: Vec<Option<Expr>>,
+        guard: Option<Box<Expr>>,
+        body: Box<Expr>,
+    },
+}
+
+impl Clause {
+    /// Matches arguments to parameters. `args` ends with one value for each of `keywords`, the
+    /// rest are positional. Parameters with a default and no argument are left as None.
+    pub fn bind(&self, args: &[Value], keywords: &[String]) -> Result<Vec<Option<Value>>, String> {
+        bind_params(&self.params, args, keywords)
+    }
+}
+
+/// Matches arguments to parameters, see `Clause::bind`.
+pub fn bind_params(
+    params: &[Param],
+    args: &[Value],
+    keywords: &[String],
+) -> Result<Vec<Option<Value>>, String> {
+    let (positional, named) = args.split_at(args.len() - keywords.len());
+    let mut bound: Vec<Option<Value>> = vec![None; params.len()];
+    let rest = params.iter().position(|p| matches!(p, Param::Rest(_)));
+    let fixed = rest.unwrap_or(params.len());
+    if positional.len() > fixed && rest.is_none() {
+        return Err(arity_error(params, args.len()));
+    }
+    for (slot, arg) in bound
+        .iter_mut()
+        .zip(&positional[..positional.len().min(fixed)])
+    {
+        *slot = Some(arg.clone());
+    }
+    if let Some(rest) = rest {
+        let extra = positional.get(fixed..).unwrap_or_default();
+        bound[rest] = Some(Value::list(extra.to_vec()));
+    }
+
+    for (keyword, arg) in keywords.iter().zip(named) {
+        let Some(i) = params.iter().position(|p| p.keyword() == Some(keyword)) else {
+            return Err(format!("got an unexpected keyword argument '{}'", keyword));
+        };
+        if bound[i].is_some() {
+            return Err(format!("got multiple values for argument '{}'", keyword));
+        }
+        bound[i] = Some(arg.clone());
+    }
+
+    for (i, (param, value)) in params.iter().zip(&mut bound).enumerate() {
+        match (param, value) {
+            (Param::Name(name), None) if !keywords.is_empty() => {
+                return Err(format!("missing argument '{}'", name));
+            }
+            (Param::Name(_) | Param::Literal(_) | Param::Some(_), None) => {
+                return Err(arity_error(params, args.len()));
+            }
+            (Param::Literal(literal), Some(value)) if literal != value => {
+                return Err(format!("argument {} doesn't match {}", i + 1, literal));
+            }
+            (Param::Some(name), Some(value)) => {
+                let Value::Obj(Object::Option(Some(inner))) = value else {
+                    return Err(format!("argument {} doesn't match Some({})", i + 1, name));
+                };
+                *value = inner.as_ref().clone();
+            }
+            _ => {}
+        }
+    }
+    Result::Ok(bound)
+}
+
+pub fn arity_error(params: &[Param], argc: usize) -> String {
+    let required = params
+        .iter()
+        .filter(|p| matches!(p, Param::Name(_) | Param::Literal(_) | Param::Some(_)))
+        .count();
+    let expected = if params.iter().any(|p| matches!(p, Param::Rest(_))) {
+        format!("at least {}", required)
+    } else if required < params.len() {
+        format!("{} to {}", required, params.len())
+    } else {
+        required.to_string()
+    };
+    format!("expected {} argument(s) but got {}", expected, argc)
+}
+
+impl Function {
+    /// First clause from `start` whose parameters accept the arguments, with the bound values.
+    /// Guards are not checked here, a clause whose guard fails continues the search from the
+    /// clause after it.
+    pub fn find_clause(
+        &self,
+        start: usize,
+        args: &[Value],
+        keywords: &[String],
+    ) -> Option<(usize, Vec<Option<Value>>)> {
+        (start..self.clauses.len())
+            .find_map(|i| self.clauses[i].bind(args, keywords).ok().map(|b| (i, b)))
+    }
+
+    pub fn no_match(&self, args: &[Value], keywords: &[String]) -> anyhow::Error {
+        if let [clause] = self.clauses.as_slice() {
+            if let Err(reason) = clause.bind(args, keywords) {
+                return anyhow!("{} {}", self.name, reason);
+            }
+        }
+        let (positional, named) = args.split_at(args.len() - keywords.len());
+        let args: Vec<String> = positional
+            .iter()
+            .map(|arg| arg.to_string())
+            .chain(
+                keywords
+                    .iter()
+                    .zip(named)
+                    .map(|(keyword, arg)| format!("{}: {}", keyword, arg)),
+            )
+            .collect();
+        let tried: Vec<String> = self
+            .clauses
+            .iter()
+            .map(|clause| format!("\n    {}", clause.signature))
+            .collect();
+        anyhow!(
+            "No clause of '{}' matched arguments ({}), tried:{}",
+            self.name,
+            args.join(", "),
+            tried.concat()
+        )
+    }
+}
+
+/// Integer range created with `start..end` (exclusive) or `start..=end` (inclusive). A range is
+/// its own iterator, iterating advances `start` until the range is empty.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub inclusive: bool,
+}
+
+impl Range {
+    pub fn new(start: &Value, end: &Value, inclusive: bool) -> anyhow::Result<Self> {
+        let range = match (start.as_integer(), end.as_integer()) {
+            (Result::Ok(start), Result::Ok(end)) => Self {
+                start,
+                end,
+                inclusive,
+            },
+            _ => bail!(
+                "Range bounds must be 2 integers, got: {} and {}",
+                start.integer_type_string(),
+                end.integer_type_string()
+            ),
+        };
+        if range.start > range.end {
+            bail!(
+                "Range {} is inverted, start must not be greater than end",
+                range
+            );
+        }
+        Ok(range)
+    }
+
+    /// Whether the integer `n` is in the range, ranges hold no fractional numbers.
+    pub fn contains(&self, n: f64) -> bool {
+        let (start, end) = (self.start as f64, self.end as f64);
+        if n.fract() != 0.0 {
+            false
+        } else if self.inclusive {
+            n >= start && n <= end
+        } else {
+            n >= start && n < end
+        }
+    }
+
+    /// Number of integers in the range, saturating when all of i64 doesn't fit in a u64.
+    pub fn size(&self) -> u64 {
+        let span = (self.end as i128 - self.start as i128).max(0) + self.inclusive as i128;
+        u64::try_from(span).unwrap_or(u64::MAX)
+    }
+
+    /// Converts the range into `start..end` indices of a sequence with `len` elements, erroring
+    /// if any part of the range falls outside of the sequence.
+    pub fn slice_bounds(&self, len: usize) -> anyhow::Result<(usize, usize)> {
+        let end = if self.inclusive {
+            self.end.checked_add(1)
+        } else {
+            Some(self.end)
+        };
+        match end {
+            Some(end) if self.start >= 0 && end as u64 <= len as u64 => {
+                Ok((self.start as usize, end as usize))
+            }
+            _ => bail!("Range {} out of bounds for length {}", self, len),
+        }
+    }
+}
+
+impl Iterator for Range {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start < self.end {
+            let n = self.start;
+            self.start += 1;
+            Some(n)
+        } else if self.inclusive && self.start == self.end {
+            // turn into an empty exclusive range so i64::MAX doesn't overflow
+            self.inclusive = false;
+            Some(self.start)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dots = if self.inclusive { "..=" } else { ".." };
+        write!(f, "{}{}{}", self.start, dots, self.end)
+    }
+}
+
+/// Lazy iterator returned by `iter` and the adapters, values are only produced as a `for` loop,
+/// `next` or `collect` asks for them. Adapters share the iterator they wrap, stepping either one
+/// advances both.
+///
+/// Any struct instance with a `next()` method returning `Some(value)`, or `None` once it is done,
+/// can be iterated.
+#[derive(Debug)]
+pub enum Iter {
+    List {
+        values: Vec<Value>,
+        next: RwLock<usize>,
+    },
+    Range(RwLock<Range>),
+    /// Struct instance stepped by calling its `next` method
+    Instance {
+        receiver: Value,
+        next: Arc<Function>,
+    },
+    Map {
+        source: Arc<Iter>,
+        f: Value,
+    },
+    Filter {
+        source: Arc<Iter>,
+        predicate: Value,
+    },
+    Take {
+        source: Arc<Iter>,
+        remaining: RwLock<usize>,
+    },
+    /// Pairs of values as 2 element lists, ends with the shorter iterator
+    Zip(Arc<Iter>, Arc<Iter>),
+    /// `[index, value]` lists
+    Enumerate {
+        source: Arc<Iter>,
+        index: RwLock<usize>,
+    },
+    Generator(Arc<Generator>),
+}
+
+impl Iter {
+    /// Iterator over `value`, an iterator is returned as is so it keeps its position.
+    pub fn from_value(value: &Value) -> anyhow::Result<Arc<Self>> {
+        let iter = match value {
+            Value::Obj(Object::Iterator(iter)) => return Ok(iter.clone()),
+            Value::Obj(Object::List(list)) => Self::List {
+                values: list.values().clone(),
+                next: RwLock::new(0),
+            },
+            // iterating over a map gives its keys
+            Value::Obj(Object::Map(map)) => Self::List {
+                values: map.entries().into_iter().map(|(key, _)| key).collect(),
+                next: RwLock::new(0),
+            },
+            Value::Obj(Object::Range(range)) => Self::Range(RwLock::new(*range)),
+            Value::Obj(Object::Generator(generator)) => Self::Generator(generator.clone()),
+            Value::Obj(Object::Instance(instance)) => match instance.ty.find_method("next") {
+                Some(next) => Self::Instance {
+                    receiver: value.clone(),
+                    next,
+                },
+                None => bail!(
+                    "Cannot iterate over {}, it has no next() method",
+                    instance.ty.name
+                ),
+            },
+            _ => bail!("Cannot iterate over {}", value.type_string()),
+        };
+        Ok(Arc::new(iter))
+    }
+
+    /// Produces the next value, None once the iterator is done. Functions given to adapters are
+    /// run through `caller`.
+    pub fn next(&self, caller: &mut dyn Caller) -> anyhow::Result<Option<Value>> {
+        match self {
+            Self::List { values, next } => {
+                let mut next = state(next);
+                let value = values.get(*next).cloned();
+                if value.is_some() {
+                    *next += 1;
+                }
+                Ok(value)
+            }
+            Self::Range(range) => Ok(state(range).next().map(|n| Value::Number(n as f64))),
+            Self::Instance { receiver, next } => {
+                let method = Value::Obj(Object::Function(next.clone()));
+                match caller.call(&method, std::slice::from_ref(receiver))? {
+                    Value::Obj(Object::Option(value)) => Ok(value.map(|value| *value)),
+                    other => bail!(
+                        "{}.next() must return Some(value) or None, got: {}",
+                        receiver.type_string(),
+                        other.type_string()
+                    ),
+                }
+            }
+            Self::Map { source, f } => match source.next(caller)? {
+                Some(value) => Ok(Some(caller.call(f, &[value])?)),
+                None => Ok(None),
+            },
+            Self::Filter { source, predicate } => {
+                while let Some(value) = source.next(caller)? {
+                    if !caller
+                        .call(predicate, std::slice::from_ref(&value))?
+                        .is_falsey()
+                    {
+                        return Ok(Some(value));
+                    }
+                }
+                Ok(None)
+            }
+            Self::Take { source, remaining } => {
+                {
+                    let mut remaining = state(remaining);
+                    if *remaining == 0 {
+                        return Ok(None);
+                    }
+                    *remaining -= 1;
+                }
+                source.next(caller)
+            }
+            Self::Zip(left, right) => {
+                let Some(left) = left.next(caller)? else {
+                    return Ok(None);
+                };
+                let Some(right) = right.next(caller)? else {
+                    return Ok(None);
+                };
+                Ok(Some(Value::list(vec![left, right])))
+            }
+            Self::Enumerate { source, index } => {
+                let Some(value) = source.next(caller)? else {
+                    return Ok(None);
+                };
+                let mut index = state(index);
+                let pair = vec![Value::Number(*index as f64), value];
+                *index += 1;
+                Ok(Some(Value::list(pair)))
+            }
+            Self::Generator(generator) => caller.resume(generator),
+        }
+    }
+}
+
+/// Position of an iterator, only locked while it is updated so adapter functions can step it.
+fn state<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().expect("iterator lock poisoned")
 }
 
 #[derive(Debug, Clone)]
@@ -101,10 +1311,68 @@ pub enum Value {
     // String(String),
     Boolean(bool),
     Nil,
+    Atom(Atom),
     Obj(Object),
 }
 
 impl Value {
+    pub fn list(values: Vec<Value>) -> Self {
+        Self::Obj(Object::List(Arc::new(List(RwLock::new(values)))))
+    }
+
+    /// Map of `entries`, a later entry replaces the value of an earlier one with the same key.
+    pub fn map(entries: Vec<(Value, Value)>) -> anyhow::Result<Self> {
+        let map = Map::default();
+        for (key, value) in entries {
+            map.insert(key, value)?;
+        }
+        Ok(Self::Obj(Object::Map(Arc::new(map))))
+    }
+
+    pub fn some(value: Value) -> Self {
+        Self::Obj(Object::Option(Some(Box::new(value))))
+    }
+
+    pub const fn none() -> Self {
+        Self::Obj(Object::Option(None))
+    }
+
+    pub fn ok(value: Value) -> Self {
+        Self::Obj(Object::Result(std::result::Result::Ok(Box::new(value))))
+    }
+
+    pub fn err(value: Value) -> Self {
+        Self::Obj(Object::Result(Err(Box::new(value))))
+    }
+
+    /// Resolves the operand of the postfix `?` operator. Ok(x) and Some(x) unwrap to x, while
+    /// Err and None are returned as the error so the caller can return early with them.
+    pub fn try_unwrap(&self) -> anyhow::Result<std::result::Result<Value, Value>> {
+        match self {
+            Value::Obj(Object::Result(std::result::Result::Ok(value)))
+            | Value::Obj(Object::Option(Some(value))) => {
+                Ok(std::result::Result::Ok(value.as_ref().clone()))
+            }
+            Value::Obj(Object::Result(Err(_))) | Value::Obj(Object::Option(None)) => {
+                Ok(Err(self.clone()))
+            }
+            _ => bail!(
+                "Operand of '?' must be a Result or Option, got: {}",
+                self.type_string()
+            ),
+        }
+    }
+
+    /// Resolves the left hand side of `??`. Nil and None have no value, Some(x) unwraps to x and
+    /// any other value is returned as is.
+    pub fn coalesce(&self) -> Option<Value> {
+        match self {
+            Value::Nil | Value::Obj(Object::Option(None)) => None,
+            Value::Obj(Object::Option(Some(value))) => Some(value.as_ref().clone()),
+            _ => Some(self.clone()),
+        }
+    }
+
     pub const fn is_falsey(&self) -> bool {
         match self {
             Value::Boolean(false) => true,
@@ -144,6 +1412,29 @@ impl Value {
         }
     }
 
+    /// Numbers are stored as f64, so a value is only an integer if it has no fractional part and
+    /// fits in an i64.
+    pub fn as_integer(&self) -> anyhow::Result<i64> {
+        match self {
+            Self::Number(n)
+                if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n <= i64::MAX as f64 =>
+            {
+                Ok(*n as i64)
+            }
+            _ => {
+                let value = self.clone();
+                let type_str = value.integer_type_string();
+                bail!(
+                    "{}",
+                    AstWalkError::TypeError {
+                        value,
+                        message: format!("Expected Integer, got: {}", type_str)
+                    }
+                )
+            }
+        }
+    }
+
     pub fn as_bool(&self) -> anyhow::Result<bool> {
         let value = self.clone();
         if let Self::Boolean(b) = value {
@@ -170,14 +1461,205 @@ impl Value {
         }
     }
 
+    /// `in` operator, ranges contain numbers that fall within their bounds, strings contain
+    /// their substrings and maps contain their keys.
+    pub fn contains(&self, needle: &Value) -> anyhow::Result<bool> {
+        match (self, needle) {
+            (Value::Obj(Object::Range(range)), Value::Number(n)) => Ok(range.contains(*n)),
+            (Value::Obj(Object::String(string)), Value::Obj(Object::String(substr))) => {
+                Ok(string.contains(substr.as_str()))
+            }
+            (Value::Obj(Object::List(list)), _) => Ok(list.values().contains(needle)),
+            (Value::Obj(Object::Map(map)), _) => Ok(map.get(needle)?.is_some()),
+            _ => bail!(
+                "Operands of 'in' must be a Number in a Range, a String in a String or a value in a List or Map, got: {} in {}",
+                needle.type_string(),
+                self.type_string()
+            ),
+        }
+    }
+
+    /// Indexes into a string by character or a list by element, either a single one with an
+    /// integer or a slice with a range, or into a map by key.
+    pub fn index(&self, index: &Value) -> anyhow::Result<Value> {
+        match (self, index) {
+            (Value::Obj(Object::Map(map)), _) => match map.get(index)? {
+                Some(value) => Ok(value),
+                None => bail!("Map has no key {}", index),
+            },
+            (Value::Obj(Object::List(list)), Value::Obj(Object::Range(range))) => {
+                let values = list.values();
+                let (start, end) = range.slice_bounds(values.len())?;
+                Ok(Value::list(values[start..end].to_vec()))
+            }
+            (Value::Obj(Object::List(list)), _) => {
+                let values = list.values();
+                let i = index.as_integer()?;
+                match usize::try_from(i).ok().and_then(|i| values.get(i)) {
+                    Some(value) => Ok(value.clone()),
+                    None => bail!("List index {} out of bounds for length {}", i, values.len()),
+                }
+            }
+            (Value::Obj(Object::String(string)), Value::Obj(Object::Range(range))) => {
+                let len = string.chars().count();
+                let (start, end) = range.slice_bounds(len)?;
+                let substr = string.chars().skip(start).take(end - start).collect();
+                Ok(Value::Obj(Object::String(substr)))
+            }
+            (Value::Obj(Object::String(string)), _) => {
+                let i = index.as_integer()?;
+                let len = string.chars().count();
+                match usize::try_from(i).ok().and_then(|i| string.chars().nth(i)) {
+                    Some(c) => Ok(Value::Obj(Object::String(c.to_string()))),
+                    None => bail!("String index {} out of bounds for length {}", i, len),
+                }
+            }
+            _ => bail!(
+                "Cannot index into {} with {}",
+                self.type_string(),
+                index.type_string()
+            ),
+        }
+    }
+
+    /// `value[index] = new_value`, only the elements of a list and the keys of a map are
+    /// assignable. Assigning to a key a map doesn't have adds it.
+    pub fn set_index(&self, index: &Value, value: Value) -> anyhow::Result<()> {
+        if let Value::Obj(Object::Map(map)) = self {
+            return map.insert(index.clone(), value);
+        }
+        let Value::Obj(Object::List(list)) = self else {
+            bail!("Cannot assign to an index of {}", self.type_string());
+        };
+        let i = index.as_integer()?;
+        let mut values = list.values_mut();
+        let len = values.len();
+        match usize::try_from(i).ok().and_then(|i| values.get_mut(i)) {
+            Some(element) => {
+                *element = value;
+                Ok(())
+            }
+            None => bail!("List index {} out of bounds for length {}", i, len),
+        }
+    }
+
+    /// `value::name` path access, used to get the variants of an enum.
+    pub fn get_path(&self, name: &str) -> anyhow::Result<Value> {
+        match self {
+            Value::Obj(Object::Enum(ty)) => get_variant(ty, name),
+            _ => bail!("Cannot access '{}' on {}", name, self.type_string()),
+        }
+    }
+
+    /// Calls a native function or enum variant constructor.
+    pub fn call(&self, caller: &mut dyn Caller, args: &[Value]) -> anyhow::Result<Value> {
+        match self {
+            Value::Obj(Object::Native(native)) => native.call(caller, args),
+            Value::Obj(Object::VariantConstructor(ty, variant)) => {
+                EnumType::construct(ty, *variant, args, &[])
+            }
+            Value::Obj(Object::Struct(ty)) => StructType::construct(ty, args, &[]),
+            // script functions run on the VM or Interpreter, which call them before getting here
+            Value::Obj(Object::Function(function)) => {
+                bail!("Function '{}' cannot be called from here", function.name)
+            }
+            Value::Obj(Object::BoundMethod(method)) => {
+                bail!(
+                    "Function '{}' cannot be called from here",
+                    method.method.name
+                )
+            }
+            _ => bail!("Can only call functions, got: {}", self.type_string()),
+        }
+    }
+
+    /// `value.name` property access, only built in objects have properties for now.
+    pub fn get_property(&self, name: &str) -> anyhow::Result<Value> {
+        match (self, name) {
+            (Value::Obj(Object::Error(err)), "message") => {
+                Ok(Value::Obj(Object::String(err.message.clone())))
+            }
+            (Value::Obj(Object::Error(err)), "kind") => {
+                Ok(Value::Obj(Object::String(err.kind.clone())))
+            }
+            (Value::Obj(Object::Error(err)), "line") => Ok(Value::Number(err.line as f64)),
+            (Value::Obj(Object::Instance(instance)), _) => instance.get(name),
+            (Value::Obj(Object::EnumInstance(instance)), _) => {
+                let position = match &instance.variant().fields {
+                    VariantFields::Tuple(_) => name.parse::<usize>().ok(),
+                    VariantFields::Struct(fields) => fields.iter().position(|f| f == name),
+                    VariantFields::Unit => None,
+                };
+                match position.and_then(|i| instance.values.get(i)) {
+                    Some(value) => Ok(value.clone()),
+                    None => bail!(
+                        "{}::{} has no field '{}'",
+                        instance.ty.name,
+                        instance.variant().name,
+                        name
+                    ),
+                }
+            }
+            _ => bail!("{} has no property '{}'", self.type_string(), name),
+        }
+    }
+
+    /// `value.name = new_value`, only struct instances have assignable fields.
+    pub fn set_property(&self, name: &str, value: Value) -> anyhow::Result<()> {
+        match self {
+            Value::Obj(Object::Instance(instance)) => instance.set(name, value),
+            _ => bail!(
+                "Cannot assign to property '{}' of {}",
+                name,
+                self.type_string()
+            ),
+        }
+    }
+
+    /// Method `name` of the struct instance's implementation of `tr`, None if it doesn't have
+    /// one or isn't a struct instance.
+    pub fn trait_method(&self, tr: &Arc<Trait>, name: &str) -> Option<Arc<Function>> {
+        let Value::Obj(Object::Instance(instance)) = self else {
+            return None;
+        };
+        instance.ty.impl_method(tr, name)
+    }
+
     pub fn type_string(&self) -> String {
         match self {
             Value::Number(_) => "Number".into(),
             Value::Boolean(_) => "Boolean".into(),
             Value::Obj(obj) => match obj {
                 Object::String(_) => "String".into(),
+                Object::List(_) => "List".into(),
+                Object::Map(_) => "Map".into(),
+                Object::Range(_) => "Range".into(),
+                Object::Option(_) => "Option".into(),
+                Object::Result(_) => "Result".into(),
+                Object::Native(_) => "NativeFunction".into(),
+                Object::Error(_) => "Error".into(),
+                Object::Enum(_) => "Enum".into(),
+                Object::VariantConstructor(..) => "VariantConstructor".into(),
+                Object::EnumInstance(instance) => instance.ty.name.clone(),
+                Object::Function(_) => "Function".into(),
+                Object::Struct(_) => "Struct".into(),
+                Object::Instance(instance) => instance.ty.name.clone(),
+                Object::BoundMethod(_) => "BoundMethod".into(),
+                Object::Trait(_) => "Trait".into(),
+                Object::Iterator(_) => "Iterator".into(),
+                Object::Generator(_) => "Generator".into(),
             },
             Value::Nil => "Unit".into(),
+            Value::Atom(_) => "Atom".into(),
+        }
+    }
+
+    /// Same as type_string, but includes the value of numbers that aren't integers so errors
+    /// from integer-only operators don't read as "expected Integer, got: Number".
+    pub fn integer_type_string(&self) -> String {
+        match self {
+            Value::Number(n) if n.fract() != 0.0 => format!("Number({})", n),
+            _ => self.type_string(),
         }
     }
 
@@ -189,6 +1671,10 @@ impl Value {
         }
     }
 
+    pub const fn is_number(&self) -> bool {
+        matches!(self, Self::Number(_))
+    }
+
     pub const fn is_nil(&self) -> bool {
         if let Self::Nil = self {
             true
@@ -223,6 +1709,130 @@ impl PartialEq for Value {
                         false
                     }
                 }
+                Object::List(left) => {
+                    if let Value::Obj(Object::List(right)) = other {
+                        Arc::ptr_eq(left, right) || *left.values() == *right.values()
+                    } else {
+                        false
+                    }
+                }
+                // equal with the same entries in any order
+                Object::Map(left) => {
+                    if let Value::Obj(Object::Map(right)) = other {
+                        Arc::ptr_eq(left, right)
+                            || left.len() == right.len()
+                                && left.entries().iter().all(|(key, value)| {
+                                    right.get(key).ok().flatten().as_ref() == Some(value)
+                                })
+                    } else {
+                        false
+                    }
+                }
+                Object::Range(left) => {
+                    if let Value::Obj(Object::Range(right)) = other {
+                        left == right
+                    } else {
+                        false
+                    }
+                }
+                Object::Option(left) => {
+                    if let Value::Obj(Object::Option(right)) = other {
+                        left == right
+                    } else {
+                        false
+                    }
+                }
+                Object::Result(left) => {
+                    if let Value::Obj(Object::Result(right)) = other {
+                        left == right
+                    } else {
+                        false
+                    }
+                }
+                Object::Native(left) => {
+                    if let Value::Obj(Object::Native(right)) = other {
+                        left.name == right.name
+                    } else {
+                        false
+                    }
+                }
+                Object::Error(left) => {
+                    if let Value::Obj(Object::Error(right)) = other {
+                        left == right
+                    } else {
+                        false
+                    }
+                }
+                Object::Enum(left) => {
+                    if let Value::Obj(Object::Enum(right)) = other {
+                        Arc::ptr_eq(left, right)
+                    } else {
+                        false
+                    }
+                }
+                Object::VariantConstructor(left, lvariant) => {
+                    if let Value::Obj(Object::VariantConstructor(right, rvariant)) = other {
+                        Arc::ptr_eq(left, right) && lvariant == rvariant
+                    } else {
+                        false
+                    }
+                }
+                Object::EnumInstance(left) => {
+                    if let Value::Obj(Object::EnumInstance(right)) = other {
+                        left == right
+                    } else {
+                        false
+                    }
+                }
+                Object::Function(left) => {
+                    if let Value::Obj(Object::Function(right)) = other {
+                        Arc::ptr_eq(left, right)
+                    } else {
+                        false
+                    }
+                }
+                Object::Struct(left) => {
+                    if let Value::Obj(Object::Struct(right)) = other {
+                        Arc::ptr_eq(left, right)
+                    } else {
+                        false
+                    }
+                }
+                Object::Instance(left) => {
+                    if let Value::Obj(Object::Instance(right)) = other {
+                        left == right
+                    } else {
+                        false
+                    }
+                }
+                Object::BoundMethod(left) => {
+                    if let Value::Obj(Object::BoundMethod(right)) = other {
+                        Arc::ptr_eq(&left.method, &right.method) && left.receiver == right.receiver
+                    } else {
+                        false
+                    }
+                }
+                Object::Trait(left) => {
+                    if let Value::Obj(Object::Trait(right)) = other {
+                        Arc::ptr_eq(left, right)
+                    } else {
+                        false
+                    }
+                }
+                Object::Iterator(left) => {
+                    if let Value::Obj(Object::Iterator(right)) = other {
+                        Arc::ptr_eq(left, right)
+                    } else {
+                        false
+                    }
+                }
+                Object::Generator(left) => {
+                    if let Value::Obj(Object::Generator(right)) = other {
+                        Arc::ptr_eq(left, right)
+                    } else {
+                        false
+                    }
+                }
             },
             Value::Nil => {
                 if let Value::Nil = other {
@@ -231,6 +1841,13 @@ impl PartialEq for Value {
                     false
                 }
             }
+            Value::Atom(left) => {
+                if let Value::Atom(right) = other {
+                    left == right
+                } else {
+                    false
+                }
+            }
         }
     }
 }
@@ -248,8 +1865,40 @@ impl fmt::Display for Value {
             Value::Boolean(b) => b.to_string(),
             Value::Obj(obj) => match obj {
                 Object::String(string) => string.to_owned(),
+                Object::List(list) => {
+                    let values: Vec<String> = list.values().iter().map(|v| v.to_string()).collect();
+                    format!("[{}]", values.join(", "))
+                }
+                Object::Map(map) => {
+                    let entries: Vec<String> = map
+                        .entries()
+                        .iter()
+                        .map(|(key, value)| format!("{} => {}", key, value))
+                        .collect();
+                    format!("%{{{}}}", entries.join(", "))
+                }
+                Object::Range(range) => range.to_string(),
+                Object::Option(Some(value)) => format!("Some({})", value),
+                Object::Option(None) => String::from("None"),
+                Object::Result(std::result::Result::Ok(value)) => format!("Ok({})", value),
+                Object::Result(Err(value)) => format!("Err({})", value),
+                Object::Native(native) => format!("<native fn {}>", native.name),
+                Object::Error(err) => err.to_string(),
+                Object::Enum(ty) => format!("<enum {}>", ty.name),
+                Object::VariantConstructor(ty, variant) => {
+                    format!("<variant {}::{}>", ty.name, ty.variants[*variant].name)
+                }
+                Object::EnumInstance(instance) => instance.to_string(),
+                Object::Function(function) => format!("<fn {}>", function.name),
+                Object::Struct(ty) => format!("<struct {}>", ty.name),
+                Object::Instance(instance) => instance.to_string(),
+                Object::BoundMethod(method) => format!("<method {}>", method.method.name),
+                Object::Trait(tr) => format!("<trait {}>", tr.name),
+                Object::Iterator(_) => String::from("<iterator>"),
+                Object::Generator(generator) => format!("<generator {}>", generator.name),
             },
             Value::Nil => String::from("nil"),
+            Value::Atom(atom) => atom.to_string(),
         };
         write!(f, "{}", str)
     }
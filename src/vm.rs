@@ -1,44 +1,160 @@
-use std::{collections::HashMap, fs::File};
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
 
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 use log::debug;
 
 use crate::{
     ast::AstWalkError,
     compiler::{Chunk, Compiler},
-    value::{Object, Value},
+    native::{concat_operands, displayed, Operator, NATIVES},
+    value::{
+        implement, BoundMethod, Caller, EnumType, Function, FunctionBody, Generator,
+        GeneratorState, Iter, Object, Output, Range, ScriptError, StructType, Thrown, Value,
+    },
 };
 
 macro_rules! binary_op {
     ($vm:ident, $op:tt, $op_return:expr) => {
-        let b = $vm.pop()?.as_number()?;
-        let a = $vm.pop()?.as_number()?;
-        $vm.push($op_return(a $op b));
+        let b = $vm.pop()?;
+        let a = $vm.pop()?;
+        match (&a, &b) {
+            (Value::Number(a), Value::Number(b)) => $vm.push($op_return(a $op b)),
+            _ => bail!(
+                "Operands of '{}' must be 2 numbers, got: {} and {}.",
+                stringify!($op),
+                a.type_string(),
+                b.type_string()
+            ),
+        }
+    };
+}
+
+/// Integer operators (bitwise and ~/) only accept numbers without a fractional part. Operands are
+/// converted to i64 before $op_fn is applied, and the result is converted back into a Number.
+macro_rules! integer_op {
+    ($vm:ident, $op_str:literal, $op_fn:expr) => {
+        let b = $vm.pop()?;
+        let a = $vm.pop()?;
+        match (a.as_integer(), b.as_integer()) {
+            (Ok(a), Ok(b)) => {
+                let result: i64 = $op_fn(a, b)?;
+                $vm.push(Value::Number(result as f64));
+            }
+            _ => bail!(
+                "Operands of '{}' must be 2 integers, got: {} and {}.",
+                $op_str,
+                a.integer_type_string(),
+                b.integer_type_string()
+            ),
+        }
     };
 }
 
 pub struct VM {
     pc: usize,
-    chunk: Chunk,
+    chunk: Arc<Chunk>,
+    /// Stack index of slot 0 of the running function, locals are relative to it
+    base: usize,
     stack: Vec<Value>,
     globals: HashMap<String, Value>,
+    handlers: Vec<Handler>,
+    frames: Vec<CallFrame>,
+    /// Runs of `run_frames` nested inside natives and generator resumes, each one recursing
+    /// through the Rust stack
+    nested_runs: usize,
+    output: Output,
+}
+
+/// Exception handler pushed when entering a try (or catch) block.
+#[derive(Debug, Clone, Copy)]
+struct Handler {
+    /// Instruction to continue from with the error on top of the stack
+    target: usize,
+    /// Stack length to unwind to before pushing the error
+    stack_len: usize,
+    /// Number of call frames when the handler was pushed, deeper frames are unwound first
+    frame_depth: usize,
+    /// Whether `target` is a finally block, which a return from inside the try block runs first
+    finally: bool,
+}
+
+/// Function call in progress, holding the caller's state to resume once it returns.
+#[derive(Debug, Clone)]
+struct CallFrame {
+    function: Arc<Function>,
+    clause: usize,
+    /// Arguments as passed, kept to bind them to the next clause if this clause's guard fails
+    args: Vec<Value>,
+    keywords: Vec<String>,
+    /// Whether each parameter was given an argument, parameters that weren't run their default
+    provided: Vec<bool>,
+    chunk: Arc<Chunk>,
+    pc: usize,
+    base: usize,
+    /// Generator this frame runs the body of, yielding suspends it into the generator
+    generator: Option<Arc<Generator>>,
+}
+
+/// Generator call suspended at the start of its body or at a yield, resuming it pushes its frame
+/// back onto the VM.
+#[derive(Debug)]
+pub struct SuspendedFrame {
+    function: Arc<Function>,
+    clause: usize,
+    args: Vec<Value>,
+    keywords: Vec<String>,
+    provided: Vec<bool>,
+    chunk: Arc<Chunk>,
+    pc: usize,
+    /// Slots of the call from the callee up, moved off the value stack
+    stack: Vec<Value>,
+    /// Handlers of the try blocks it is suspended in, `stack_len` relative to the callee slot
+    handlers: Vec<Handler>,
 }
 
 impl VM {
     const STACK_SIZE: usize = 256;
+    const FRAMES_MAX: usize = 1024;
+    /// Deepest nesting of `nested_runs`, each takes around 50KB of the Rust stack in debug builds
+    const NESTED_RUNS_MAX: usize = 256;
+    /// Stack of the thread scripts run on, leaving room for `NESTED_RUNS_MAX`
+    const RUST_STACK_SIZE: usize = 64 * 1024 * 1024;
     pub fn new() -> Self {
         Self {
             pc: 0,
-            chunk: Chunk::new(),
+            chunk: Arc::new(Chunk::new()),
+            base: 0,
             stack: Vec::with_capacity(Self::STACK_SIZE),
-            globals: HashMap::new(),
+            globals: NATIVES
+                .iter()
+                .map(|native| (native.name.to_string(), Value::Obj(Object::Native(*native))))
+                .chain(Operator::globals().map(|(name, value)| (name.to_string(), value)))
+                .collect(),
+            handlers: Vec::new(),
+            frames: Vec::new(),
+            nested_runs: 0,
+            output: Output::default(),
         }
     }
 
+    /// Makes `print` write to `output` instead of stdout.
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
     pub fn reset(&mut self, chunk: Chunk) {
         self.pc = 0;
-        self.chunk = chunk;
+        self.chunk = Arc::new(chunk);
+        self.base = 0;
         self.stack.clear();
+        self.handlers.clear();
+        self.frames.clear();
     }
 
     pub fn peek_stack(&self, offset: usize) -> Option<&Value> {
@@ -68,9 +184,16 @@ impl VM {
         }
     }
 
-    pub fn interpret_script(&mut self, script_path: &str) -> anyhow::Result<()> {
-        let source = std::fs::read_to_string(script_path)?;
-        self.interpret_source(&source)
+    /// Runs the script at `script_path`, looking for the modules it declares in `search_paths`
+    /// after its own directory.
+    pub fn interpret_script(
+        &mut self,
+        script_path: &str,
+        search_paths: &[PathBuf],
+    ) -> anyhow::Result<()> {
+        let chunk = Compiler::compile_file(Path::new(script_path), search_paths)?;
+        self.reset(chunk);
+        self.run()
     }
 
     pub fn next_op(&mut self) -> Opcode {
@@ -85,12 +208,362 @@ impl VM {
         self.run()
     }
 
+    /// Runs the current chunk. Errors raised inside a try block are caught by the innermost
+    /// handler, anything else ends the script.
+    /// Runs on a thread with a `RUST_STACK_SIZE` stack, so hitting `NESTED_RUNS_MAX` raises an
+    /// error instead of overflowing whichever stack the caller is on.
     pub fn run(&mut self) -> anyhow::Result<()> {
+        thread::scope(|scope| {
+            let run = thread::Builder::new()
+                .stack_size(Self::RUST_STACK_SIZE)
+                .spawn_scoped(scope, || self.run_frames(0))?;
+            run.join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    /// Runs until the function whose frame is at `depth` returns, or the script ends when `depth`
+    /// is 0. Errors are only caught by handlers pushed inside that function, anything else is
+    /// returned to the native code that called it.
+    fn run_frames(&mut self, depth: usize) -> anyhow::Result<()> {
+        loop {
+            match self.execute(depth) {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    if self
+                        .handlers
+                        .last()
+                        .is_none_or(|handler| handler.frame_depth < depth)
+                    {
+                        return Err(err);
+                    }
+                    let handler = self.handlers.pop().expect("Handler checked above");
+                    let line = self.chunk.line_at(self.pc.saturating_sub(1));
+                    let error = ScriptError::from_runtime(err, line);
+                    while self.frames.len() > handler.frame_depth {
+                        self.leave_frame();
+                    }
+                    self.stack.truncate(handler.stack_len);
+                    self.push(Value::Obj(Object::Error(error)));
+                    self.pc = handler.target;
+                }
+            }
+        }
+    }
+
+    /// `run_frames` for a function called or resumed by native code, counted in `nested_runs`.
+    fn run_nested(&mut self, depth: usize) -> anyhow::Result<()> {
+        self.nested_runs += 1;
+        let result = self.run_frames(depth);
+        self.nested_runs -= 1;
+        result
+    }
+
+    /// Calls the value at `callee_index` with the arguments above it, keyword arguments last.
+    fn call_value(&mut self, callee_index: usize, keywords: Vec<String>) -> anyhow::Result<()> {
+        let args = self.stack.split_off(callee_index + 1);
+        match self.stack[callee_index].clone() {
+            Value::Obj(Object::Function(function)) => {
+                self.call_function(function, callee_index, args, keywords)
+            }
+            Value::Obj(Object::BoundMethod(method)) => self.call_function(
+                method.method.clone(),
+                callee_index,
+                method.args(&args),
+                keywords,
+            ),
+            Value::Obj(Object::Struct(ty)) => {
+                let instance = StructType::construct(&ty, &args, &keywords)?;
+                self.stack.truncate(callee_index);
+                self.push(instance);
+                Ok(())
+            }
+            Value::Obj(Object::VariantConstructor(ty, variant)) => {
+                let instance = EnumType::construct(&ty, variant, &args, &keywords)?;
+                self.stack.truncate(callee_index);
+                self.push(instance);
+                Ok(())
+            }
+            callee if !keywords.is_empty() => {
+                bail!("{} does not accept keyword arguments", callee)
+            }
+            callee => {
+                let result = callee.call(self, &args)?;
+                self.stack.truncate(callee_index);
+                self.push(result);
+                Ok(())
+            }
+        }
+    }
+
+    /// Method overloading `op` for the value `depth` slots below the top of the stack.
+    fn operator_method(&self, op: Operator, depth: usize) -> Option<Arc<Function>> {
+        op.method_for(self.peek_stack(depth)?)
+    }
+
+    /// Calls `method` with the top `argc` values of the stack as its arguments, the receiver
+    /// first. Its result replaces them once it returns.
+    fn call_method(&mut self, method: Arc<Function>, argc: usize) -> anyhow::Result<()> {
+        let callee_index = self.stack.len() - argc;
+        self.stack
+            .insert(callee_index, Value::Obj(Object::Function(method.clone())));
+        let args = self.stack.split_off(callee_index + 1);
+        self.call_function(method, callee_index, args, vec![])
+    }
+
+    /// Runs `method` overloading `op` to its end with the top `argc` values of the stack as its
+    /// arguments, unlike `call_method` the result is checked by `op` before it is returned.
+    fn call_operator(
+        &mut self,
+        op: Operator,
+        method: Arc<Function>,
+        argc: usize,
+    ) -> anyhow::Result<Value> {
+        let args = self.stack.split_off(self.stack.len() - argc);
+        let result = self.call(&Value::Obj(Object::Function(method)), &args)?;
+        op.check_result(result)
+    }
+
+    /// Calls `function` with the arguments above the callee at `callee_index`. Calling a
+    /// generator function binds the arguments but suspends the call before its body runs,
+    /// replacing the callee with the Generator.
+    fn call_function(
+        &mut self,
+        function: Arc<Function>,
+        callee_index: usize,
+        args: Vec<Value>,
+        keywords: Vec<String>,
+    ) -> anyhow::Result<()> {
+        if !function.generator {
+            return self.enter_clause(function, callee_index, args, keywords, 0);
+        }
+        let name = function.name.clone();
+        self.enter_clause(function, callee_index, args, keywords, 0)?;
+        let frame = self.suspend();
+        let generator = Generator::new(&name, GeneratorState::Frame(frame));
+        self.push(Value::Obj(Object::Generator(Arc::new(generator))));
+        Ok(())
+    }
+
+    /// Enters the first clause of `function` from `start` that accepts the arguments, replacing
+    /// them above the callee at `callee_index` with one slot per parameter.
+    fn enter_clause(
+        &mut self,
+        function: Arc<Function>,
+        callee_index: usize,
+        args: Vec<Value>,
+        keywords: Vec<String>,
+        start: usize,
+    ) -> anyhow::Result<()> {
+        if self.frames.len() >= Self::FRAMES_MAX {
+            bail!("Stack overflow calling '{}'", function.name);
+        }
+        let Some((clause, bound)) = function.find_clause(start, &args, &keywords) else {
+            return Err(function.no_match(&args, &keywords));
+        };
+        let FunctionBody::Chunk(chunk) = &function.clauses[clause].body else {
+            bail!("Function '{}' was not compiled to bytecode", function.name);
+        };
+        let chunk = chunk.clone();
+        self.stack.truncate(callee_index + 1);
+        let provided = bound.iter().map(Option::is_some).collect();
+        self.stack
+            .extend(bound.into_iter().map(|value| value.unwrap_or(Value::Nil)));
+        self.frames.push(CallFrame {
+            function,
+            clause,
+            args,
+            keywords,
+            provided,
+            chunk: std::mem::replace(&mut self.chunk, chunk),
+            pc: self.pc,
+            base: self.base,
+            generator: None,
+        });
+        self.pc = 0;
+        self.base = callee_index;
+        Ok(())
+    }
+
+    /// Moves the running generator's frame, slots and handlers off the VM, resuming its caller.
+    fn suspend(&mut self) -> SuspendedFrame {
+        let (chunk, pc, base) = (self.chunk.clone(), self.pc, self.base);
+        let depth = self.frames.len();
+        let first = self
+            .handlers
+            .partition_point(|handler| handler.frame_depth < depth);
+        let handlers = self
+            .handlers
+            .split_off(first)
+            .into_iter()
+            .map(|handler| Handler {
+                stack_len: handler.stack_len - base,
+                ..handler
+            })
+            .collect();
+        let frame = self.leave_frame();
+        SuspendedFrame {
+            function: frame.function,
+            clause: frame.clause,
+            args: frame.args,
+            keywords: frame.keywords,
+            provided: frame.provided,
+            chunk,
+            pc,
+            stack: self.stack.split_off(base),
+            handlers,
+        }
+    }
+
+    /// Pushes the suspended frame of `generator` back and runs it until it yields or returns.
+    fn resume_frame(
+        &mut self,
+        generator: &Arc<Generator>,
+        frame: SuspendedFrame,
+    ) -> anyhow::Result<Option<Value>> {
+        if self.frames.len() >= Self::FRAMES_MAX || self.nested_runs >= Self::NESTED_RUNS_MAX {
+            generator.finish();
+            bail!("Stack overflow resuming '{}'", generator.name);
+        }
+        let callee_index = self.stack.len();
+        let depth = self.frames.len() + 1;
+        self.stack.extend(frame.stack);
+        self.handlers
+            .extend(frame.handlers.into_iter().map(|handler| Handler {
+                stack_len: handler.stack_len + callee_index,
+                frame_depth: depth,
+                ..handler
+            }));
+        self.frames.push(CallFrame {
+            function: frame.function,
+            clause: frame.clause,
+            args: frame.args,
+            keywords: frame.keywords,
+            provided: frame.provided,
+            chunk: std::mem::replace(&mut self.chunk, frame.chunk),
+            pc: self.pc,
+            base: self.base,
+            generator: Some(generator.clone()),
+        });
+        self.pc = frame.pc;
+        self.base = callee_index;
+        if let Err(err) = self.run_nested(depth) {
+            generator.finish();
+            return Err(err);
+        }
+        let value = self.pop()?;
+        // a yield suspends the generator again, returning from the body finishes it
+        if generator.is_suspended() {
+            Ok(Some(value))
+        } else {
+            generator.finish();
+            Ok(None)
+        }
+    }
+
+    /// Pops the running function's frame and resumes its caller.
+    fn leave_frame(&mut self) -> CallFrame {
+        let frame = self
+            .frames
+            .pop()
+            .expect("Unable to leave call frame; no function is running.");
+        self.chunk = frame.chunk.clone();
+        self.pc = frame.pc;
+        self.base = frame.base;
+        frame
+    }
+
+    /// Discards the running function's slots and pushes `result` in place of the callee.
+    fn return_from_function(&mut self, result: Value) {
+        let callee_index = self.base;
+        self.leave_frame();
+        self.stack.truncate(callee_index);
+        // handlers of try blocks that were returned out of
+        self.handlers
+            .retain(|handler| handler.frame_depth <= self.frames.len());
+        self.push(result);
+    }
+
+    /// Returns `result` from the running function, unless it is inside a try statement with a
+    /// finally block. That block runs first with `Some(result)` as its pending value, and
+    /// EndFinally returns it once the block completes. Returns whether the function returned.
+    fn return_through_finally(&mut self, result: Value) -> bool {
+        while let Some(handler) = self
+            .handlers
+            .last()
+            .filter(|handler| handler.frame_depth == self.frames.len())
+            .copied()
+        {
+            self.handlers.pop();
+            if handler.finally {
+                self.stack.truncate(handler.stack_len);
+                self.push(Value::Obj(Object::Option(Some(Box::new(result)))));
+                self.pc = handler.target;
+                return false;
+            }
+        }
+        self.return_from_function(result);
+        true
+    }
+
+    fn execute(&mut self, depth: usize) -> anyhow::Result<()> {
         while self.pc < self.chunk.instructions_len() {
             let op = self.next_op();
             match op.ty() {
                 OpcodeType::Return => {
-                    return Ok(());
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    let result = self.pop()?;
+                    // back in the native code that called the function
+                    if self.return_through_finally(result) && self.frames.len() < depth {
+                        return Ok(());
+                    }
+                }
+                OpcodeType::DefaultArg => {
+                    let slot = self.next_op().0;
+                    let skip = self.next_op().0;
+                    let frame = self
+                        .frames
+                        .last()
+                        .expect("Default argument outside of a function call.");
+                    // slot 0 is the callee, parameters start at 1
+                    if frame.provided[slot - 1] {
+                        self.pc = skip;
+                    }
+                }
+                OpcodeType::Guard => {
+                    if self.pop()?.is_falsey() {
+                        // retry the call with the clauses after this one
+                        let callee_index = self.base;
+                        let frame = self.leave_frame();
+                        self.stack.truncate(callee_index + 1);
+                        self.enter_clause(
+                            frame.function,
+                            callee_index,
+                            frame.args,
+                            frame.keywords,
+                            frame.clause + 1,
+                        )?;
+                        if let Some(entered) = self.frames.last_mut() {
+                            entered.generator = frame.generator;
+                        }
+                    }
+                }
+                OpcodeType::Yield => {
+                    let value = self.pop()?;
+                    let generator = self
+                        .frames
+                        .last()
+                        .and_then(|frame| frame.generator.clone())
+                        .expect("Yield outside of a generator.");
+                    let frame = self.suspend();
+                    generator.suspend(GeneratorState::Frame(frame));
+                    self.push(value);
+                    // back in resume_frame, which runs the generator's frame at its own depth
+                    if self.frames.len() < depth {
+                        return Ok(());
+                    }
                 }
                 OpcodeType::Constant => {
                     let cindex = self.next_op();
@@ -107,8 +580,13 @@ impl VM {
                     }
                 }
                 OpcodeType::Add => {
+                    if let Some(method) = self.operator_method(Operator::Add, 1) {
+                        self.call_method(method, 2)?;
+                        continue;
+                    }
                     let b = self.pop()?;
                     let a = self.pop()?;
+                    let (a, b) = concat_operands(self, a, b)?;
                     match a {
                         Value::Number(ln) => {
                             if let Value::Number(rn) = b {
@@ -117,15 +595,13 @@ impl VM {
                                 bail!("Addition operands must be 2 numbers or 2 strings.");
                             }
                         }
-                        Value::Obj(lobj) => match lobj {
-                            Object::String(lstr) => {
-                                if let Value::Obj(Object::String(rstr)) = b {
-                                    self.push(Value::Obj(Object::String(lstr + &rstr)))
-                                } else {
-                                    bail!("Addition operands must be 2 numbers or 2 strings.");
-                                }
+                        Value::Obj(Object::String(lstr)) => {
+                            if let Value::Obj(Object::String(rstr)) = b {
+                                self.push(Value::Obj(Object::String(lstr + &rstr)))
+                            } else {
+                                bail!("Addition operands must be 2 numbers or 2 strings.");
                             }
-                        },
+                        }
                         _ => {
                             bail!("Addition operands must be 2 numbers or 2 strings.");
                         }
@@ -140,6 +616,307 @@ impl VM {
                 OpcodeType::Div => {
                     binary_op!(self, /, Value::Number);
                 }
+                OpcodeType::Modulo => {
+                    binary_op!(self, %, Value::Number);
+                }
+                OpcodeType::Power => {
+                    let b = self.pop()?;
+                    let a = self.pop()?;
+                    match (&a, &b) {
+                        (Value::Number(a), Value::Number(b)) => {
+                            self.push(Value::Number(a.powf(*b)))
+                        }
+                        _ => bail!(
+                            "Operands of '**' must be 2 numbers, got: {} and {}.",
+                            a.type_string(),
+                            b.type_string()
+                        ),
+                    }
+                }
+                OpcodeType::IntDiv => {
+                    // truncates toward zero, same as Dart's ~/
+                    integer_op!(self, "~/", |a: i64, b: i64| match a.checked_div(b) {
+                        Some(n) => Ok(n),
+                        None if b == 0 => bail!("Integer division by zero."),
+                        None => bail!("Integer division overflow: {} ~/ {}", a, b),
+                    });
+                }
+                OpcodeType::BitAnd => {
+                    integer_op!(self, "&", |a: i64, b: i64| anyhow::Ok(a & b));
+                }
+                OpcodeType::BitOr => {
+                    integer_op!(self, "|", |a: i64, b: i64| anyhow::Ok(a | b));
+                }
+                OpcodeType::BitXor => {
+                    integer_op!(self, "^", |a: i64, b: i64| anyhow::Ok(a ^ b));
+                }
+                OpcodeType::ShiftLeft => {
+                    integer_op!(self, "<<", |a: i64, b: i64| shift(a, b, i64::checked_shl));
+                }
+                OpcodeType::ShiftRight => {
+                    integer_op!(self, ">>", |a: i64, b: i64| shift(a, b, i64::checked_shr));
+                }
+                OpcodeType::BitNot => {
+                    let iback = self.stack.len() - 1;
+                    let val = &self.stack[iback];
+                    if let Ok(n) = val.as_integer() {
+                        self.stack[iback] = Value::Number(!n as f64);
+                    } else {
+                        bail!(
+                            "Operand of '~' must be an integer, got: {}.",
+                            val.integer_type_string()
+                        )
+                    }
+                }
+                OpcodeType::Range | OpcodeType::RangeInclusive => {
+                    let end = self.pop()?;
+                    let start = self.pop()?;
+                    let inclusive = matches!(op.ty(), OpcodeType::RangeInclusive);
+                    let range = Range::new(&start, &end, inclusive)?;
+                    self.push(Value::Obj(Object::Range(range)));
+                }
+                OpcodeType::In => {
+                    let haystack = self.pop()?;
+                    let needle = self.pop()?;
+                    self.push(Value::Boolean(haystack.contains(&needle)?));
+                }
+                OpcodeType::Index => {
+                    if let Some(method) = self.operator_method(Operator::Index, 1) {
+                        self.call_method(method, 2)?;
+                        continue;
+                    }
+                    let index = self.pop()?;
+                    let value = self.pop()?;
+                    self.push(value.index(&index)?);
+                }
+                OpcodeType::Jump => {
+                    self.pc = self.next_op().0;
+                }
+                // leave the condition on the stack as the value of `and` / `or`
+                OpcodeType::JumpIfFalse => {
+                    let target = self.next_op().0;
+                    if self.stack_top().is_falsey() {
+                        self.pc = target;
+                    }
+                }
+                OpcodeType::JumpIfTrue => {
+                    let target = self.next_op().0;
+                    if !self.stack_top().is_falsey() {
+                        self.pc = target;
+                    }
+                }
+                // the iterable is replaced by an iterator over it the first time around
+                OpcodeType::ForIter => {
+                    let slot = self.base + self.next_op().0;
+                    let exit = self.next_op().0;
+                    let iter = Iter::from_value(&self.stack[slot])?;
+                    self.stack[slot] = Value::Obj(Object::Iterator(iter.clone()));
+                    match iter.next(self)? {
+                        Some(value) => self.push(value),
+                        None => self.pc = exit,
+                    }
+                }
+                OpcodeType::Some => {
+                    let value = self.pop()?;
+                    self.push(Value::some(value));
+                }
+                OpcodeType::Ok => {
+                    let value = self.pop()?;
+                    self.push(Value::ok(value));
+                }
+                OpcodeType::Err => {
+                    let value = self.pop()?;
+                    self.push(Value::err(value));
+                }
+                OpcodeType::Try => match self.pop()?.try_unwrap()? {
+                    Ok(value) => self.push(value),
+                    // the Err or None itself is returned from the running function
+                    Err(err) if !self.frames.is_empty() => {
+                        if self.return_through_finally(err) && self.frames.len() < depth {
+                            return Ok(());
+                        }
+                    }
+                    // returning early from the top level script ends it with the error
+                    Err(err) => bail!("Script returned early from '?' with: {}", err),
+                },
+                OpcodeType::PushHandler => {
+                    let target = self.next_op().0;
+                    self.handlers.push(Handler {
+                        target,
+                        stack_len: self.stack.len(),
+                        frame_depth: self.frames.len(),
+                        finally: false,
+                    });
+                }
+                OpcodeType::PushFinally => {
+                    let target = self.next_op().0;
+                    self.handlers.push(Handler {
+                        target,
+                        stack_len: self.stack.len(),
+                        frame_depth: self.frames.len(),
+                        finally: true,
+                    });
+                }
+                OpcodeType::PopHandler => {
+                    let _ = self.handlers.pop();
+                }
+                OpcodeType::Throw => {
+                    let value = self.pop()?;
+                    let line = self.chunk.line_at(self.pc - 1);
+                    return Err(Thrown(ScriptError::from_thrown(value, line)).into());
+                }
+                OpcodeType::EndFinally => {
+                    // rethrow the error that was pending when the finally block was entered, or
+                    // finish the return that entered it
+                    match self.pop()? {
+                        Value::Obj(Object::Error(err)) => return Err(Thrown(err).into()),
+                        Value::Obj(Object::Option(Some(result))) => {
+                            let returned = self.return_through_finally(*result);
+                            if returned && self.frames.len() < depth {
+                                return Ok(());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                OpcodeType::GetProperty => {
+                    let name_index = self.next_op();
+                    let name = self.chunk.constant_at(name_index.0).as_string()?;
+                    let value = self.pop()?;
+                    self.push(value.get_property(&name)?);
+                }
+                OpcodeType::SetProperty => {
+                    let name_index = self.next_op();
+                    let name = self.chunk.constant_at(name_index.0).as_string()?;
+                    let value = self.pop()?;
+                    let object = self.pop()?;
+                    object.set_property(&name, value.clone())?;
+                    self.push(value);
+                }
+                OpcodeType::SetIndex => {
+                    let value = self.pop()?;
+                    let index = self.pop()?;
+                    let object = self.pop()?;
+                    object.set_index(&index, value.clone())?;
+                    self.push(value);
+                }
+                OpcodeType::Dup => {
+                    let value = self.stack_top().clone();
+                    self.push(value);
+                }
+                OpcodeType::Dup2 => {
+                    let top = self.stack.len() - 2;
+                    self.stack.extend_from_within(top..);
+                }
+                OpcodeType::GetPath => {
+                    let name_index = self.next_op();
+                    let name = self.chunk.constant_at(name_index.0).as_string()?;
+                    let value = self.pop()?;
+                    self.push(value.get_path(&name)?);
+                }
+                OpcodeType::GetSuper => {
+                    let name_index = self.next_op();
+                    let name = self.chunk.constant_at(name_index.0).as_string()?;
+                    let receiver = self.pop()?;
+                    let owner = self.pop()?;
+                    self.push(BoundMethod::find_super(&owner, receiver, &name)?);
+                }
+                OpcodeType::Inherit => {
+                    let Value::Obj(Object::Struct(ty)) = self.pop()? else {
+                        bail!("Expected struct to inherit into");
+                    };
+                    let parent = match self.pop()? {
+                        Value::Obj(Object::Struct(parent)) => parent,
+                        parent => bail!(
+                            "Struct {} can only extend a struct, got: {}",
+                            ty.name,
+                            parent.type_string()
+                        ),
+                    };
+                    let ty = ty.inherit(parent).map_err(|e| anyhow!(e))?;
+                    self.push(Value::Obj(Object::Struct(Arc::new(ty))));
+                }
+                OpcodeType::Impl => {
+                    let count = self.next_op().0;
+                    let pairs = self.stack.split_off(self.stack.len() - 2 * count);
+                    let mut methods = HashMap::new();
+                    for pair in pairs.chunks(2) {
+                        let Value::Obj(Object::Function(method)) = &pair[1] else {
+                            bail!("Expected method function, got: {}", pair[1]);
+                        };
+                        methods.insert(pair[0].as_string()?, method.clone());
+                    }
+                    let ty = self.pop()?;
+                    let tr = self.pop()?;
+                    implement(&tr, &ty, methods).map_err(|e| anyhow!(e))?;
+                }
+                OpcodeType::Call => {
+                    let argc = self.next_op().0;
+                    self.call_value(self.stack.len() - 1 - argc, Vec::new())?;
+                }
+                OpcodeType::CallKeywords => {
+                    let argc = self.next_op().0;
+                    let keywords_index = self.next_op().0;
+                    let keywords = match self.chunk.constant_at(keywords_index) {
+                        Value::Obj(Object::List(names)) => names
+                            .values()
+                            .iter()
+                            .map(Value::as_string)
+                            .collect::<anyhow::Result<Vec<String>>>()?,
+                        constant => bail!("Expected keyword names, got: {}", constant),
+                    };
+                    self.call_value(self.stack.len() - 1 - argc, keywords)?;
+                }
+                OpcodeType::List => {
+                    let len = self.next_op().0;
+                    let values = self.stack.split_off(self.stack.len() - len);
+                    self.push(Value::list(values));
+                }
+                OpcodeType::Append => {
+                    let slot = self.base + self.next_op().0;
+                    let value = self.pop()?;
+                    let Value::Obj(Object::List(list)) = &self.stack[slot] else {
+                        bail!("Append expected a list in slot {}", slot - self.base);
+                    };
+                    list.values_mut().push(value);
+                }
+                OpcodeType::Map => {
+                    let len = self.next_op().0;
+                    let mut values = self.stack.split_off(self.stack.len() - len * 2).into_iter();
+                    let entries = std::iter::from_fn(|| Some((values.next()?, values.next()?)));
+                    self.push(Value::map(entries.collect())?);
+                }
+                OpcodeType::Insert => {
+                    let slot = self.base + self.next_op().0;
+                    let value = self.pop()?;
+                    let key = self.pop()?;
+                    let Value::Obj(Object::Map(map)) = &self.stack[slot] else {
+                        bail!("Insert expected a map in slot {}", slot - self.base);
+                    };
+                    map.insert(key, value)?;
+                }
+                OpcodeType::Coalesce => {
+                    let end = self.next_op().0;
+                    // leaves the unwrapped value and skips the right hand side, or discards the
+                    // missing value so the right hand side takes its place
+                    if let Some(value) = self.pop()?.coalesce() {
+                        self.push(value);
+                        self.pc = end;
+                    }
+                }
+                OpcodeType::SafeNav => {
+                    let end = self.next_op().0;
+                    // leaves a missing value as the result and skips the access, or accesses the
+                    // value inside Some
+                    let object = self.pop()?;
+                    match object.coalesce() {
+                        Some(value) => self.push(value),
+                        None => {
+                            self.push(object);
+                            self.pc = end;
+                        }
+                    }
+                }
                 OpcodeType::Nil => {
                     self.push(Value::Nil);
                 }
@@ -151,19 +928,38 @@ impl VM {
                     self.stack[iback] = Value::Boolean(val.is_falsey());
                 }
                 OpcodeType::Equal => {
+                    if let Some(method) = self.operator_method(Operator::Eq, 1) {
+                        let result = self.call_operator(Operator::Eq, method, 2)?;
+                        self.push(result);
+                        continue;
+                    }
                     let b = self.pop()?;
                     let a = self.pop()?;
                     self.push(Value::Boolean(a == b));
                 }
+                // a > b is b.lt(a)
                 OpcodeType::GreaterThan => {
+                    if let Some(method) = self.operator_method(Operator::Ord, 0) {
+                        let len = self.stack.len();
+                        self.stack.swap(len - 1, len - 2);
+                        let result = self.call_operator(Operator::Ord, method, 2)?;
+                        self.push(result);
+                        continue;
+                    }
                     binary_op!(self, >, Value::Boolean);
                 }
                 OpcodeType::LessThan => {
+                    if let Some(method) = self.operator_method(Operator::Ord, 1) {
+                        let result = self.call_operator(Operator::Ord, method, 2)?;
+                        self.push(result);
+                        continue;
+                    }
                     binary_op!(self, <, Value::Boolean);
                 }
                 OpcodeType::Print => {
                     let val = self.pop()?;
-                    println!("{}", val);
+                    let val = displayed(self, &val)?;
+                    self.output.println(&val)?;
                 }
                 OpcodeType::Pop => {
                     let _ = self.pop()?;
@@ -203,11 +999,11 @@ impl VM {
                     }
                 }
                 OpcodeType::GetLocal => {
-                    let slot = self.next_op().0;
+                    let slot = self.base + self.next_op().0;
                     self.push(self.stack[slot].clone());
                 }
                 OpcodeType::SetLocal => {
-                    let slot = self.next_op().0;
+                    let slot = self.base + self.next_op().0;
                     self.stack[slot] = self.stack_top().clone();
                 }
                 OpcodeType::Unknown => {
@@ -219,6 +1015,44 @@ impl VM {
     }
 }
 
+/// Runs a script function to completion for native code, the function's frame is run by a nested
+/// `run_frames` that returns once it does.
+/// Generators are resumed the same way, until their next yield.
+impl Caller for VM {
+    fn call(&mut self, callee: &Value, args: &[Value]) -> anyhow::Result<Value> {
+        if self.nested_runs >= Self::NESTED_RUNS_MAX {
+            bail!("Stack overflow calling {} from a native", callee);
+        }
+        let callee_index = self.stack.len();
+        self.push(callee.clone());
+        self.stack.extend_from_slice(args);
+        let depth = self.frames.len();
+        self.call_value(callee_index, vec![])?;
+        if self.frames.len() > depth {
+            self.run_nested(depth + 1)?;
+        }
+        self.pop()
+    }
+
+    fn resume(&mut self, generator: &Arc<Generator>) -> anyhow::Result<Option<Value>> {
+        match generator.take()? {
+            Some(GeneratorState::Frame(frame)) => self.resume_frame(generator, frame),
+            Some(_) => {
+                generator.finish();
+                bail!("Generator '{}' was not created by the VM", generator.name)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+fn shift(a: i64, b: i64, op: fn(i64, u32) -> Option<i64>) -> anyhow::Result<i64> {
+    match u32::try_from(b).ok().and_then(|b| op(a, b)) {
+        Some(n) => Ok(n),
+        None => bail!("Shift amount must be between 0 and 63, got: {}", b),
+    }
+}
+
 #[derive(Debug, Copy, Clone, Default)]
 pub struct Opcode(pub usize);
 
@@ -246,6 +1080,52 @@ impl Opcode {
             Self(18) => OpcodeType::SetGlobal,
             Self(19) => OpcodeType::GetLocal,
             Self(20) => OpcodeType::SetLocal,
+            Self(21) => OpcodeType::Modulo,
+            Self(22) => OpcodeType::Power,
+            Self(23) => OpcodeType::IntDiv,
+            Self(24) => OpcodeType::BitAnd,
+            Self(25) => OpcodeType::BitOr,
+            Self(26) => OpcodeType::BitXor,
+            Self(27) => OpcodeType::BitNot,
+            Self(28) => OpcodeType::ShiftLeft,
+            Self(29) => OpcodeType::ShiftRight,
+            Self(30) => OpcodeType::Range,
+            Self(31) => OpcodeType::RangeInclusive,
+            Self(32) => OpcodeType::In,
+            Self(33) => OpcodeType::Index,
+            Self(34) => OpcodeType::Jump,
+            Self(35) => OpcodeType::ForIter,
+            Self(36) => OpcodeType::Some,
+            Self(37) => OpcodeType::Coalesce,
+            Self(38) => OpcodeType::Ok,
+            Self(39) => OpcodeType::Err,
+            Self(40) => OpcodeType::Try,
+            Self(41) => OpcodeType::Call,
+            Self(42) => OpcodeType::PushHandler,
+            Self(43) => OpcodeType::PopHandler,
+            Self(44) => OpcodeType::Throw,
+            Self(45) => OpcodeType::EndFinally,
+            Self(46) => OpcodeType::GetProperty,
+            Self(47) => OpcodeType::GetPath,
+            Self(48) => OpcodeType::Guard,
+            Self(49) => OpcodeType::JumpIfFalse,
+            Self(50) => OpcodeType::JumpIfTrue,
+            Self(51) => OpcodeType::DefaultArg,
+            Self(52) => OpcodeType::CallKeywords,
+            Self(53) => OpcodeType::List,
+            Self(54) => OpcodeType::GetSuper,
+            Self(55) => OpcodeType::Inherit,
+            Self(56) => OpcodeType::SetProperty,
+            Self(57) => OpcodeType::Impl,
+            Self(58) => OpcodeType::Yield,
+            Self(59) => OpcodeType::Append,
+            Self(60) => OpcodeType::SetIndex,
+            Self(61) => OpcodeType::Dup,
+            Self(62) => OpcodeType::Dup2,
+            Self(63) => OpcodeType::SafeNav,
+            Self(64) => OpcodeType::PushFinally,
+            Self(65) => OpcodeType::Map,
+            Self(66) => OpcodeType::Insert,
             _ => OpcodeType::Unknown,
         }
     }
@@ -292,5 +1172,51 @@ pub enum OpcodeType {
     SetGlobal,
     GetLocal,
     SetLocal,
+    Modulo,
+    Power,
+    IntDiv,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    Range,
+    RangeInclusive,
+    In,
+    Index,
+    Jump,
+    ForIter,
+    Some,
+    Coalesce,
+    Ok,
+    Err,
+    Try,
+    Call,
+    PushHandler,
+    PopHandler,
+    Throw,
+    EndFinally,
+    GetProperty,
+    GetPath,
+    Guard,
+    JumpIfFalse,
+    JumpIfTrue,
+    DefaultArg,
+    CallKeywords,
+    List,
+    GetSuper,
+    Inherit,
+    SetProperty,
+    Impl,
+    Yield,
+    Append,
+    SetIndex,
+    Dup,
+    Dup2,
+    SafeNav,
+    PushFinally,
+    Map,
+    Insert,
     Unknown,
 }
@@ -0,0 +1,421 @@
+//! Runs scripts on both the VM and the tree-walking Interpreter and checks what they print.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use cvm::{
+    compiler::Compiler, interp::Interpreter, lex::Lexer, parse::Parser, value::Output, vm::VM,
+};
+
+/// Output the script printed, followed by `error` if it failed.
+fn run_vm(source: &str) -> String {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let mut vm = VM::new();
+    vm.set_output(Output::new(buffer.clone()));
+    let result = vm.interpret_source(source);
+    printed(&buffer, result)
+}
+
+fn run_interpreter(source: &str) -> String {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Output::new(buffer.clone()));
+    let lexed = Lexer::scan_tokens(source.trim());
+    assert!(lexed.errors.is_empty(), "{:?}", lexed.errors);
+    let result = Parser::parse(&lexed.tokens)
+        .and_then(|stmts| stmts.iter().try_for_each(|stmt| interpreter.execute(stmt)));
+    printed(&buffer, result)
+}
+
+fn printed(buffer: &Mutex<Vec<u8>>, result: anyhow::Result<()>) -> String {
+    let mut output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+    if result.is_err() {
+        output.push_str("error\n");
+    }
+    output
+}
+
+/// Writes `files` to a fresh directory named `name` under the temp dir, returning its path.
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("corrosion-{}", name));
+    let _ = fs::remove_dir_all(&root);
+    for (path, source) in files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+    root
+}
+
+/// Error of compiling and of parsing `script`, both backends have to fail.
+fn load_errors(script: &Path) -> (String, String) {
+    let compiled = Compiler::compile_file(script, &[]).expect_err("compiling should fail");
+    let parsed = Parser::parse_file(script, &[]).expect_err("parsing should fail");
+    (format!("{:#}", compiled), format!("{:#}", parsed))
+}
+
+fn assert_prints(source: &str, expected: &str) {
+    assert_eq!(run_vm(source), expected, "VM output of:\n{}", source);
+    assert_eq!(
+        run_interpreter(source),
+        expected,
+        "Interpreter output of:\n{}",
+        source
+    );
+}
+
+#[test]
+fn catch_binds_the_error() {
+    assert_prints(
+        r#"
+        try { throw "boom"; print "not reached"; } catch (e) { print e.message; }
+        fn fails() { throw "inner"; }
+        try { fails(); } catch (e) { print "caught " + e.message; }
+        print "after";
+        "#,
+        "boom\ncaught inner\nafter\n",
+    );
+}
+
+#[test]
+fn finally_runs_on_every_path() {
+    assert_prints(
+        r#"
+        try { print "body"; } finally { print "fin 1"; }
+        try { throw "x"; } catch (e) { print "catch"; } finally { print "fin 2"; }
+        try {
+            try { throw "y"; } finally { print "fin 3"; }
+        } catch (e) { print "outer " + e.message; }
+        try {
+            try { throw "z"; } catch (e) { throw "from catch"; } finally { print "fin 4"; }
+        } catch (e) { print "outer " + e.message; }
+        "#,
+        "body\nfin 1\ncatch\nfin 2\nfin 3\nouter y\nfin 4\nouter from catch\n",
+    );
+}
+
+#[test]
+fn return_runs_finally() {
+    assert_prints(
+        r#"
+        fn f() { try { return 1; } finally { print "fin"; } }
+        print f();
+        for i in 0..2 { print f(); }
+        fn from_catch() { try { throw "x"; } catch (e) { return e.message; } finally { print "c fin"; } }
+        print from_catch();
+        fn nested() {
+            try {
+                try { return "inner"; } finally { print "first"; }
+            } finally { print "second"; }
+        }
+        print nested();
+        fn in_loop() {
+            for i in 0..3 {
+                try { if i == 1 { return i; } print i; } finally { print "loop fin"; }
+            }
+        }
+        print in_loop();
+        "#,
+        "fin\n1\nfin\n1\nfin\n1\nc fin\nx\nfirst\nsecond\ninner\n0\nloop fin\nloop fin\n1\n",
+    );
+}
+
+#[test]
+fn finally_can_replace_a_return() {
+    assert_prints(
+        r#"
+        fn replaced() { try { return 1; } finally { return 2; } }
+        print replaced();
+        fn raises() { try { return 1; } finally { throw "late"; } }
+        try { raises(); } catch (e) { print e.message; }
+        fn recovers() {
+            try { try { return 1; } finally { throw "y"; } } catch (e) { print "caught"; }
+            return 3;
+        }
+        print recovers();
+        "#,
+        "2\nlate\ncaught\n3\n",
+    );
+}
+
+#[test]
+fn question_mark_runs_finally() {
+    assert_prints(
+        r#"
+        fn g(x) { try { let v = x?; return v; } finally { print "g fin"; } }
+        print g(Some(2));
+        print g(None);
+        "#,
+        "g fin\n2\ng fin\nNone\n",
+    );
+}
+
+#[test]
+fn generators_suspend_and_resume() {
+    assert_prints(
+        r#"
+        fn* count(n) { for i in 0..n { yield i; } }
+        for v in count(3) { print v; }
+        let c = count(2);
+        print next(c);
+        print next(c);
+        print next(c);
+        print next(c);
+        fn* inner() { yield "a"; yield "b"; }
+        fn* outer() { for x in inner() { yield x + "!"; } }
+        print collect(outer());
+        fn* rec(n) { if n > 0 { yield n; for x in rec(n - 1) { yield x; } } }
+        print collect(rec(3));
+        "#,
+        "0\n1\n2\nSome(0)\nSome(1)\nNone\nNone\n[a!, b!]\n[3, 2, 1]\n",
+    );
+}
+
+#[test]
+fn try_spans_yield() {
+    assert_prints(
+        r#"
+        fn* g() {
+            try { yield 1; throw "in gen"; }
+            catch (e) { yield "caught " + e.message; }
+            finally { print "gen finally"; }
+            yield 3;
+        }
+        for v in g() { print v; }
+        fn* bad() { yield 1; throw "oops"; }
+        let b = bad();
+        print next(b);
+        try { next(b); } catch (e) { print "outer " + e.message; }
+        print next(b);
+        fn* guarded() { try { yield 1; yield 2; } finally { print "guarded fin"; } }
+        let gd = guarded();
+        print next(gd);
+        try { throw "between"; } catch (e) { print e.message; }
+        print next(gd);
+        print next(gd);
+        "#,
+        "1\ncaught in gen\ngen finally\n3\nSome(1)\nouter oops\nNone\nSome(1)\nbetween\nSome(2)\nguarded fin\nNone\n",
+    );
+}
+
+#[test]
+fn struct_variants_take_named_fields() {
+    assert_prints(
+        r#"
+        enum Shape { Circle(r), Rect { w, h }, Empty }
+        let a = Shape::Rect(1, 2);
+        let b = Shape::Rect(h: 2, w: 1);
+        print a == b;
+        print b.w;
+        print b.h;
+        try { Shape::Rect(1, d: 2); } catch (e) { print "unexpected keyword"; }
+        try { Shape::Circle(r: 1); } catch (e) { print "tuple variant"; }
+        "#,
+        "true\n1\n2\nunexpected keyword\ntuple variant\n",
+    );
+}
+
+#[test]
+fn unbounded_recursion_is_an_error() {
+    assert_prints(
+        r#"
+        fn deep(n) { return deep(n + 1); }
+        try { deep(0); } catch (e) { print "caught"; }
+        fn nested(n) {
+            let xs = [1, 2];
+            for i in xs { if i == 2 { return nested(n + 1) + 1; } }
+        }
+        try { nested(0); } catch (e) { print "caught"; }
+        print "after";
+        "#,
+        "caught\ncaught\nafter\n",
+    );
+}
+
+#[test]
+fn len_of_a_range_does_not_iterate_it() {
+    assert_prints(
+        r#"
+        print len(0..100000000000);
+        print len(2..=5);
+        print len(3..3);
+        print len(3..=3);
+        "#,
+        "100000000000\n4\n0\n1\n",
+    );
+}
+
+#[test]
+fn display_applies_wherever_a_value_is_shown() {
+    assert_prints(
+        r#"
+        struct V { n }
+        impl Display for V { fn to_string(self) { return "V" + self.n; } }
+        struct Holder { inner }
+        let a = V("1");
+        print a;
+        print [a, V("2")];
+        print Some(a);
+        print Holder(a);
+        print "v=" + a;
+        print a + "!";
+        "#,
+        "V1\n[V1, V2]\nSome(V1)\nHolder { inner: V1 }\nv=V1\nV1!\n",
+    );
+}
+
+#[test]
+fn comparisons_must_return_booleans() {
+    assert_prints(
+        r#"
+        struct E { n }
+        impl Eq for E { fn eq(self, other) { return 1; } }
+        try { print E(1) == E(2); } catch (e) { print "eq"; }
+        struct O { n }
+        impl Ord for O { fn lt(self, other) { return self.n < other.n; } }
+        print O(1) < O(2);
+        print O(1) >= O(2);
+        "#,
+        "eq\ntrue\nfalse\n",
+    );
+}
+
+#[test]
+fn nested_callbacks_and_resumes_are_limited() {
+    assert_prints(
+        r#"
+        fn down(n) { return collect(map([n + 1], down)); }
+        try { down(0); } catch (e) { print "callbacks"; }
+        fn* g(n) { yield next(g(n + 1)); }
+        try { next(g(0)); } catch (e) { print "resumes"; }
+        print "after";
+        "#,
+        "callbacks\nresumes\nafter\n",
+    );
+}
+
+#[test]
+fn maps_and_map_comprehensions() {
+    assert_prints(
+        r#"
+        let m = %{"a" => 1, "b" => 2};
+        m["c"] = 3;
+        m["a"] += 10;
+        print m;
+        print len(m);
+        print "b" in m;
+        for k in m { print k; }
+        print %{1 => 2, 3 => 4} == %{3 => 4, 1 => 2};
+        let xs = [1, -2, 3];
+        print %{x => x * 2 for x in xs if x > 0};
+        print %{i => j for i in 0..2 for j in 0..2};
+        try { m["missing"]; } catch (e) { print "no key"; }
+        try { %{[1] => 2}; } catch (e) { print "bad key"; }
+        "#,
+        "%{a => 11, b => 2, c => 3}\n3\ntrue\na\nb\nc\ntrue\n%{1 => 2, 3 => 6}\n%{0 => 1, 1 => 1}\nno key\nbad key\n",
+    );
+}
+
+#[test]
+fn use_list_rejects_duplicate_names() {
+    let root = project(
+        "duplicate-use",
+        &[
+            ("math.cor", "pub fn add(a, b) { a + b }"),
+            ("twice.cor", "mod math;\nuse math::{add, add};"),
+            (
+                "alias.cor",
+                "mod math;\nuse math::{add as plus, add as plus};",
+            ),
+        ],
+    );
+    for script in ["twice.cor", "alias.cor"] {
+        let (compiled, parsed) = load_errors(&root.join(script));
+        assert!(compiled.contains("failed with 1 error"), "{}", compiled);
+        assert!(parsed.contains("listed twice"), "{}", parsed);
+    }
+}
+
+#[test]
+fn unreadable_module_reports_its_path() {
+    let root = project("unreadable-module", &[("main.cor", "mod other;")]);
+    let other = root.join("other.cor");
+    fs::create_dir(&other).unwrap();
+    let (_, parsed) = load_errors(&root.join("main.cor"));
+    let expected = format!("Unable to read module 'other' from {}", other.display());
+    assert!(parsed.contains(&expected), "{}", parsed);
+}
+
+#[test]
+fn macro_arguments_can_be_statements() {
+    assert_prints(
+        "macro twice(body) { body body }\ntwice!(print \"x\";);\ntwice!(let y = 1; print y;);",
+        "x\nx\n1\n1\n",
+    );
+}
+
+#[test]
+fn macro_errors_point_at_the_call() {
+    let lexed = Lexer::scan_tokens("macro m(x) {\n  let y = x;\n  print y + nil;\n}\nm!(1);");
+    let stmts = Parser::parse(&lexed.tokens).unwrap();
+    let mut interpreter = Interpreter::new();
+    let failed = stmts
+        .iter()
+        .try_for_each(|stmt| interpreter.execute(stmt))
+        .unwrap_err()
+        .to_string();
+    assert!(failed.contains("LineNo:5 Plus"), "{}", failed);
+    let root = project(
+        "macro-parse-error",
+        &[("main.cor", "macro m(x) {\n  print x x;\n}\nm!(1);")],
+    );
+    let (compiled, parsed) = load_errors(&root.join("main.cor"));
+    assert!(compiled.contains("failed with"), "{}", compiled);
+    let origin = "in the expansion of macro 'm' called on line 4, defined on line 1";
+    assert!(parsed.contains(origin), "{}", parsed);
+}
+
+#[test]
+fn required_trait_methods_take_attributes() {
+    assert_prints(
+        r#"
+        trait Show {
+            @doc "Text of the value."
+            @spec show(any()) -> string()
+            fn show(self);
+        }
+        struct P { n }
+        impl Show for P { fn show(self) { return "p"; } }
+        print P(1).show();
+        "#,
+        "p\n",
+    );
+    let root = project(
+        "required-method-spec",
+        &[(
+            "main.cor",
+            "trait Show {\n    @spec other(any()) -> string()\n    fn show(self);\n}",
+        )],
+    );
+    let (compiled, parsed) = load_errors(&root.join("main.cor"));
+    assert!(compiled.contains("failed with 1 error"), "{}", compiled);
+    assert!(
+        parsed.contains("is for 'other' but precedes fn show"),
+        "{}",
+        parsed
+    );
+}
+
+#[test]
+fn truncated_input_is_an_error() {
+    for source in ["fn f() {", "struct S {", "try {", "fn f(a", "let x = 1"] {
+        let lexed = Lexer::scan_tokens(source);
+        assert!(lexed.errors.is_empty(), "{}: {:?}", source, lexed.errors);
+        assert!(Parser::parse(&lexed.tokens).is_err(), "{}", source);
+        assert_eq!(run_vm(source), "error\n", "{}", source);
+    }
+    assert!(Lexer::scan_tokens("print 1;  \n").errors.is_empty());
+}